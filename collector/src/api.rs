@@ -0,0 +1,453 @@
+use crate::base_integration::{new_import_id, AuthStatus, IntegrationError, Observation};
+use crate::db::Db;
+use crate::imports::{self, ImportError, ImportRow, ImportSummary};
+use crate::integration_registry::{self, IntegrationRegistry};
+use crate::oauth::PendingAuthorizations;
+use crate::raw_data;
+use crate::timezones::{self, DayClock, RecomputeScope, TimezoneChange};
+use crate::uploads::UploadStore;
+use actix_web::{delete, get, http::header, post, web, HttpResponse, Responder};
+use chrono::NaiveDate;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IntegrationQueryParams {
+    pub integration_name: String,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+}
+
+#[derive(Debug, Serialize)]
+struct IntegrationData {
+    #[serde(flatten)]
+    params: IntegrationQueryParams,
+    data: Vec<Observation>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OAuthCallbackParams {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct IntegrationStatus {
+    integration: String,
+    #[serde(flatten)]
+    status: AuthStatus,
+}
+
+#[derive(Debug, Serialize)]
+struct ImportResult {
+    #[serde(flatten)]
+    params: IntegrationQueryParams,
+    importid: Option<String>,
+    rows: u64,
+}
+
+/// Runs the integration without storing anything.
+#[get("/api/v1/getData")]
+pub async fn get_data(
+    registry: web::Data<IntegrationRegistry>,
+    params: web::Query<IntegrationQueryParams>,
+) -> impl Responder {
+    let params = params.into_inner();
+    match run_integration(&registry, &params).await {
+        Ok(data) => HttpResponse::Ok().json(IntegrationData { params, data }),
+        Err(response) => response,
+    }
+}
+
+/// Runs the integration and upserts its observations into `raw_data`.
+#[post("/api/v1/import")]
+pub async fn import_data(
+    registry: web::Data<IntegrationRegistry>,
+    db: web::Data<Db>,
+    params: web::Query<IntegrationQueryParams>,
+) -> impl Responder {
+    let params = params.into_inner();
+    let observations = match run_integration(&registry, &params).await {
+        Ok(observations) => observations,
+        Err(response) => return response,
+    };
+
+    match raw_data::upsert_observations(&db, &observations).await {
+        Ok(rows) => HttpResponse::Ok().json(ImportResult {
+            params,
+            importid: observations.first().map(|o| o.importid.clone()),
+            rows,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(json!({ "errorMessage": e.to_string() })),
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct UploadResult {
+    integration: String,
+    importid: String,
+    rows: u64,
+}
+
+/// Stores an export uploaded as the request body, then imports it into
+/// `raw_data` under a fresh importid.
+#[post("/api/v1/upload/{integration_name}")]
+pub async fn upload_data(
+    registry: web::Data<IntegrationRegistry>,
+    db: web::Data<Db>,
+    uploads: web::Data<UploadStore>,
+    integration_name: web::Path<String>,
+    mut body: web::Payload,
+) -> impl Responder {
+    let integration = match registry.get(&integration_name) {
+        Some(integration) => integration,
+        None => return unknown_integration_response(&integration_name),
+    };
+
+    let importid = new_import_id();
+    let mut writer = match uploads.create(&integration.name(), &importid) {
+        Ok(writer) => writer,
+        Err(e) => return HttpResponse::InternalServerError().json(json!({ "errorMessage": e.to_string() })),
+    };
+    while let Some(chunk) = body.next().await {
+        let written = match chunk {
+            Ok(chunk) => writer.write(&chunk).map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        if let Err(e) = written {
+            writer.abort();
+            return HttpResponse::BadRequest().json(json!({ "errorMessage": format!("Upload failed - {}", e) }));
+        }
+    }
+    let path = match writer.finish() {
+        Ok(path) => path,
+        Err(e) => return HttpResponse::InternalServerError().json(json!({ "errorMessage": e.to_string() })),
+    };
+
+    let name = integration.name();
+    let block_importid = importid.clone();
+    let observations = match web::block(move || integration.import_upload(&path, &block_importid)).await {
+        Ok(Ok(observations)) => observations,
+        Ok(Err(e)) => return error_response(e),
+        Err(e) => return error_response(IntegrationError::Task(e.to_string())),
+    };
+
+    // Integrations may tag rows with an importid of their own, e.g. one
+    // derived from the file's contents.
+    let importid = observations.first().map(|o| o.importid.clone()).unwrap_or(importid);
+    match raw_data::upsert_observations(&db, &observations).await {
+        Ok(rows) => HttpResponse::Ok().json(UploadResult { integration: name, importid, rows }),
+        Err(e) => HttpResponse::InternalServerError().json(json!({ "errorMessage": e.to_string() })),
+    }
+}
+
+/// Lists the registered integrations with their credential status.
+#[get("/api/v1/integrations")]
+pub async fn list_integrations(registry: web::Data<IntegrationRegistry>) -> impl Responder {
+    let integrations = registry.all();
+    let statuses = web::block(move || {
+        integrations.iter()
+            .map(|i| IntegrationStatus { integration: i.name(), status: i.authorize() })
+            .collect::<Vec<_>>()
+    })
+    .await;
+    match statuses {
+        Ok(statuses) => HttpResponse::Ok().json(statuses),
+        Err(e) => error_response(IntegrationError::Task(e.to_string())),
+    }
+}
+
+/// Redirects to the provider's consent page.
+#[get("/api/v1/oauth/{integration_name}/start")]
+pub async fn oauth_start(
+    registry: web::Data<IntegrationRegistry>,
+    pending: web::Data<PendingAuthorizations>,
+    integration_name: web::Path<String>,
+) -> impl Responder {
+    let integration = match registry.get(&integration_name) {
+        Some(integration) => integration,
+        None => return unknown_integration_response(&integration_name),
+    };
+    match integration.oauth() {
+        Some(oauth) => {
+            let state = pending.begin(&integration_name);
+            match oauth.authorize_url(&state) {
+                Ok(url) => HttpResponse::Found().insert_header((header::LOCATION, url)).finish(),
+                Err(e) => error_response(e),
+            }
+        }
+        None => no_oauth_response(&integration.name()),
+    }
+}
+
+/// Receives the provider's redirect and exchanges the code for tokens.
+#[get("/api/v1/oauth/{integration_name}/callback")]
+pub async fn oauth_callback(
+    registry: web::Data<IntegrationRegistry>,
+    pending: web::Data<PendingAuthorizations>,
+    integration_name: web::Path<String>,
+    params: web::Query<OAuthCallbackParams>,
+) -> impl Responder {
+    let integration = match registry.get(&integration_name) {
+        Some(integration) => integration,
+        None => return unknown_integration_response(&integration_name),
+    };
+    if integration.oauth().is_none() {
+        return no_oauth_response(&integration.name());
+    }
+
+    let params = params.into_inner();
+    if let Some(error) = params.error {
+        return HttpResponse::BadRequest().json(json!({ "errorMessage": format!("Authorization denied - {}", error) }));
+    }
+    let state_ok = params.state.map(|state| pending.complete(&state, &integration_name)).unwrap_or(false);
+    let code = match params.code {
+        Some(code) if state_ok => code,
+        _ => return HttpResponse::BadRequest().json(json!({ "errorMessage": "Invalid or expired authorization state" })),
+    };
+
+    let result = web::block(move || {
+        let oauth = integration.oauth().expect("checked above");
+        oauth.exchange_code(&code).map(|_| IntegrationStatus { integration: integration.name(), status: oauth.status() })
+    })
+    .await;
+    match result {
+        Ok(Ok(status)) => HttpResponse::Ok().json(status),
+        Ok(Err(e)) => error_response(e),
+        Err(e) => error_response(IntegrationError::Task(e.to_string())),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportListParams {
+    pub source: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportRowsParams {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+struct ImportDetail {
+    #[serde(flatten)]
+    summary: ImportSummary,
+    limit: i64,
+    offset: i64,
+    data: Vec<ImportRow>,
+}
+
+/// Lists past imports with their row counts and date ranges.
+#[get("/api/v1/imports")]
+pub async fn list_imports(db: web::Data<Db>, params: web::Query<ImportListParams>) -> impl Responder {
+    match imports::list_imports(&db, params.source.as_deref()).await {
+        Ok(imports) => HttpResponse::Ok().json(imports),
+        Err(e) => HttpResponse::InternalServerError().json(json!({ "errorMessage": e.to_string() })),
+    }
+}
+
+/// One import with a page of its rows.
+#[get("/api/v1/imports/{importid}")]
+pub async fn get_import(
+    db: web::Data<Db>,
+    importid: web::Path<String>,
+    params: web::Query<ImportRowsParams>,
+) -> impl Responder {
+    let summary = match imports::get_import(&db, &importid).await {
+        Ok(Some(summary)) => summary,
+        Ok(None) => return unknown_import_response(&importid),
+        Err(e) => return HttpResponse::InternalServerError().json(json!({ "errorMessage": e.to_string() })),
+    };
+    let (limit, offset) = imports::page(params.limit, params.offset);
+    match imports::import_rows(&db, &importid, limit, offset).await {
+        Ok(data) => HttpResponse::Ok().json(ImportDetail { summary, limit, offset, data }),
+        Err(e) => HttpResponse::InternalServerError().json(json!({ "errorMessage": e.to_string() })),
+    }
+}
+
+/// Deletes every row of an import; a rollback afterwards still restores the
+/// values it overwrote.
+#[delete("/api/v1/imports/{importid}")]
+pub async fn delete_import(db: web::Data<Db>, importid: web::Path<String>) -> impl Responder {
+    match imports::delete_import(&db, &importid).await {
+        Ok(0) => unknown_import_response(&importid),
+        Ok(deleted) => HttpResponse::Ok().json(json!({ "importid": importid.as_str(), "deleted": deleted })),
+        Err(e) => import_error_response(e),
+    }
+}
+
+/// Deletes an import and restores the values it overwrote. Later imports that
+/// overwrote its rows have to be rolled back first.
+#[post("/api/v1/imports/{importid}/rollback")]
+pub async fn rollback_import(db: web::Data<Db>, importid: web::Path<String>) -> impl Responder {
+    match imports::rollback_import(&db, &importid).await {
+        Ok(result) if result.deleted == 0 && result.restored == 0 => unknown_import_response(&importid),
+        Ok(result) => HttpResponse::Ok().json(json!({
+            "importid": importid.as_str(),
+            "deleted": result.deleted,
+            "restored": result.restored,
+        })),
+        Err(e) => import_error_response(e),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TimezoneChangeParams {
+    /// Milliseconds since the epoch.
+    pub starts_at: i64,
+    /// IANA name such as `Asia/Tokyo`.
+    pub timezone: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RecomputeParams {
+    /// Comma-separated sources to rematch besides the rows without a day;
+    /// defaults to the sources matched by local day.
+    pub sources: Option<String>,
+    pub from: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+struct TimezoneSettings {
+    timezone: String,
+    day_boundary_hour: u32,
+    changes: Vec<TimezoneChange>,
+}
+
+#[derive(Debug, Serialize)]
+struct TimezoneChangeResult {
+    #[serde(flatten)]
+    change: TimezoneChange,
+    recomputed: timezones::RecomputeResult,
+}
+
+/// The default zone, the day boundary and the timezone history.
+#[get("/api/v1/timezones")]
+pub async fn list_timezones(db: web::Data<Db>) -> impl Responder {
+    let clock = match DayClock::load(&db).await {
+        Ok(clock) => clock,
+        Err(e) => return HttpResponse::InternalServerError().json(json!({ "errorMessage": e.to_string() })),
+    };
+    match timezones::list_changes(&db).await {
+        Ok(changes) => HttpResponse::Ok().json(TimezoneSettings {
+            timezone: clock.config().default_zone.name().to_string(),
+            day_boundary_hour: clock.config().day_boundary_hour,
+            changes,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(json!({ "errorMessage": e.to_string() })),
+    }
+}
+
+/// Records a move to another zone and rematches the rows from then on.
+#[post("/api/v1/timezones")]
+pub async fn add_timezone(db: web::Data<Db>, params: web::Json<TimezoneChangeParams>) -> impl Responder {
+    let zone = match params.timezone.parse() {
+        Ok(zone) => zone,
+        Err(_) => {
+            return HttpResponse::BadRequest()
+                .json(json!({ "errorMessage": format!("Unknown timezone '{}'", params.timezone) }))
+        }
+    };
+    let result = async {
+        let change = timezones::save_change(&db, params.starts_at, zone).await?;
+        let recomputed = rematch_from(&db, change.starts_at).await?;
+        Ok::<_, sqlx::Error>(TimezoneChangeResult { change, recomputed })
+    };
+    match result.await {
+        Ok(result) => HttpResponse::Ok().json(result),
+        Err(e) => HttpResponse::InternalServerError().json(json!({ "errorMessage": e.to_string() })),
+    }
+}
+
+/// Removes a timezone change and rematches the rows it covered.
+#[delete("/api/v1/timezones/{id}")]
+pub async fn delete_timezone(db: web::Data<Db>, id: web::Path<i32>) -> impl Responder {
+    let result = async {
+        let change = match timezones::delete_change(&db, *id).await? {
+            Some(change) => change,
+            None => return Ok(None),
+        };
+        let recomputed = rematch_from(&db, change.starts_at).await?;
+        Ok::<_, sqlx::Error>(Some(TimezoneChangeResult { change, recomputed }))
+    };
+    match result.await {
+        Ok(Some(result)) => HttpResponse::Ok().json(result),
+        Ok(None) => HttpResponse::NotFound().json(json!({ "errorMessage": format!("Unknown timezone change {}", id) })),
+        Err(e) => HttpResponse::InternalServerError().json(json!({ "errorMessage": e.to_string() })),
+    }
+}
+
+/// Rematches stored rows to their local day, e.g. after changing
+/// `DAY_BOUNDARY_HOUR`.
+#[post("/api/v1/timezones/recompute")]
+pub async fn recompute_days(db: web::Data<Db>, params: web::Query<RecomputeParams>) -> impl Responder {
+    let mut scope = RecomputeScope { from: params.from, ..RecomputeScope::default() };
+    if let Some(sources) = &params.sources {
+        scope.sources = sources.split(',').map(str::trim).filter(|s| !s.is_empty()).map(str::to_string).collect();
+    }
+    let result = async {
+        let clock = DayClock::load(&db).await?;
+        timezones::recompute(&db, &clock, &scope).await
+    };
+    match result.await {
+        Ok(result) => HttpResponse::Ok().json(result),
+        Err(e) => HttpResponse::InternalServerError().json(json!({ "errorMessage": e.to_string() })),
+    }
+}
+
+async fn rematch_from(db: &Db, starts_at: i64) -> Result<timezones::RecomputeResult, sqlx::Error> {
+    let clock = DayClock::load(db).await?;
+    timezones::recompute(db, &clock, &RecomputeScope { from: Some(starts_at), ..RecomputeScope::default() }).await
+}
+
+fn unknown_import_response(importid: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(json!({ "errorMessage": format!("Unknown import '{}'", importid) }))
+}
+
+fn import_error_response(e: ImportError) -> HttpResponse {
+    match e {
+        ImportError::OverwrittenLater(_) => HttpResponse::Conflict().json(json!({ "errorMessage": e.to_string() })),
+        ImportError::Sqlx(_) => HttpResponse::InternalServerError().json(json!({ "errorMessage": e.to_string() })),
+    }
+}
+
+fn unknown_integration_response(integration_name: &str) -> HttpResponse {
+    HttpResponse::NotFound()
+        .json(json!({ "errorMessage": format!("Unknown integration '{}'", integration_name) }))
+}
+
+fn no_oauth_response(integration_name: &str) -> HttpResponse {
+    HttpResponse::BadRequest()
+        .json(json!({ "errorMessage": format!("{} has no OAuth2 app configured", integration_name) }))
+}
+
+async fn run_integration(
+    registry: &IntegrationRegistry,
+    params: &IntegrationQueryParams,
+) -> Result<Vec<Observation>, HttpResponse> {
+    if params.start_date > params.end_date {
+        return Err(error_response(IntegrationError::InvalidDateRange(params.start_date, params.end_date)));
+    }
+
+    let integration = registry.get(&params.integration_name)
+        .ok_or_else(|| unknown_integration_response(&params.integration_name))?;
+
+    integration_registry::run_blocking(integration, params.start_date, params.end_date)
+        .await
+        .map_err(error_response)
+}
+
+pub fn error_response(error: IntegrationError) -> HttpResponse {
+    let mut response = match error {
+        IntegrationError::InvalidDateRange(_, _)
+        | IntegrationError::UploadNotSupported(_)
+        | IntegrationError::InvalidUpload(_) => HttpResponse::BadRequest(),
+        IntegrationError::Unauthorized(_, _) => HttpResponse::Unauthorized(),
+        IntegrationError::Http(_) | IntegrationError::InvalidResponse(_) => HttpResponse::BadGateway(),
+        IntegrationError::InvalidConfig(_) | IntegrationError::Task(_) => HttpResponse::InternalServerError(),
+    };
+    response.json(json!({ "errorMessage": error.to_string() }))
+}
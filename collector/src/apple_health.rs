@@ -0,0 +1,290 @@
+use crate::base_integration::{AuthStatus, BaseIntegration, IntegrationError, Observation, ObservationValue};
+use crate::uploads::UploadStore;
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Arc;
+
+const SOURCE: &str = "apple_health";
+const DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S %z";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Aggregation {
+    /// Daily total. Totals are summed per recording device and the largest one
+    /// is kept, as the iPhone and the Watch both record steps for the same walk.
+    Sum,
+    Mean,
+}
+
+/// HealthKit record type, raw_data key, question and daily aggregation.
+type RecordMapping = (&'static str, &'static str, &'static str, Aggregation);
+
+const QUANTITY_TYPES: &[RecordMapping] = &[
+    ("HKQuantityTypeIdentifierStepCount", "appleSteps", "Apple Health Steps", Aggregation::Sum),
+    ("HKQuantityTypeIdentifierDistanceWalkingRunning", "appleWalkingDistance", "Apple Health Walking + Running Distance", Aggregation::Sum),
+    ("HKQuantityTypeIdentifierFlightsClimbed", "appleFlightsClimbed", "Apple Health Flights Climbed", Aggregation::Sum),
+    ("HKQuantityTypeIdentifierActiveEnergyBurned", "appleActiveEnergy", "Apple Health Active Energy", Aggregation::Sum),
+    ("HKQuantityTypeIdentifierAppleExerciseTime", "appleExerciseMinutes", "Apple Health Exercise Minutes", Aggregation::Sum),
+    ("HKQuantityTypeIdentifierHeartRate", "appleHeartRate", "Apple Health Heart Rate", Aggregation::Mean),
+    ("HKQuantityTypeIdentifierRestingHeartRate", "appleRestingHeartRate", "Apple Health Resting Heart Rate", Aggregation::Mean),
+    ("HKQuantityTypeIdentifierHeartRateVariabilitySDNN", "appleHRV", "Apple Health Heart Rate Variability", Aggregation::Mean),
+    ("HKQuantityTypeIdentifierBodyMass", "appleBodyMass", "Apple Health Body Mass", Aggregation::Mean),
+];
+
+const SLEEP_TYPE: &str = "HKCategoryTypeIdentifierSleepAnalysis";
+const SLEEP_ASLEEP: RecordMapping = ("", "appleSleepHours", "Apple Health Hours Asleep", Aggregation::Sum);
+const SLEEP_IN_BED: RecordMapping = ("", "appleInBedHours", "Apple Health Hours In Bed", Aggregation::Sum);
+const WORKOUT_MINUTES: RecordMapping = ("", "appleWorkoutMinutes", "Apple Health Workout Minutes", Aggregation::Sum);
+const WORKOUT_ENERGY: RecordMapping = ("", "appleWorkoutEnergy", "Apple Health Workout Energy", Aggregation::Sum);
+const WORKOUT_TYPE_KEY: &str = "appleWorkoutType";
+
+/// Imports the `export.zip` (or bare `export.xml`) of the iOS Health app,
+/// uploaded through `/api/v1/upload/applehealth`.
+pub struct AppleHealth {
+    uploads: Arc<UploadStore>,
+}
+
+impl AppleHealth {
+    pub fn new(uploads: Arc<UploadStore>) -> AppleHealth {
+        AppleHealth { uploads }
+    }
+}
+
+impl BaseIntegration for AppleHealth {
+    fn name(&self) -> String {
+        "AppleHealth".to_string()
+    }
+
+    fn authorize(&self) -> AuthStatus {
+        AuthStatus::Authorized
+    }
+
+    /// Reads the days in range from the most recent upload.
+    fn get_data(&self, start_date: NaiveDate, end_date: NaiveDate) -> Result<Vec<Observation>, IntegrationError> {
+        if start_date > end_date {
+            return Err(IntegrationError::InvalidDateRange(start_date, end_date));
+        }
+        match self.uploads.latest(&self.name()) {
+            Some((importid, path)) => parse_export_file(&path, &importid, Some((start_date, end_date))),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn is_scheduled(&self) -> bool {
+        false
+    }
+
+    fn import_upload(&self, path: &Path, importid: &str) -> Result<Vec<Observation>, IntegrationError> {
+        parse_export_file(path, importid, None)
+    }
+}
+
+/// Parses an export archive or XML file, detected by the zip magic bytes.
+pub fn parse_export_file(
+    path: &Path,
+    importid: &str,
+    range: Option<(NaiveDate, NaiveDate)>,
+) -> Result<Vec<Observation>, IntegrationError> {
+    let mut file = File::open(path).map_err(invalid_upload)?;
+    let mut magic = [0u8; 2];
+    let is_zip = file.read(&mut magic).map_err(invalid_upload)? == 2 && &magic == b"PK";
+    file.seek(SeekFrom::Start(0)).map_err(invalid_upload)?;
+
+    if !is_zip {
+        return parse_export(BufReader::new(file), importid, range);
+    }
+
+    let mut archive = zip::ZipArchive::new(file).map_err(invalid_upload)?;
+    let index = (0..archive.len())
+        .find(|i| {
+            archive.by_index(*i)
+                .map(|entry| entry.name().ends_with("/export.xml") || entry.name() == "export.xml")
+                .unwrap_or(false)
+        })
+        .ok_or_else(|| IntegrationError::InvalidUpload("no export.xml in archive".to_string()))?;
+    let entry = archive.by_index(index).map_err(invalid_upload)?;
+    parse_export(BufReader::new(entry), importid, range)
+}
+
+/// Streams `export.xml`, holding only the per-day aggregates in memory.
+pub fn parse_export<R: BufRead>(
+    reader: R,
+    importid: &str,
+    range: Option<(NaiveDate, NaiveDate)>,
+) -> Result<Vec<Observation>, IntegrationError> {
+    let mut reader = Reader::from_reader(reader);
+    let mut buf = Vec::new();
+    let mut days = DailyAggregates::default();
+    let mut workout_types = Vec::new();
+    let in_range = |date: NaiveDate| range.map(|(start, end)| start <= date && date <= end).unwrap_or(true);
+
+    loop {
+        match reader.read_event_into(&mut buf).map_err(invalid_upload)? {
+            Event::Start(e) | Event::Empty(e) => match e.name().as_ref() {
+                b"Record" => {
+                    let record = attributes(&e)?;
+                    if let Some((mapping, date, source, value)) = map_record(&record)? {
+                        if in_range(date) {
+                            days.add(mapping, date, source, value);
+                        }
+                    }
+                }
+                b"Workout" => {
+                    let workout = attributes(&e)?;
+                    let start = parse_date(&workout, "startDate")?;
+                    let date = start.date_naive();
+                    if in_range(date) {
+                        let source = workout.get("sourceName").cloned().unwrap_or_default();
+                        if let Some(minutes) = workout_minutes(&workout) {
+                            days.add(WORKOUT_MINUTES, date, source.clone(), minutes);
+                        }
+                        if let Some(kcal) = workout.get("totalEnergyBurned").and_then(|v| v.parse().ok()) {
+                            days.add(WORKOUT_ENERGY, date, source, kcal);
+                        }
+                        if let Some(activity) = workout.get("workoutActivityType") {
+                            let activity = activity.trim_start_matches("HKWorkoutActivityType").to_string();
+                            workout_types.push((start, activity));
+                        }
+                    }
+                }
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    let mut observations = days.into_observations(importid);
+    observations.extend(workout_types.into_iter().map(|(start, activity)| Observation {
+        key: WORKOUT_TYPE_KEY.to_string(),
+        question: "Apple Health Workout Type".to_string(),
+        value: ObservationValue::Text(activity),
+        timestamp: start.timestamp_millis(),
+        matcheddate: start.date_naive(),
+        source: SOURCE.to_string(),
+        importid: importid.to_string(),
+    }));
+    Ok(observations)
+}
+
+type Attributes = HashMap<String, String>;
+
+fn attributes(element: &BytesStart) -> Result<Attributes, IntegrationError> {
+    element.attributes()
+        .map(|attr| {
+            let attr = attr.map_err(invalid_upload)?;
+            let key = String::from_utf8_lossy(attr.key.as_ref()).to_string();
+            let value = attr.unescape_value().map_err(invalid_upload)?.to_string();
+            Ok((key, value))
+        })
+        .collect()
+}
+
+/// The mapping, matched day, recording device and value of a `<Record>`, or
+/// None for record types we do not import.
+fn map_record(record: &Attributes) -> Result<Option<(RecordMapping, NaiveDate, String, f64)>, IntegrationError> {
+    let record_type = record.get("type").map(String::as_str).unwrap_or_default();
+    let source = record.get("sourceName").cloned().unwrap_or_default();
+
+    if record_type == SLEEP_TYPE {
+        let start = parse_date(record, "startDate")?;
+        let end = parse_date(record, "endDate")?;
+        let hours = (end - start).num_seconds() as f64 / 3600.0;
+        // A night counts towards the day you wake up on.
+        let date = end.date_naive();
+        let value = record.get("value").map(String::as_str).unwrap_or_default();
+        let mapping = if value.starts_with("HKCategoryValueSleepAnalysisAsleep") {
+            SLEEP_ASLEEP
+        } else if value == "HKCategoryValueSleepAnalysisInBed" {
+            SLEEP_IN_BED
+        } else {
+            return Ok(None);
+        };
+        return Ok(Some((mapping, date, source, hours)));
+    }
+
+    let mapping = match QUANTITY_TYPES.iter().find(|(t, _, _, _)| *t == record_type) {
+        Some(mapping) => *mapping,
+        None => return Ok(None),
+    };
+    let value = match record.get("value").and_then(|v| v.parse::<f64>().ok()) {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    let date = parse_date(record, "startDate")?.date_naive();
+    Ok(Some((mapping, date, source, value)))
+}
+
+fn workout_minutes(workout: &Attributes) -> Option<f64> {
+    let duration: f64 = workout.get("duration")?.parse().ok()?;
+    match workout.get("durationUnit").map(String::as_str) {
+        Some("s") | Some("sec") => Some(duration / 60.0),
+        Some("hr") | Some("h") => Some(duration * 60.0),
+        _ => Some(duration),
+    }
+}
+
+fn parse_date(attributes: &Attributes, name: &str) -> Result<DateTime<FixedOffset>, IntegrationError> {
+    let value = attributes.get(name)
+        .ok_or_else(|| IntegrationError::InvalidUpload(format!("record without {}", name)))?;
+    DateTime::parse_from_str(value, DATE_FORMAT)
+        .map_err(|e| IntegrationError::InvalidUpload(format!("{} '{}' - {}", name, value, e)))
+}
+
+fn invalid_upload<E: std::fmt::Display>(e: E) -> IntegrationError {
+    IntegrationError::InvalidUpload(e.to_string())
+}
+
+#[derive(Default)]
+struct DailyAggregates {
+    // BTreeMap keeps the output ordered by key and day.
+    values: BTreeMap<(&'static str, NaiveDate), DailyValue>,
+}
+
+struct DailyValue {
+    mapping: RecordMapping,
+    per_source: HashMap<String, (f64, u32)>,
+}
+
+impl DailyAggregates {
+    fn add(&mut self, mapping: RecordMapping, date: NaiveDate, source: String, value: f64) {
+        let day = self.values.entry((mapping.1, date)).or_insert_with(|| DailyValue {
+            mapping,
+            per_source: HashMap::new(),
+        });
+        let (total, count) = day.per_source.entry(source).or_insert((0.0, 0));
+        *total += value;
+        *count += 1;
+    }
+
+    fn into_observations(self, importid: &str) -> Vec<Observation> {
+        self.values.into_iter()
+            .map(|((key, date), day)| {
+                let value = match day.mapping.3 {
+                    Aggregation::Sum => day.per_source.values().map(|(total, _)| *total).fold(0.0, f64::max),
+                    Aggregation::Mean => {
+                        let (total, count) = day.per_source.values()
+                            .fold((0.0, 0), |(t, c), (total, count)| (t + total, c + count));
+                        total / count as f64
+                    }
+                };
+                Observation {
+                    key: key.to_string(),
+                    question: day.mapping.2.to_string(),
+                    value: ObservationValue::Number(value),
+                    // Daily aggregates are stamped at midnight UTC of their day so
+                    // re-importing a newer export updates the same rows.
+                    timestamp: date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis(),
+                    matcheddate: date,
+                    source: SOURCE.to_string(),
+                    importid: importid.to_string(),
+                }
+            })
+            .collect()
+    }
+}
@@ -1,32 +1,110 @@
+use crate::oauth::OAuthClient;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
 pub trait BaseIntegration {
     fn name(&self) -> String;
-    fn authorize(&self) -> bool;
-    fn get_data(&self, start_date: String, end_date: String) -> String;
+    fn authorize(&self) -> AuthStatus;
+    fn get_data(&self, start_date: NaiveDate, end_date: NaiveDate) -> Result<Vec<Observation>, IntegrationError>;
+
+    /// The OAuth2 flow used to obtain credentials, for integrations that have one.
+    fn oauth(&self) -> Option<&OAuthClient> {
+        None
+    }
+
+    /// False for integrations fed by uploads rather than polled by the scheduler.
+    fn is_scheduled(&self) -> bool {
+        true
+    }
+
+    /// Parses a file uploaded through `/api/v1/upload/{integration_name}`.
+    fn import_upload(&self, _path: &Path, _importid: &str) -> Result<Vec<Observation>, IntegrationError> {
+        Err(IntegrationError::UploadNotSupported(self.name()))
+    }
 }
 
-pub struct Oura {
-    name: String
+/// Whether an integration currently holds usable credentials.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", content = "message", rename_all = "snake_case")]
+pub enum AuthStatus {
+    Authorized,
+    MissingCredentials,
+    Expired,
+    Error(String),
 }
 
-impl Oura {
-    pub fn new() -> Oura {
-        return Oura {
-            name: "Oura".to_string()
-        };
+impl AuthStatus {
+    pub fn is_authorized(&self) -> bool {
+        *self == AuthStatus::Authorized
     }
 }
 
-impl BaseIntegration for Oura  {
+/// One value fetched from an integration, mirroring the `raw_data` columns.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Observation {
+    pub key: String,
+    pub question: String,
+    pub value: ObservationValue,
+    pub timestamp: i64,
+    pub matcheddate: NaiveDate,
+    pub source: String,
+    pub importid: String,
+}
 
-    fn name(&self) -> String {
-        return "Oura".to_string();
-    }
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ObservationValue {
+    Number(f64),
+    Text(String),
+}
 
-    fn authorize(&self) -> bool {
-        return true;
+impl ObservationValue {
+    /// The `raw_data.type` this value is stored as.
+    pub fn data_type(&self) -> &'static str {
+        match self {
+            ObservationValue::Number(_) => "number",
+            ObservationValue::Text(_) => "text",
+        }
     }
 
-    fn get_data(&self, start_date: String, end_date: String) -> String {
-        return "Oura data".to_string();
+    /// The textual form written to `raw_data.value`.
+    pub fn to_raw_value(&self) -> String {
+        match self {
+            ObservationValue::Number(n) => n.to_string(),
+            ObservationValue::Text(s) => s.clone(),
+        }
     }
 }
+
+#[derive(thiserror::Error, Debug)]
+pub enum IntegrationError {
+    #[error("{0} is not authorized - {1:?}")]
+    Unauthorized(String, AuthStatus),
+
+    #[error("Invalid date range {0} - {1}")]
+    InvalidDateRange(NaiveDate, NaiveDate),
+
+    #[error("Request failed - {0}")]
+    Http(String),
+
+    #[error("Unexpected response - {0}")]
+    InvalidResponse(String),
+
+    #[error("{0} does not accept uploads")]
+    UploadNotSupported(String),
+
+    #[error("Invalid upload - {0}")]
+    InvalidUpload(String),
+
+    #[error("Invalid configuration - {0}")]
+    InvalidConfig(String),
+
+    #[error("Integration task failed - {0}")]
+    Task(String),
+}
+
+/// Identifier shared by every observation produced in one integration run.
+pub fn new_import_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
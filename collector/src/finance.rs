@@ -0,0 +1,456 @@
+use crate::base_integration::{AuthStatus, BaseIntegration, IntegrationError, Observation, ObservationValue};
+use crate::uploads::UploadStore;
+use chrono::{Duration, NaiveDate};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+const SOURCE: &str = "finance";
+const NET_WORTH_KEY: &str = "financeNetWorth";
+const NET_WORTH_QUESTION: &str = "Net Worth";
+/// How far into a CSV the header row is looked for, past bank preambles.
+const HEADER_SEARCH_ROWS: usize = 30;
+
+/// Per-institution statement formats, read from `FINANCE_CONFIG` (default
+/// `finance.json`) on every import so new banks need no rebuild. See
+/// `finance.sample.json`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct FinanceConfig {
+    #[serde(default)]
+    pub institutions: Vec<InstitutionMapping>,
+    /// OFX `ACCTID` to account name; unmapped accounts keep their id.
+    #[serde(default)]
+    pub ofx_accounts: HashMap<String, String>,
+}
+
+/// Column mapping of one bank or brokerage CSV export. A statement is matched
+/// to the first institution whose columns all appear in its header row.
+#[derive(Debug, Clone, Deserialize)]
+pub struct InstitutionMapping {
+    pub name: String,
+    pub account: String,
+    pub date_column: String,
+    pub date_format: String,
+    /// Running balance after each row. Without it the balance is accumulated
+    /// from `opening_balance` and the amount columns.
+    pub balance_column: Option<String>,
+    pub amount_column: Option<String>,
+    pub debit_column: Option<String>,
+    pub credit_column: Option<String>,
+    #[serde(default)]
+    pub opening_balance: f64,
+    /// Credit cards and loans, whose balances count against net worth.
+    #[serde(default)]
+    pub liability: bool,
+    pub delimiter: Option<char>,
+}
+
+impl InstitutionMapping {
+    fn columns(&self) -> Vec<&str> {
+        [
+            Some(&self.date_column),
+            self.balance_column.as_ref(),
+            self.amount_column.as_ref(),
+            self.debit_column.as_ref(),
+            self.credit_column.as_ref(),
+        ]
+        .iter()
+        .flatten()
+        .map(|c| c.as_str())
+        .collect()
+    }
+}
+
+impl FinanceConfig {
+    /// A missing file is an empty config, which only accepts OFX statements.
+    pub fn load(path: &Path) -> Result<FinanceConfig, IntegrationError> {
+        let invalid = |e: &dyn std::fmt::Display| IntegrationError::InvalidUpload(format!("{} - {}", path.display(), e));
+        match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| invalid(&e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(FinanceConfig::default()),
+            Err(e) => Err(invalid(&e)),
+        }
+    }
+}
+
+/// End-of-day balances of one account.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountBalances {
+    pub account: String,
+    pub balances: BTreeMap<NaiveDate, f64>,
+}
+
+/// Daily account balances and net worth from uploaded CSV and OFX statements.
+pub struct Finance {
+    uploads: Arc<UploadStore>,
+    config_path: PathBuf,
+}
+
+impl Finance {
+    /// Reads the institution mappings from `FINANCE_CONFIG`.
+    pub fn from_env(uploads: Arc<UploadStore>) -> Finance {
+        let config_path = std::env::var("FINANCE_CONFIG").unwrap_or_else(|_| "finance.json".to_string());
+        Finance::new(uploads, PathBuf::from(config_path))
+    }
+
+    pub fn new(uploads: Arc<UploadStore>, config_path: PathBuf) -> Finance {
+        Finance { uploads, config_path }
+    }
+
+    fn config(&self) -> Result<FinanceConfig, IntegrationError> {
+        FinanceConfig::load(&self.config_path)
+    }
+
+    /// Every stored statement, oldest first, with an extra one that may not be
+    /// in the store yet. Re-uploads of the same file are only counted once.
+    fn statements(&self, extra: Option<&Path>) -> Result<Vec<(String, Vec<u8>)>, IntegrationError> {
+        let mut paths: Vec<(std::time::SystemTime, PathBuf)> = fs::read_dir(self.uploads.dir(&self.name()))
+            .map(|entries| {
+                entries.filter_map(Result::ok)
+                    .map(|entry| entry.path())
+                    .filter(|path| path.extension().map(|e| e == "upload").unwrap_or(false))
+                    .map(|path| (fs::metadata(&path).and_then(|m| m.modified()).unwrap_or(std::time::UNIX_EPOCH), path))
+                    .collect()
+            })
+            .unwrap_or_default();
+        paths.sort();
+        let mut paths: Vec<PathBuf> = paths.into_iter().map(|(_, path)| path).collect();
+        if let Some(extra) = extra {
+            paths.retain(|path| path != extra);
+            paths.push(extra.to_path_buf());
+        }
+
+        let mut seen = HashSet::new();
+        let mut statements = Vec::new();
+        for path in paths {
+            let contents = fs::read(&path).map_err(|e| IntegrationError::InvalidUpload(e.to_string()))?;
+            let importid = statement_import_id(&contents);
+            if seen.insert(importid.clone()) {
+                statements.push((importid, contents));
+            }
+        }
+        Ok(statements)
+    }
+
+    fn observations(
+        &self,
+        statements: &[(String, Vec<u8>)],
+        only: Option<&str>,
+        range: Option<(NaiveDate, NaiveDate)>,
+    ) -> Result<Vec<Observation>, IntegrationError> {
+        let config = self.config()?;
+        // account -> day -> (balance, importid); later statements win.
+        let mut accounts: BTreeMap<String, BTreeMap<NaiveDate, (f64, String)>> = BTreeMap::new();
+        for (importid, contents) in statements {
+            for series in parse_statement(contents, &config)? {
+                let days = accounts.entry(series.account).or_default();
+                for (date, balance) in series.balances {
+                    days.insert(date, (balance, importid.clone()));
+                }
+            }
+        }
+        let latest = statements.last().map(|(importid, _)| importid.clone()).unwrap_or_default();
+        let net_worth_id = only.map(str::to_string).unwrap_or(latest);
+        let in_range = |date: &NaiveDate| range.map(|(start, end)| start <= *date && *date <= end).unwrap_or(true);
+
+        let mut observations = Vec::new();
+        for (account, days) in &accounts {
+            let key = balance_key(account);
+            for (date, (balance, importid)) in days.iter().filter(|(date, _)| in_range(date)) {
+                if only.map(|id| id == importid).unwrap_or(true) {
+                    observations.push(observation(&key, &format!("{} Balance", account), *date, *balance, importid));
+                }
+            }
+        }
+        let series: Vec<AccountBalances> = accounts.into_iter()
+            .map(|(account, days)| AccountBalances {
+                account,
+                balances: days.into_iter().map(|(date, (balance, _))| (date, balance)).collect(),
+            })
+            .collect();
+        for (date, total) in net_worth(&series).into_iter().filter(|(date, _)| in_range(date)) {
+            observations.push(observation(NET_WORTH_KEY, NET_WORTH_QUESTION, date, total, &net_worth_id));
+        }
+        Ok(observations)
+    }
+}
+
+impl BaseIntegration for Finance {
+    fn name(&self) -> String {
+        "Finance".to_string()
+    }
+
+    fn authorize(&self) -> AuthStatus {
+        match self.config() {
+            Ok(_) => AuthStatus::Authorized,
+            Err(e) => AuthStatus::Error(e.to_string()),
+        }
+    }
+
+    /// Balances and net worth in range, recomputed from every stored statement.
+    fn get_data(&self, start_date: NaiveDate, end_date: NaiveDate) -> Result<Vec<Observation>, IntegrationError> {
+        if start_date > end_date {
+            return Err(IntegrationError::InvalidDateRange(start_date, end_date));
+        }
+        let statements = self.statements(None)?;
+        self.observations(&statements, None, Some((start_date, end_date)))
+    }
+
+    fn is_scheduled(&self) -> bool {
+        false
+    }
+
+    /// The uploaded statement's balances plus the net-worth series, which
+    /// depends on every statement uploaded so far. Rows are tagged with an
+    /// importid derived from the statement's contents, so uploading the same
+    /// file again updates them in place.
+    fn import_upload(&self, path: &Path, _importid: &str) -> Result<Vec<Observation>, IntegrationError> {
+        let statements = self.statements(Some(path))?;
+        let contents = fs::read(path).map_err(|e| IntegrationError::InvalidUpload(e.to_string()))?;
+        let importid = statement_import_id(&contents);
+        self.observations(&statements, Some(&importid), None)
+    }
+}
+
+fn observation(key: &str, question: &str, date: NaiveDate, value: f64, importid: &str) -> Observation {
+    Observation {
+        key: key.to_string(),
+        question: question.to_string(),
+        value: ObservationValue::Number(value),
+        timestamp: date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis(),
+        matcheddate: date,
+        source: SOURCE.to_string(),
+        importid: importid.to_string(),
+    }
+}
+
+/// `finance-` and the first 16 hex digits of the statement's SHA-256.
+pub fn statement_import_id(contents: &[u8]) -> String {
+    let digest = Sha256::digest(contents);
+    let hex: String = digest.iter().take(8).map(|b| format!("{:02x}", b)).collect();
+    format!("finance-{}", hex)
+}
+
+/// `financeBalance` followed by the account name in PascalCase.
+pub fn balance_key(account: &str) -> String {
+    let pascal: String = account
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            let first = chars.next().unwrap().to_ascii_uppercase();
+            first.to_string() + &chars.as_str().to_ascii_lowercase()
+        })
+        .collect();
+    format!("financeBalance{}", pascal)
+}
+
+/// Sum of every account's latest known balance, for each day from the first
+/// balance to the last.
+pub fn net_worth(accounts: &[AccountBalances]) -> BTreeMap<NaiveDate, f64> {
+    let first = accounts.iter().filter_map(|a| a.balances.keys().next()).min();
+    let last = accounts.iter().filter_map(|a| a.balances.keys().next_back()).max();
+    let (mut day, last) = match (first, last) {
+        (Some(first), Some(last)) => (*first, *last),
+        _ => return BTreeMap::new(),
+    };
+
+    let mut totals = BTreeMap::new();
+    while day <= last {
+        let total = accounts.iter()
+            .filter_map(|a| a.balances.range(..=day).next_back().map(|(_, balance)| balance))
+            .sum();
+        totals.insert(day, total);
+        day += Duration::days(1);
+    }
+    totals
+}
+
+/// Parses an OFX statement, or a CSV matched against the configured institutions.
+pub fn parse_statement(contents: &[u8], config: &FinanceConfig) -> Result<Vec<AccountBalances>, IntegrationError> {
+    let text = String::from_utf8_lossy(contents);
+    let head = text.trim_start();
+    if head.starts_with("OFXHEADER") || head.starts_with("<?xml") || head.contains("<OFX>") {
+        parse_ofx(&text, config)
+    } else {
+        parse_csv(contents, config).map(|balances| vec![balances])
+    }
+}
+
+pub fn parse_csv(contents: &[u8], config: &FinanceConfig) -> Result<AccountBalances, IntegrationError> {
+    let mut rows_by_delimiter: HashMap<char, Vec<Vec<String>>> = HashMap::new();
+    let mut matched = None;
+    for institution in &config.institutions {
+        let delimiter = institution.delimiter.unwrap_or(',');
+        let rows = rows_by_delimiter.entry(delimiter).or_insert_with(|| read_rows(contents, delimiter));
+        let header_index = rows.iter()
+            .take(HEADER_SEARCH_ROWS)
+            .position(|row| institution.columns().iter().all(|c| row.iter().any(|cell| cell == c)));
+        if let Some(header_index) = header_index {
+            matched = Some((institution, delimiter, header_index));
+            break;
+        }
+    }
+    let (institution, delimiter, header_index) = matched
+        .ok_or_else(|| IntegrationError::InvalidUpload("statement matches no configured institution".to_string()))?;
+    let rows = &rows_by_delimiter[&delimiter];
+    let header = &rows[header_index];
+    let column = |name: &Option<String>| name.as_ref().and_then(|n| header.iter().position(|cell| cell == n));
+    let date_column = column(&Some(institution.date_column.clone())).expect("matched above");
+    let balance_column = column(&institution.balance_column);
+    let amount_column = column(&institution.amount_column);
+    let debit_column = column(&institution.debit_column);
+    let credit_column = column(&institution.credit_column);
+
+    // (date, balance or amount) for every row with a parseable date; the rest
+    // are separators and footers.
+    let mut entries: Vec<(NaiveDate, f64)> = rows[header_index + 1..].iter()
+        .filter_map(|row| {
+            let date = NaiveDate::parse_from_str(row.get(date_column)?.trim(), &institution.date_format).ok()?;
+            let cell = |index: Option<usize>| index.and_then(|i| row.get(i)).and_then(|v| parse_amount(v));
+            let value = if balance_column.is_some() {
+                cell(balance_column)?
+            } else if amount_column.is_some() {
+                cell(amount_column)?
+            } else {
+                cell(credit_column).unwrap_or(0.0) - cell(debit_column).unwrap_or(0.0)
+            };
+            Some((date, value))
+        })
+        .collect();
+    if entries.is_empty() {
+        return Err(IntegrationError::InvalidUpload(format!("no {} transactions in statement", institution.name)));
+    }
+    // Statements listed newest first are walked oldest first.
+    if entries.first().map(|e| e.0) > entries.last().map(|e| e.0) {
+        entries.reverse();
+    }
+
+    let mut balances = BTreeMap::new();
+    let mut running = institution.opening_balance;
+    for (date, value) in entries {
+        let balance = if balance_column.is_some() {
+            value
+        } else {
+            running += value;
+            running
+        };
+        // Subtracting from 0.0 keeps a settled card at 0 rather than -0.
+        balances.insert(date, if institution.liability { 0.0 - balance } else { balance });
+    }
+    Ok(AccountBalances { account: institution.account.clone(), balances })
+}
+
+fn read_rows(contents: &[u8], delimiter: char) -> Vec<Vec<String>> {
+    csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter as u8)
+        .from_reader(contents)
+        .records()
+        .filter_map(Result::ok)
+        .map(|record| record.iter().map(|cell| cell.trim().to_string()).collect())
+        .collect()
+}
+
+/// Parses amounts such as `1,234.50`, `₹ -20`, `(75.00)` or `12.00 Dr`.
+pub fn parse_amount(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let negative = (value.starts_with('(') && value.ends_with(')')) || value.to_ascii_lowercase().ends_with("dr");
+    let digits: String = value.chars().filter(|c| c.is_ascii_digit() || *c == '.' || *c == '-').collect();
+    if digits.is_empty() || digits == "-" {
+        return None;
+    }
+    let amount: f64 = digits.parse().ok()?;
+    Some(if negative { -amount.abs() } else { amount })
+}
+
+/// Reads OFX 1.x (SGML) and 2.x (XML) statements. Balances before the ledger
+/// date are rebuilt by walking the transactions backwards from it.
+pub fn parse_ofx(text: &str, config: &FinanceConfig) -> Result<Vec<AccountBalances>, IntegrationError> {
+    let mut statements = Vec::new();
+    let mut account: Option<String> = None;
+    let mut transactions: Vec<(NaiveDate, f64)> = Vec::new();
+    let mut transaction: (Option<NaiveDate>, Option<f64>) = (None, None);
+    let mut ledger: (Option<f64>, Option<NaiveDate>) = (None, None);
+    let mut in_ledger = false;
+
+    for (tag, value) in ofx_elements(text) {
+        match tag.as_str() {
+            "ACCTID" => account = Some(value),
+            "STMTTRN" => transaction = (None, None),
+            "DTPOSTED" => transaction.0 = parse_ofx_date(&value),
+            "TRNAMT" => transaction.1 = parse_amount(&value),
+            "/STMTTRN" => {
+                if let (Some(date), Some(amount)) = transaction {
+                    transactions.push((date, amount));
+                }
+            }
+            "LEDGERBAL" => in_ledger = true,
+            "/LEDGERBAL" => in_ledger = false,
+            "BALAMT" if in_ledger => ledger.0 = parse_amount(&value),
+            "DTASOF" if in_ledger => ledger.1 = parse_ofx_date(&value),
+            "/STMTRS" | "/CCSTMTRS" => {
+                let id = account.take()
+                    .ok_or_else(|| IntegrationError::InvalidUpload("OFX statement without ACCTID".to_string()))?;
+                let (balance, as_of) = match ledger {
+                    (Some(balance), Some(as_of)) => (balance, as_of),
+                    _ => return Err(IntegrationError::InvalidUpload(format!("OFX statement {} without LEDGERBAL", id))),
+                };
+                let name = config.ofx_accounts.get(&id).cloned().unwrap_or(id);
+                statements.push(AccountBalances {
+                    account: name,
+                    balances: rebuild_balances(&transactions, balance, as_of),
+                });
+                transactions.clear();
+                ledger = (None, None);
+            }
+            _ => {}
+        }
+    }
+    if statements.is_empty() {
+        return Err(IntegrationError::InvalidUpload("OFX file without statements".to_string()));
+    }
+    Ok(statements)
+}
+
+fn rebuild_balances(transactions: &[(NaiveDate, f64)], balance: f64, as_of: NaiveDate) -> BTreeMap<NaiveDate, f64> {
+    let mut by_day: BTreeMap<NaiveDate, f64> = BTreeMap::new();
+    for (date, amount) in transactions.iter().filter(|(date, _)| *date <= as_of) {
+        *by_day.entry(*date).or_default() += amount;
+    }
+    let mut balances = BTreeMap::new();
+    balances.insert(as_of, balance);
+    let mut running = balance;
+    for (date, amount) in by_day.iter().rev() {
+        balances.insert(*date, running);
+        running -= amount;
+    }
+    balances
+}
+
+/// `(tag, text)` pairs in document order; closing tags keep their `/`.
+fn ofx_elements(text: &str) -> Vec<(String, String)> {
+    let mut elements = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let end = match rest.find('>') {
+            Some(end) => end,
+            None => break,
+        };
+        let tag = rest[..end].trim().to_ascii_uppercase();
+        rest = &rest[end + 1..];
+        let value_end = rest.find('<').unwrap_or(rest.len());
+        elements.push((tag, rest[..value_end].trim().to_string()));
+    }
+    elements
+}
+
+/// OFX dates start with `YYYYMMDD`, optionally followed by a time and zone.
+fn parse_ofx_date(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value.get(..8)?, "%Y%m%d").ok()
+}
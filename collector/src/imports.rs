@@ -0,0 +1,164 @@
+use crate::db::Db;
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::Serialize;
+
+const RAW_DATA_COLUMNS: &str = "timestamp, yearmonth, yearweek, year, quarter, month, day,
+    hour, minute, week, key, question, type, value, matcheddate,
+    source, importedat, importid, timezone";
+
+pub const DEFAULT_ROW_LIMIT: i64 = 500;
+pub const MAX_ROW_LIMIT: i64 = 5000;
+
+/// One past import, grouped from the `raw_data` rows it still owns.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ImportSummary {
+    pub importid: String,
+    pub source: Option<String>,
+    pub rows: i64,
+    pub first_date: Option<NaiveDate>,
+    pub last_date: Option<NaiveDate>,
+    pub importedat: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ImportRow {
+    pub id: i32,
+    pub timestamp: Option<i64>,
+    pub key: Option<String>,
+    pub question: Option<String>,
+    #[serde(rename = "type")]
+    #[sqlx(rename = "type")]
+    pub data_type: Option<String>,
+    pub value: Option<String>,
+    pub matcheddate: Option<NaiveDate>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ImportError {
+    /// Imports are undone newest first, so no version of a row is lost.
+    #[error("Rows of this import were overwritten by {}; roll those back first", .0.join(", "))]
+    OverwrittenLater(Vec<String>),
+
+    #[error(transparent)]
+    Sqlx(#[from] sqlx::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct RollbackResult {
+    pub deleted: u64,
+    pub restored: u64,
+}
+
+/// Clamps a requested page to `1..=MAX_ROW_LIMIT` rows and a non-negative offset.
+pub fn page(limit: Option<i64>, offset: Option<i64>) -> (i64, i64) {
+    let limit = limit.unwrap_or(DEFAULT_ROW_LIMIT).clamp(1, MAX_ROW_LIMIT);
+    (limit, offset.unwrap_or(0).max(0))
+}
+
+/// Imports newest first, optionally of one source.
+pub async fn list_imports(db: &Db, source: Option<&str>) -> Result<Vec<ImportSummary>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT importid, source, COUNT(*) AS rows, MIN(matcheddate) AS first_date,
+            MAX(matcheddate) AS last_date, MAX(importedat) AS importedat
+        FROM raw_data
+        WHERE importid IS NOT NULL AND ($1::text IS NULL OR source = $1)
+        GROUP BY importid, source
+        ORDER BY MAX(importedat) DESC NULLS LAST",
+    )
+    .bind(source)
+    .fetch_all(db)
+    .await
+}
+
+pub async fn get_import(db: &Db, importid: &str) -> Result<Option<ImportSummary>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT importid, MIN(source) AS source, COUNT(*) AS rows, MIN(matcheddate) AS first_date,
+            MAX(matcheddate) AS last_date, MAX(importedat) AS importedat
+        FROM raw_data
+        WHERE importid = $1
+        GROUP BY importid",
+    )
+    .bind(importid)
+    .fetch_optional(db)
+    .await
+}
+
+pub async fn import_rows(db: &Db, importid: &str, limit: i64, offset: i64) -> Result<Vec<ImportRow>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT id, timestamp, key, question, type, value, matcheddate
+        FROM raw_data
+        WHERE importid = $1
+        ORDER BY matcheddate, key, timestamp, id
+        LIMIT $2 OFFSET $3",
+    )
+    .bind(importid)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(db)
+    .await
+}
+
+/// Removes every row of an import in one transaction, and returns how many.
+/// The versions it overwrote stay in `raw_data_history`, so rolling the
+/// import back afterwards still restores them. Refused while a later import
+/// has overwritten some of its rows.
+pub async fn delete_import(db: &Db, importid: &str) -> Result<u64, ImportError> {
+    let mut tx = db.begin().await?;
+    refuse_overwritten(&mut tx, importid).await?;
+    let deleted = sqlx::query("DELETE FROM raw_data WHERE importid = $1")
+        .bind(importid)
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+    tx.commit().await?;
+    Ok(deleted)
+}
+
+/// Deletes an import and puts back the rows it overwrote, in one transaction.
+/// Refused while a later import has overwritten some of its rows: rolling
+/// back the later one first restores them, so imports are undone newest first
+/// and every older version comes back in turn.
+pub async fn rollback_import(db: &Db, importid: &str) -> Result<RollbackResult, ImportError> {
+    let mut tx = db.begin().await?;
+    refuse_overwritten(&mut tx, importid).await?;
+    let deleted = sqlx::query("DELETE FROM raw_data WHERE importid = $1")
+        .bind(importid)
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+    let restore = format!(
+        "INSERT INTO raw_data ({columns})
+        SELECT DISTINCT ON (source, key, timestamp) {columns}
+        FROM raw_data_history
+        WHERE replaced_by = $1
+        ORDER BY source, key, timestamp, replaced_at
+        ON CONFLICT (source, key, timestamp) WHERE importid IS NOT NULL DO NOTHING",
+        columns = RAW_DATA_COLUMNS,
+    );
+    let restored = sqlx::query(&restore)
+        .bind(importid)
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+    sqlx::query("DELETE FROM raw_data_history WHERE replaced_by = $1")
+        .bind(importid)
+        .execute(&mut tx)
+        .await?;
+    tx.commit().await?;
+    Ok(RollbackResult { deleted, restored })
+}
+
+/// Fails with the imports that overwrote rows of `importid` since.
+async fn refuse_overwritten(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>, importid: &str) -> Result<(), ImportError> {
+    let later: Vec<(String,)> = sqlx::query_as(
+        "SELECT replaced_by FROM raw_data_history WHERE importid = $1 AND replaced_by IS NOT NULL
+        GROUP BY replaced_by ORDER BY MAX(replaced_at) DESC",
+    )
+    .bind(importid)
+    .fetch_all(&mut *tx)
+    .await?;
+    if !later.is_empty() {
+        return Err(ImportError::OverwrittenLater(later.into_iter().map(|(importid,)| importid).collect()));
+    }
+    Ok(())
+}
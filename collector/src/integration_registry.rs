@@ -0,0 +1,56 @@
+use crate::base_integration::{BaseIntegration, IntegrationError, Observation};
+use actix_web::web;
+use chrono::NaiveDate;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub type SharedIntegration = Arc<dyn BaseIntegration + Send + Sync>;
+
+/// The integrations the collector can run, looked up by their `name()`.
+/// Names are matched case-insensitively so `?integration_name=oura` finds "Oura".
+#[derive(Default, Clone)]
+pub struct IntegrationRegistry {
+    integrations: HashMap<String, SharedIntegration>,
+}
+
+impl IntegrationRegistry {
+    pub fn new() -> IntegrationRegistry {
+        IntegrationRegistry::default()
+    }
+
+    pub fn register<I>(&mut self, integration: I)
+    where
+        I: BaseIntegration + Send + Sync + 'static,
+    {
+        let key = integration.name().to_lowercase();
+        self.integrations.insert(key, Arc::new(integration));
+    }
+
+    pub fn get(&self, name: &str) -> Option<SharedIntegration> {
+        self.integrations.get(&name.to_lowercase()).cloned()
+    }
+
+    pub fn all(&self) -> Vec<SharedIntegration> {
+        let mut integrations: Vec<SharedIntegration> = self.integrations.values().cloned().collect();
+        integrations.sort_by_key(|i| i.name());
+        integrations
+    }
+}
+
+/// Authorizes and runs an integration on the blocking thread pool, as the
+/// integrations use synchronous HTTP clients.
+pub async fn run_blocking(
+    integration: SharedIntegration,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+) -> Result<Vec<Observation>, IntegrationError> {
+    web::block(move || {
+        let status = integration.authorize();
+        if !status.is_authorized() {
+            return Err(IntegrationError::Unauthorized(integration.name(), status));
+        }
+        integration.get_data(start_date, end_date)
+    })
+    .await
+    .map_err(|e| IntegrationError::Task(e.to_string()))?
+}
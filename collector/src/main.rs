@@ -1,35 +1,95 @@
-use actix_web::{get, web, App, HttpServer, Responder, HttpRequest};
-use serde::{Serialize, Deserialize};
-
-#[derive(Debug, Serialize, Deserialize)]
-struct IntegrationQueryParams {
-    integration_name: String,
-    start_date: String,
-    end_date: String,
-}
+use actix_web::{get, web, App, HttpServer, Responder};
 
-#[get("/api/v1/getData")]
-async fn get_data(req: HttpRequest) -> impl Responder {
-    // let params = web::Query::<IntegrationQueryParams>::from_query(req.query_string()).unwrap();
-    let params = IntegrationQueryParams {
-        integration_name: req.query_string().to_string(),
-        start_date: "2020-01-01".to_string(),
-        end_date: "2020-01-01".to_string(),
-    };
-    return web::Json(params);
-}
+mod api;
+mod apple_health;
+mod base_integration;
+mod db;
+mod finance;
+mod imports;
+mod integration_registry;
+mod oauth;
+mod oura;
+mod raw_data;
+mod scheduler;
+mod timezones;
+mod token_store;
+mod uploads;
+mod weather;
+mod whoop;
+
+use apple_health::AppleHealth;
+use finance::Finance;
+use integration_registry::IntegrationRegistry;
+use oauth::PendingAuthorizations;
+use oura::Oura;
+use scheduler::SchedulerConfig;
+use std::sync::Arc;
+use token_store::EncryptedFileTokenStore;
+use uploads::UploadStore;
+use weather::Weather;
+use whoop::Whoop;
 
 #[get("/")]
 async fn greet() -> impl Responder {
-    format!("Hello bobo")
+    "Hello bobo".to_string()
 }
 
 #[actix_web::main] // or #[tokio::main]
 async fn main() -> std::io::Result<()> {
-    HttpServer::new(|| {
-        App::new().service(greet).service(get_data)
+    let token_store = match EncryptedFileTokenStore::from_env() {
+        Ok(store) => Some(Arc::new(store)),
+        Err(e) => {
+            println!("OAuth2 integrations disabled. Cause {}", e);
+            None
+        }
+    };
+
+    let uploads = Arc::new(UploadStore::from_env());
+
+    let db = db::init_db().await.expect("Cannot init db");
+
+    let mut registry = IntegrationRegistry::new();
+    registry.register(Oura::new(token_store.clone()));
+    registry.register(Whoop::new(token_store));
+    registry.register(AppleHealth::new(uploads.clone()));
+    registry.register(Finance::from_env(uploads.clone()));
+    registry.register(Weather::from_env(Some(db.clone())));
+
+    match SchedulerConfig::from_env() {
+        Some(config) => {
+            actix_web::rt::spawn(scheduler::run(registry.clone(), db.clone(), config));
+        }
+        None => println!("Scheduled sync disabled"),
+    }
+
+    let registry = web::Data::new(registry);
+    let db = web::Data::new(db);
+    let pending = web::Data::new(PendingAuthorizations::default());
+    let uploads = web::Data::from(uploads);
+
+    HttpServer::new(move || {
+        App::new()
+            .app_data(registry.clone())
+            .app_data(db.clone())
+            .app_data(pending.clone())
+            .app_data(uploads.clone())
+            .service(greet)
+            .service(api::list_integrations)
+            .service(api::get_data)
+            .service(api::import_data)
+            .service(api::upload_data)
+            .service(api::list_imports)
+            .service(api::get_import)
+            .service(api::delete_import)
+            .service(api::rollback_import)
+            .service(api::oauth_start)
+            .service(api::oauth_callback)
+            .service(api::list_timezones)
+            .service(api::add_timezone)
+            .service(api::recompute_days)
+            .service(api::delete_timezone)
     })
     .bind(("127.0.0.1", 8080))?
     .run()
     .await
-}
\ No newline at end of file
+}
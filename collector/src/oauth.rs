@@ -0,0 +1,237 @@
+use crate::base_integration::{AuthStatus, IntegrationError};
+use crate::token_store::{EncryptedFileTokenStore, OAuthToken};
+use chrono::Utc;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// Tokens this close to their expiry are refreshed before use.
+const EXPIRY_SKEW_MS: i64 = 60_000;
+const PENDING_STATE_TTL: Duration = Duration::from_secs(600);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OAuthConfig {
+    pub client_id: String,
+    pub client_secret: String,
+    pub authorize_url: String,
+    pub token_url: String,
+    pub redirect_uri: String,
+    pub scopes: Vec<String>,
+}
+
+impl OAuthConfig {
+    /// Reads `<PREFIX>_CLIENT_ID`, `<PREFIX>_CLIENT_SECRET` and, optionally,
+    /// `<PREFIX>_REDIRECT_URI`, `<PREFIX>_AUTHORIZE_URL` and `<PREFIX>_TOKEN_URL`.
+    /// Returns None when the integration has no OAuth app configured.
+    pub fn from_env(prefix: &str, authorize_url: &str, token_url: &str, scopes: &[&str]) -> Option<OAuthConfig> {
+        let var = |name: &str| std::env::var(format!("{}_{}", prefix, name)).ok();
+        let client_id = var("CLIENT_ID")?;
+        let default_redirect = format!("http://localhost:8080/api/v1/oauth/{}/callback", prefix.to_lowercase());
+
+        Some(OAuthConfig {
+            client_id,
+            client_secret: var("CLIENT_SECRET").unwrap_or_default(),
+            authorize_url: var("AUTHORIZE_URL").unwrap_or_else(|| authorize_url.to_string()),
+            token_url: var("TOKEN_URL").unwrap_or_else(|| token_url.to_string()),
+            redirect_uri: var("REDIRECT_URI").unwrap_or(default_redirect),
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_in: Option<i64>,
+}
+
+/// Authorization-code flow of one integration, with its tokens kept in the
+/// encrypted token store.
+pub struct OAuthClient {
+    integration: String,
+    config: OAuthConfig,
+    store: Arc<EncryptedFileTokenStore>,
+    agent: ureq::Agent,
+    // Providers rotate refresh tokens, so two concurrent refreshes would
+    // invalidate each other.
+    refresh_lock: Mutex<()>,
+}
+
+impl OAuthClient {
+    pub fn new(integration: &str, config: OAuthConfig, store: Arc<EncryptedFileTokenStore>) -> OAuthClient {
+        OAuthClient {
+            integration: integration.to_string(),
+            config,
+            store,
+            agent: ureq::Agent::new(),
+            refresh_lock: Mutex::new(()),
+        }
+    }
+
+    /// Fails when `<PREFIX>_AUTHORIZE_URL` isn't a valid URL.
+    pub fn authorize_url(&self, state: &str) -> Result<String, IntegrationError> {
+        let mut url = Url::parse(&self.config.authorize_url).map_err(|e| {
+            IntegrationError::InvalidConfig(format!("{} authorize url '{}' - {}", self.integration, self.config.authorize_url, e))
+        })?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("redirect_uri", &self.config.redirect_uri)
+            .append_pair("scope", &self.config.scopes.join(" "))
+            .append_pair("state", state);
+        Ok(url.to_string())
+    }
+
+    /// Exchanges the code from the provider's callback and stores the tokens.
+    pub fn exchange_code(&self, code: &str) -> Result<OAuthToken, IntegrationError> {
+        let token = self.request_token(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &self.config.redirect_uri),
+        ], None)?;
+        self.save(&token)?;
+        Ok(token)
+    }
+
+    /// A valid access token, refreshing (and rotating) it when it has expired.
+    pub fn access_token(&self) -> Result<String, IntegrationError> {
+        let _guard = self.refresh_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let token = self.store.load(&self.integration)
+            .map_err(|e| self.unauthorized(AuthStatus::Error(e.to_string())))?
+            .ok_or_else(|| self.unauthorized(AuthStatus::MissingCredentials))?;
+
+        if !is_expired(&token) {
+            return Ok(token.access_token);
+        }
+        let refresh_token = token.refresh_token
+            .ok_or_else(|| self.unauthorized(AuthStatus::Expired))?;
+        let refreshed = self.request_token(&[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", &refresh_token),
+        ], Some(&refresh_token))?;
+        self.save(&refreshed)?;
+        Ok(refreshed.access_token)
+    }
+
+    pub fn status(&self) -> AuthStatus {
+        match self.access_token() {
+            Ok(_) => AuthStatus::Authorized,
+            Err(IntegrationError::Unauthorized(_, status)) => status,
+            Err(e) => AuthStatus::Error(e.to_string()),
+        }
+    }
+
+    fn request_token(&self, params: &[(&str, &str)], previous_refresh_token: Option<&str>) -> Result<OAuthToken, IntegrationError> {
+        let mut form = params.to_vec();
+        form.push(("client_id", &self.config.client_id));
+        form.push(("client_secret", &self.config.client_secret));
+
+        let response: TokenResponse = self.agent.post(&self.config.token_url)
+            .send_form(&form)
+            .map_err(|e| match e {
+                // invalid_grant: the refresh token was revoked or already used
+                ureq::Error::Status(400, _) | ureq::Error::Status(401, _) => self.unauthorized(AuthStatus::Expired),
+                other => IntegrationError::Http(format!("{} token endpoint - {}", self.integration, other)),
+            })?
+            .into_json()
+            .map_err(|e| IntegrationError::InvalidResponse(format!("{} token endpoint - {}", self.integration, e)))?;
+
+        Ok(OAuthToken {
+            access_token: response.access_token,
+            // Providers that do not rotate refresh tokens omit them on refresh.
+            refresh_token: response.refresh_token.or_else(|| previous_refresh_token.map(str::to_string)),
+            expires_at: response.expires_in.map(|secs| Utc::now().timestamp_millis() + secs * 1000),
+        })
+    }
+
+    fn save(&self, token: &OAuthToken) -> Result<(), IntegrationError> {
+        self.store.save(&self.integration, token)
+            .map_err(|e| self.unauthorized(AuthStatus::Error(e.to_string())))
+    }
+
+    fn unauthorized(&self, status: AuthStatus) -> IntegrationError {
+        IntegrationError::Unauthorized(self.integration.clone(), status)
+    }
+}
+
+fn is_expired(token: &OAuthToken) -> bool {
+    match token.expires_at {
+        Some(expires_at) => expires_at - EXPIRY_SKEW_MS <= Utc::now().timestamp_millis(),
+        None => false,
+    }
+}
+
+/// How an integration authenticates its API calls.
+pub enum Credentials {
+    /// A personal access token, e.g. from `OURA_ACCESS_TOKEN`.
+    AccessToken(String),
+    OAuth(OAuthClient),
+}
+
+impl Credentials {
+    /// Uses the OAuth2 app from `<PREFIX>_CLIENT_ID`/`<PREFIX>_CLIENT_SECRET` when
+    /// one is configured together with a token store, else `<PREFIX>_ACCESS_TOKEN`.
+    pub fn from_env(
+        integration: &str,
+        authorize_url: &str,
+        token_url: &str,
+        scopes: &[&str],
+        store: Option<Arc<EncryptedFileTokenStore>>,
+    ) -> Credentials {
+        let prefix = integration.to_uppercase();
+        match (OAuthConfig::from_env(&prefix, authorize_url, token_url, scopes), store) {
+            (Some(config), Some(store)) => Credentials::OAuth(OAuthClient::new(integration, config, store)),
+            _ => Credentials::AccessToken(std::env::var(format!("{}_ACCESS_TOKEN", prefix)).unwrap_or_default()),
+        }
+    }
+
+    /// The `Authorization` header value.
+    pub fn bearer(&self, integration: &str) -> Result<String, IntegrationError> {
+        let token = match self {
+            Credentials::AccessToken(token) if token.is_empty() => {
+                return Err(IntegrationError::Unauthorized(integration.to_string(), AuthStatus::MissingCredentials));
+            }
+            Credentials::AccessToken(token) => token.clone(),
+            Credentials::OAuth(oauth) => oauth.access_token()?,
+        };
+        Ok(format!("Bearer {}", token))
+    }
+
+    pub fn oauth(&self) -> Option<&OAuthClient> {
+        match self {
+            Credentials::OAuth(oauth) => Some(oauth),
+            Credentials::AccessToken(_) => None,
+        }
+    }
+}
+
+/// `state` values handed out by the start endpoint, checked on callback.
+#[derive(Default)]
+pub struct PendingAuthorizations {
+    states: Mutex<HashMap<String, (String, Instant)>>,
+}
+
+impl PendingAuthorizations {
+    pub fn begin(&self, integration: &str) -> String {
+        let state = uuid::Uuid::new_v4().to_string();
+        let mut states = self.states.lock().unwrap_or_else(|e| e.into_inner());
+        states.retain(|_, (_, created)| created.elapsed() < PENDING_STATE_TTL);
+        states.insert(state.clone(), (integration.to_lowercase(), Instant::now()));
+        state
+    }
+
+    /// Consumes the state; true when it was issued for this integration and
+    /// has not expired.
+    pub fn complete(&self, state: &str, integration: &str) -> bool {
+        let mut states = self.states.lock().unwrap_or_else(|e| e.into_inner());
+        match states.remove(state) {
+            Some((expected, created)) => {
+                expected == integration.to_lowercase() && created.elapsed() < PENDING_STATE_TTL
+            }
+            None => false,
+        }
+    }
+}
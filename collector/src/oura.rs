@@ -0,0 +1,199 @@
+use crate::base_integration::{new_import_id, AuthStatus, BaseIntegration, IntegrationError, Observation, ObservationValue};
+use crate::oauth::{Credentials, OAuthClient};
+use crate::token_store::EncryptedFileTokenStore;
+use chrono::{DateTime, NaiveDate};
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::Arc;
+
+const OURA_API_URL: &str = "https://api.ouraring.com";
+const OURA_AUTHORIZE_URL: &str = "https://cloud.ouraring.com/oauth/authorize";
+const OURA_TOKEN_URL: &str = "https://api.ouraring.com/oauth/token";
+const OURA_SCOPES: &[&str] = &["daily", "personal"];
+const SOURCE: &str = "oura";
+
+/// (json pointer, raw_data key, question) for one numeric field.
+type FieldMapping = (&'static str, &'static str, &'static str);
+
+/// Daily collection endpoints and the fields we keep from each of them.
+const ENDPOINTS: &[(&str, &[FieldMapping])] = &[
+    ("daily_sleep", &[
+        ("/score", "ouraSleepScore", "Oura Sleep Score"),
+        ("/contributors/deep_sleep", "ouraSleepDeep", "Oura Deep Sleep Contributor"),
+        ("/contributors/efficiency", "ouraSleepEfficiency", "Oura Sleep Efficiency Contributor"),
+        ("/contributors/latency", "ouraSleepLatency", "Oura Sleep Latency Contributor"),
+        ("/contributors/rem_sleep", "ouraSleepRem", "Oura REM Sleep Contributor"),
+        ("/contributors/restfulness", "ouraSleepRestfulness", "Oura Restfulness Contributor"),
+        ("/contributors/timing", "ouraSleepTiming", "Oura Sleep Timing Contributor"),
+        ("/contributors/total_sleep", "ouraSleepTotal", "Oura Total Sleep Contributor"),
+    ]),
+    ("daily_readiness", &[
+        ("/score", "ouraReadinessScore", "Oura Readiness Score"),
+        ("/temperature_deviation", "ouraTemperatureDeviation", "Oura Temperature Deviation"),
+        ("/contributors/activity_balance", "ouraReadinessActivityBalance", "Oura Activity Balance Contributor"),
+        ("/contributors/body_temperature", "ouraReadinessBodyTemperature", "Oura Body Temperature Contributor"),
+        ("/contributors/hrv_balance", "ouraReadinessHrvBalance", "Oura HRV Balance Contributor"),
+        ("/contributors/previous_night", "ouraReadinessPreviousNight", "Oura Previous Night Contributor"),
+        ("/contributors/recovery_index", "ouraReadinessRecoveryIndex", "Oura Recovery Index Contributor"),
+        ("/contributors/resting_heart_rate", "ouraReadinessRHR", "Oura Resting Heart Rate Contributor"),
+        ("/contributors/sleep_balance", "ouraReadinessSleepBalance", "Oura Sleep Balance Contributor"),
+    ]),
+    ("daily_activity", &[
+        ("/score", "ouraActivityScore", "Oura Activity Score"),
+        ("/steps", "ouraSteps", "Oura Steps"),
+        ("/active_calories", "ouraActiveCalories", "Oura Active Calories"),
+        ("/total_calories", "ouraTotalCalories", "Oura Total Calories"),
+        ("/equivalent_walking_distance", "ouraWalkingDistance", "Oura Equivalent Walking Distance"),
+        ("/high_activity_time", "ouraHighActivityTime", "Oura High Activity Time"),
+        ("/medium_activity_time", "ouraMediumActivityTime", "Oura Medium Activity Time"),
+        ("/low_activity_time", "ouraLowActivityTime", "Oura Low Activity Time"),
+        ("/sedentary_time", "ouraSedentaryTime", "Oura Sedentary Time"),
+        ("/inactivity_alerts", "ouraInactivityAlerts", "Oura Inactivity Alerts"),
+    ]),
+];
+
+#[derive(Debug, Deserialize)]
+struct OuraPage {
+    data: Vec<Value>,
+    next_token: Option<String>,
+}
+
+pub struct Oura {
+    base_url: String,
+    credentials: Credentials,
+    agent: ureq::Agent,
+}
+
+impl Oura {
+    /// Uses the OAuth2 app from `OURA_CLIENT_ID`/`OURA_CLIENT_SECRET` when one is
+    /// configured together with a token store, else `OURA_ACCESS_TOKEN`.
+    pub fn new(store: Option<Arc<EncryptedFileTokenStore>>) -> Oura {
+        match Credentials::from_env("Oura", OURA_AUTHORIZE_URL, OURA_TOKEN_URL, OURA_SCOPES, store) {
+            Credentials::AccessToken(token) => Oura::with_base_url(OURA_API_URL.to_string(), token),
+            Credentials::OAuth(oauth) => Oura::with_oauth(OURA_API_URL.to_string(), oauth),
+        }
+    }
+
+    pub fn with_base_url(base_url: String, access_token: String) -> Oura {
+        Oura {
+            base_url,
+            credentials: Credentials::AccessToken(access_token),
+            agent: ureq::Agent::new(),
+        }
+    }
+
+    pub fn with_oauth(base_url: String, oauth: OAuthClient) -> Oura {
+        Oura {
+            base_url,
+            credentials: Credentials::OAuth(oauth),
+            agent: ureq::Agent::new(),
+        }
+    }
+
+    fn fetch_collection(&self, endpoint: &str, start_date: &str, end_date: &str) -> Result<Vec<Value>, IntegrationError> {
+        let url = format!("{}/v2/usercollection/{}", self.base_url, endpoint);
+        let bearer = self.credentials.bearer(&self.name())?;
+        let mut documents = Vec::new();
+        let mut next_token: Option<String> = None;
+
+        loop {
+            let mut request = self.agent.get(&url)
+                .set("Authorization", &bearer)
+                .query("start_date", start_date)
+                .query("end_date", end_date);
+            if let Some(token) = &next_token {
+                request = request.query("next_token", token);
+            }
+
+            let page: OuraPage = request.call()
+                .map_err(|e| match e {
+                    ureq::Error::Status(401, _) | ureq::Error::Status(403, _) => {
+                        IntegrationError::Unauthorized(self.name(), AuthStatus::Expired)
+                    }
+                    other => IntegrationError::Http(format!("Oura {} - {}", endpoint, other)),
+                })?
+                .into_json()
+                .map_err(|e| IntegrationError::InvalidResponse(format!("Oura {} - {}", endpoint, e)))?;
+            documents.extend(page.data);
+
+            match page.next_token {
+                Some(token) if !token.is_empty() => next_token = Some(token),
+                _ => break,
+            }
+        }
+        Ok(documents)
+    }
+}
+
+impl BaseIntegration for Oura {
+
+    fn name(&self) -> String {
+        "Oura".to_string()
+    }
+
+    fn authorize(&self) -> AuthStatus {
+        let bearer = match self.credentials.bearer(&self.name()) {
+            Ok(bearer) => bearer,
+            Err(IntegrationError::Unauthorized(_, status)) => return status,
+            Err(e) => return AuthStatus::Error(e.to_string()),
+        };
+        let url = format!("{}/v2/usercollection/personal_info", self.base_url);
+        match self.agent.get(&url).set("Authorization", &bearer).call() {
+            Ok(_) => AuthStatus::Authorized,
+            Err(ureq::Error::Status(401, _)) | Err(ureq::Error::Status(403, _)) => AuthStatus::Expired,
+            Err(e) => AuthStatus::Error(e.to_string()),
+        }
+    }
+
+    fn get_data(&self, start_date: NaiveDate, end_date: NaiveDate) -> Result<Vec<Observation>, IntegrationError> {
+        if start_date > end_date {
+            return Err(IntegrationError::InvalidDateRange(start_date, end_date));
+        }
+        let (start, end) = (start_date.to_string(), end_date.to_string());
+        let importid = new_import_id();
+
+        let mut observations = Vec::new();
+        for (endpoint, fields) in ENDPOINTS {
+            for document in self.fetch_collection(endpoint, &start, &end)? {
+                observations.extend(map_document(&document, fields, &importid)?);
+            }
+        }
+        Ok(observations)
+    }
+
+    fn oauth(&self) -> Option<&OAuthClient> {
+        self.credentials.oauth()
+    }
+}
+
+fn map_document(document: &Value, fields: &[FieldMapping], importid: &str) -> Result<Vec<Observation>, IntegrationError> {
+    let day = document["day"].as_str()
+        .ok_or_else(|| IntegrationError::InvalidResponse("Oura document without a day".to_string()))?;
+    let matcheddate = NaiveDate::parse_from_str(day, "%Y-%m-%d")
+        .map_err(|e| IntegrationError::InvalidResponse(format!("Oura day '{}' - {}", day, e)))?;
+    // Daily documents carry the start of the day in the ring's local offset;
+    // fall back to midnight UTC for the matched day.
+    let timestamp = match document["timestamp"].as_str() {
+        Some(ts) => DateTime::parse_from_rfc3339(ts)
+            .map_err(|e| IntegrationError::InvalidResponse(format!("Oura timestamp '{}' - {}", ts, e)))?
+            .timestamp_millis(),
+        None => matcheddate.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis(),
+    };
+
+    let observations = fields.iter()
+        .filter_map(|(pointer, key, question)| {
+            document.pointer(pointer)
+                .and_then(Value::as_f64)
+                .map(|v| Observation {
+                    key: key.to_string(),
+                    question: question.to_string(),
+                    value: ObservationValue::Number(v),
+                    timestamp,
+                    matcheddate,
+                    source: SOURCE.to_string(),
+                    importid: importid.to_string(),
+                })
+        })
+        .collect();
+    Ok(observations)
+}
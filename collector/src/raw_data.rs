@@ -0,0 +1,213 @@
+use crate::base_integration::Observation;
+use crate::db::Db;
+use crate::timezones::DayClock;
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Utc};
+use std::collections::HashSet;
+
+/// A `raw_data` row with the calendar columns derived from its observation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawDataRecord {
+    pub timestamp: i64,
+    pub yearmonth: i32,
+    pub yearweek: i32,
+    pub year: i16,
+    pub quarter: i16,
+    pub month: i16,
+    pub day: i16,
+    pub hour: i16,
+    pub minute: i16,
+    pub week: i16,
+    pub key: String,
+    pub question: String,
+    pub data_type: String,
+    pub value: String,
+    pub matcheddate: NaiveDate,
+    pub source: String,
+    pub importid: String,
+    pub timezone: String,
+}
+
+impl RawDataRecord {
+    /// Date columns follow `matcheddate`, so a late-night entry stays on the day
+    /// it belongs to; hour and minute are the local time of the timestamp in the
+    /// zone `clock` says you were in.
+    pub fn from_observation(observation: &Observation, clock: &DayClock) -> RawDataRecord {
+        let columns = DayColumns::of(observation.matcheddate);
+        let local = clock.localize(observation.timestamp);
+
+        RawDataRecord {
+            timestamp: observation.timestamp,
+            yearmonth: columns.yearmonth,
+            yearweek: columns.yearweek,
+            year: columns.year,
+            quarter: columns.quarter,
+            month: columns.month,
+            day: columns.day,
+            hour: local.hour,
+            minute: local.minute,
+            week: columns.week,
+            key: observation.key.clone(),
+            question: observation.question.clone(),
+            data_type: observation.value.data_type().to_string(),
+            value: observation.value.to_raw_value(),
+            matcheddate: observation.matcheddate,
+            source: observation.source.clone(),
+            importid: observation.importid.clone(),
+            timezone: local.timezone.name().to_string(),
+        }
+    }
+}
+
+/// The calendar columns of `raw_data` that derive from `matcheddate`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DayColumns {
+    pub yearmonth: i32,
+    pub yearweek: i32,
+    pub year: i16,
+    pub quarter: i16,
+    pub month: i16,
+    pub day: i16,
+    pub week: i16,
+}
+
+impl DayColumns {
+    /// Weeks are ISO weeks.
+    pub fn of(date: NaiveDate) -> DayColumns {
+        let week = date.iso_week().week() as i32;
+        DayColumns {
+            yearmonth: date.year() * 100 + date.month() as i32,
+            yearweek: date.year() * 100 + week,
+            year: date.year() as i16,
+            quarter: ((date.month() - 1) / 3 + 1) as i16,
+            month: date.month() as i16,
+            day: date.day() as i16,
+            week: week as i16,
+        }
+    }
+}
+
+/// Writes observations into `raw_data` in one transaction. Rows are unique per
+/// (source, key, timestamp), so re-importing a date range updates the values
+/// instead of duplicating them; the overwritten rows are copied to
+/// `raw_data_history`. Rows whose value, question, type and day are unchanged
+/// are left alone, importid included, so the scheduler's hourly re-reads of
+/// the last day don't pile up identical versions. Observations that already have a row without an
+/// importid, or a row the WHOOP script wrote for the same key and day, are
+/// skipped. Returns the number of rows written.
+pub async fn upsert_observations(db: &Db, observations: &[Observation]) -> Result<u64, sqlx::Error> {
+    let importedat: NaiveDateTime = Utc::now().naive_utc();
+    let clock = DayClock::load(db).await?;
+    let mut tx = db.begin().await?;
+    let legacy = legacy_rows(&mut tx, observations).await?;
+    let mut written = 0;
+
+    for record in observations.iter().map(|observation| RawDataRecord::from_observation(observation, &clock)) {
+        if legacy.contains(&(record.source.clone(), record.key.clone(), record.timestamp)) {
+            continue;
+        }
+        // Keep the row this import overwrites so the import can be rolled back.
+        sqlx::query(
+            "INSERT INTO raw_data_history (
+                timestamp, yearmonth, yearweek, year, quarter, month, day,
+                hour, minute, week, key, question, type, value, matcheddate,
+                source, importedat, importid, timezone, replaced_by, replaced_at
+            )
+            SELECT timestamp, yearmonth, yearweek, year, quarter, month, day,
+                hour, minute, week, key, question, type, value, matcheddate,
+                source, importedat, importid, timezone, $4, $5
+            FROM raw_data
+            WHERE source = $1 AND key = $2 AND timestamp = $3
+                AND importid IS NOT NULL AND importid <> $4
+                AND (value, question, type, matcheddate) IS DISTINCT FROM ($6, $7, $8, $9)",
+        )
+        .bind(&record.source)
+        .bind(&record.key)
+        .bind(record.timestamp)
+        .bind(&record.importid)
+        .bind(importedat)
+        .bind(&record.value)
+        .bind(&record.question)
+        .bind(&record.data_type)
+        .bind(record.matcheddate)
+        .execute(&mut tx)
+        .await?;
+
+        let result = sqlx::query(
+            "INSERT INTO raw_data (
+                timestamp, yearmonth, yearweek, year, quarter, month, day,
+                hour, minute, week, key, question, type, value, matcheddate,
+                source, importedat, importid, timezone
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19)
+            ON CONFLICT (source, key, timestamp) WHERE importid IS NOT NULL
+            DO UPDATE SET value = EXCLUDED.value, question = EXCLUDED.question, type = EXCLUDED.type,
+                yearmonth = EXCLUDED.yearmonth, yearweek = EXCLUDED.yearweek, year = EXCLUDED.year,
+                quarter = EXCLUDED.quarter, month = EXCLUDED.month, day = EXCLUDED.day,
+                hour = EXCLUDED.hour, minute = EXCLUDED.minute, week = EXCLUDED.week,
+                matcheddate = EXCLUDED.matcheddate, importedat = EXCLUDED.importedat,
+                importid = EXCLUDED.importid, timezone = EXCLUDED.timezone
+            WHERE (raw_data.value, raw_data.question, raw_data.type, raw_data.matcheddate)
+                IS DISTINCT FROM (EXCLUDED.value, EXCLUDED.question, EXCLUDED.type, EXCLUDED.matcheddate)",
+        )
+        .bind(record.timestamp)
+        .bind(record.yearmonth)
+        .bind(record.yearweek)
+        .bind(record.year)
+        .bind(record.quarter)
+        .bind(record.month)
+        .bind(record.day)
+        .bind(record.hour)
+        .bind(record.minute)
+        .bind(record.week)
+        .bind(&record.key)
+        .bind(&record.question)
+        .bind(&record.data_type)
+        .bind(&record.value)
+        .bind(record.matcheddate)
+        .bind(&record.source)
+        .bind(importedat)
+        .bind(&record.importid)
+        .bind(&record.timezone)
+        .execute(&mut tx)
+        .await?;
+        written += result.rows_affected();
+    }
+
+    tx.commit().await?;
+    Ok(written)
+}
+
+/// Sources that a script wrote before the collector took over, with an
+/// importid of its own per run and timestamps of its own.
+const SCRIPT_SOURCES: &[&str] = &["whoop"];
+
+/// (source, key, timestamp) of the observations that the scripts that came
+/// before the collector already stored: rows without an importid at the same
+/// timestamp, and rows of a `SCRIPT_SOURCES` source on the same day. The
+/// collector sets `timezone` on every row it writes, and the scripts never
+/// do, which tells their rows apart from the collector's whatever their
+/// importid. The unique index can't catch either, so these would otherwise
+/// be duplicated or overwritten.
+async fn legacy_rows(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    observations: &[Observation],
+) -> Result<HashSet<(String, String, i64)>, sqlx::Error> {
+    let sources: Vec<&str> = observations.iter().map(|o| o.source.as_str()).collect();
+    let keys: Vec<&str> = observations.iter().map(|o| o.key.as_str()).collect();
+    let timestamps: Vec<i64> = observations.iter().map(|o| o.timestamp).collect();
+    let matcheddates: Vec<NaiveDate> = observations.iter().map(|o| o.matcheddate).collect();
+    let rows: Vec<(String, String, i64)> = sqlx::query_as(
+        "SELECT DISTINCT o.source, o.key, o.timestamp FROM raw_data r
+        JOIN UNNEST($1::text[], $2::text[], $3::bigint[], $4::date[]) AS o(source, key, timestamp, matcheddate)
+            ON r.source = o.source AND r.key = o.key
+        WHERE (r.importid IS NULL AND r.timestamp = o.timestamp)
+            OR (r.source = ANY($5) AND r.timezone IS NULL AND r.matcheddate = o.matcheddate)",
+    )
+    .bind(&sources)
+    .bind(&keys)
+    .bind(&timestamps)
+    .bind(&matcheddates)
+    .bind(SCRIPT_SOURCES)
+    .fetch_all(&mut *tx)
+    .await?;
+    Ok(rows.into_iter().collect())
+}
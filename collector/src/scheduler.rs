@@ -0,0 +1,126 @@
+use crate::db::Db;
+use crate::integration_registry::{self, IntegrationRegistry, SharedIntegration};
+use crate::raw_data;
+use crate::timezones::{self, DayClock, RecomputeScope};
+use actix_web::rt::time;
+use chrono::{Duration, NaiveDate, Utc};
+use std::time::Duration as StdDuration;
+
+const DEFAULT_SYNC_INTERVAL_MINUTES: u64 = 60;
+const DEFAULT_BACKFILL_DAYS: i64 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SchedulerConfig {
+    pub interval: StdDuration,
+    /// How far back the first sync of an integration reaches.
+    pub backfill_days: i64,
+}
+
+impl SchedulerConfig {
+    /// Reads `SYNC_INTERVAL_MINUTES` and `SYNC_BACKFILL_DAYS`. An interval of 0
+    /// disables the scheduler.
+    pub fn from_env() -> Option<SchedulerConfig> {
+        let minutes = env_or("SYNC_INTERVAL_MINUTES", DEFAULT_SYNC_INTERVAL_MINUTES);
+        if minutes == 0 {
+            return None;
+        }
+        Some(SchedulerConfig {
+            interval: StdDuration::from_secs(minutes * 60),
+            backfill_days: env_or("SYNC_BACKFILL_DAYS", DEFAULT_BACKFILL_DAYS),
+        })
+    }
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+/// The days the next run should fetch. The high-water mark day is fetched again
+/// because it was usually still in progress during the previous run; the
+/// upsert in `raw_data` keeps that idempotent.
+pub fn next_range(synced_until: Option<NaiveDate>, today: NaiveDate, backfill_days: i64) -> (NaiveDate, NaiveDate) {
+    let start = match synced_until {
+        Some(date) if date <= today => date,
+        Some(_) => today,
+        None => today - Duration::days(backfill_days),
+    };
+    (start, today)
+}
+
+/// Runs every registered integration once per `config.interval`, forever.
+pub async fn run(registry: IntegrationRegistry, db: Db, config: SchedulerConfig) {
+    let mut interval = time::interval(config.interval);
+    loop {
+        interval.tick().await;
+        for integration in registry.all().into_iter().filter(|i| i.is_scheduled()) {
+            if let Err(e) = sync_integration(&db, integration.clone(), config.backfill_days).await {
+                println!("ERROR - sync of {} failed. Cause {}", integration.name(), e);
+                let _ = record_failure(&db, &integration.name(), &e).await;
+            }
+        }
+        if let Err(e) = match_new_rows(&db).await {
+            println!("ERROR - matching new rows to their day failed. Cause {}", e);
+        }
+    }
+}
+
+async fn sync_integration(db: &Db, integration: SharedIntegration, backfill_days: i64) -> Result<(), String> {
+    let name = integration.name();
+    let synced_until = get_synced_until(db, &name).await.map_err(|e| e.to_string())?;
+    let (start_date, end_date) = next_range(synced_until, Utc::now().date_naive(), backfill_days);
+
+    let observations = integration_registry::run_blocking(integration, start_date, end_date)
+        .await
+        .map_err(|e| e.to_string())?;
+    let rows = raw_data::upsert_observations(db, &observations).await.map_err(|e| e.to_string())?;
+    println!("Synced {} from {} to {} ({} rows)", name, start_date, end_date, rows);
+
+    save_synced_until(db, &name, end_date).await.map_err(|e| e.to_string())
+}
+
+/// Gives rows written without a day, such as Telegram replies, their local day.
+async fn match_new_rows(db: &Db) -> Result<(), sqlx::Error> {
+    let clock = DayClock::load(db).await?;
+    let result = timezones::recompute(db, &clock, &RecomputeScope::unmatched()).await?;
+    if result.updated > 0 {
+        println!("Matched {} new rows to their day", result.updated);
+    }
+    Ok(())
+}
+
+// region:    Sync State
+async fn get_synced_until(db: &Db, integration: &str) -> Result<Option<NaiveDate>, sqlx::Error> {
+    let synced_until: Option<(Option<NaiveDate>,)> =
+        sqlx::query_as("SELECT synced_until FROM integration_sync WHERE integration = $1")
+            .bind(integration)
+            .fetch_optional(db)
+            .await?;
+    Ok(synced_until.and_then(|(date,)| date))
+}
+
+async fn save_synced_until(db: &Db, integration: &str, synced_until: NaiveDate) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO integration_sync (integration, synced_until, last_run, last_error) VALUES ($1, $2, $3, NULL)
+        ON CONFLICT (integration) DO UPDATE SET synced_until = $2, last_run = $3, last_error = NULL",
+    )
+    .bind(integration)
+    .bind(synced_until)
+    .bind(Utc::now().timestamp_millis())
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+async fn record_failure(db: &Db, integration: &str, error: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO integration_sync (integration, last_run, last_error) VALUES ($1, $2, $3)
+        ON CONFLICT (integration) DO UPDATE SET last_run = $2, last_error = $3",
+    )
+    .bind(integration)
+    .bind(Utc::now().timestamp_millis())
+    .bind(error)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+// endregion: Sync State
@@ -0,0 +1,279 @@
+use crate::db::Db;
+use crate::raw_data::DayColumns;
+use chrono::{Duration, NaiveDate, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use serde::Serialize;
+
+/// Rows updated per statement by the recompute job.
+const RECOMPUTE_BATCH: i64 = 1000;
+
+/// Sources whose rows are matched to the local day of their timestamp: the
+/// telegram bot and the viz backend's write API. Other integrations report
+/// the day a value belongs to themselves.
+pub const LOCAL_DAY_SOURCES: &[&str] = &["telegram", "viz"];
+
+/// The zone used outside of the timezone history, and the hour days start at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DayClockConfig {
+    pub default_zone: Tz,
+    /// Entries before this local hour belong to the previous day, so a reply
+    /// at 1am still counts for the evening before.
+    pub day_boundary_hour: u32,
+}
+
+impl Default for DayClockConfig {
+    fn default() -> DayClockConfig {
+        DayClockConfig { default_zone: Tz::UTC, day_boundary_hour: 0 }
+    }
+}
+
+impl DayClockConfig {
+    /// Reads `TIMEZONE` (an IANA name, default `UTC`) and `DAY_BOUNDARY_HOUR`
+    /// (0 to 23, default 0). Invalid values fall back to the defaults.
+    pub fn from_env() -> DayClockConfig {
+        let default_zone = match std::env::var("TIMEZONE") {
+            Ok(name) => name.parse().unwrap_or_else(|e| {
+                println!("Invalid TIMEZONE, using UTC. Cause {}", e);
+                Tz::UTC
+            }),
+            Err(_) => Tz::UTC,
+        };
+        let day_boundary_hour = std::env::var("DAY_BOUNDARY_HOUR")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|hour| *hour < 24)
+            .unwrap_or(0);
+        DayClockConfig { default_zone, day_boundary_hour }
+    }
+}
+
+/// One entry of `timezone_history`.
+#[derive(Debug, Clone, PartialEq, Serialize, sqlx::FromRow)]
+pub struct TimezoneChange {
+    pub id: i32,
+    /// Milliseconds since the epoch, like `raw_data.timestamp`.
+    pub starts_at: i64,
+    pub timezone: String,
+}
+
+/// A timestamp as it was on your wall clock.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LocalMoment {
+    /// The day the moment counts for, after the day boundary.
+    pub matcheddate: NaiveDate,
+    pub hour: i16,
+    pub minute: i16,
+    pub timezone: Tz,
+}
+
+/// Maps timestamps to the local day and time in the zone you were in at that
+/// moment.
+#[derive(Debug, Clone, Default)]
+pub struct DayClock {
+    config: DayClockConfig,
+    /// (starts_at, zone), oldest first.
+    changes: Vec<(i64, Tz)>,
+}
+
+impl DayClock {
+    /// History entries with unknown zone names are skipped.
+    pub fn new(config: DayClockConfig, history: &[TimezoneChange]) -> DayClock {
+        let mut changes: Vec<(i64, Tz)> = history.iter()
+            .filter_map(|change| change.timezone.parse().ok().map(|zone| (change.starts_at, zone)))
+            .collect();
+        changes.sort_by_key(|(starts_at, _)| *starts_at);
+        DayClock { config, changes }
+    }
+
+    /// The environment's config with the stored timezone history.
+    pub async fn load(db: &Db) -> Result<DayClock, sqlx::Error> {
+        let history = list_changes(db).await?;
+        Ok(DayClock::new(DayClockConfig::from_env(), &history))
+    }
+
+    pub fn config(&self) -> DayClockConfig {
+        self.config
+    }
+
+    pub fn zone_at(&self, timestamp: i64) -> Tz {
+        let index = self.changes.partition_point(|(starts_at, _)| *starts_at <= timestamp);
+        match index {
+            0 => self.config.default_zone,
+            _ => self.changes[index - 1].1,
+        }
+    }
+
+    pub fn localize(&self, timestamp: i64) -> LocalMoment {
+        let timezone = self.zone_at(timestamp);
+        let utc = Utc.timestamp_millis_opt(timestamp).single().unwrap_or_default();
+        let local = utc.with_timezone(&timezone).naive_local();
+        let shifted = local - Duration::hours(self.config.day_boundary_hour as i64);
+        LocalMoment {
+            matcheddate: shifted.date(),
+            hour: local.hour() as i16,
+            minute: local.minute() as i16,
+            timezone,
+        }
+    }
+}
+
+/// The timezone history, oldest first.
+pub async fn list_changes(db: &Db) -> Result<Vec<TimezoneChange>, sqlx::Error> {
+    sqlx::query_as("SELECT id, starts_at, timezone FROM timezone_history ORDER BY starts_at")
+        .fetch_all(db)
+        .await
+}
+
+/// Records a move to `timezone`; a change at the same moment is replaced.
+pub async fn save_change(db: &Db, starts_at: i64, timezone: Tz) -> Result<TimezoneChange, sqlx::Error> {
+    sqlx::query_as(
+        "INSERT INTO timezone_history (starts_at, timezone) VALUES ($1, $2)
+        ON CONFLICT (starts_at) DO UPDATE SET timezone = EXCLUDED.timezone
+        RETURNING id, starts_at, timezone",
+    )
+    .bind(starts_at)
+    .bind(timezone.name())
+    .fetch_one(db)
+    .await
+}
+
+pub async fn delete_change(db: &Db, id: i32) -> Result<Option<TimezoneChange>, sqlx::Error> {
+    sqlx::query_as("DELETE FROM timezone_history WHERE id = $1 RETURNING id, starts_at, timezone")
+        .bind(id)
+        .fetch_optional(db)
+        .await
+}
+
+/// Which rows the recompute job rematches.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecomputeScope {
+    /// Rows of these sources are rematched even when they already have a day.
+    pub sources: Vec<String>,
+    /// Only rows from this timestamp on.
+    pub from: Option<i64>,
+}
+
+impl Default for RecomputeScope {
+    fn default() -> RecomputeScope {
+        RecomputeScope {
+            sources: LOCAL_DAY_SOURCES.iter().map(|s| s.to_string()).collect(),
+            from: None,
+        }
+    }
+}
+
+impl RecomputeScope {
+    /// Only the rows that have no day yet, such as new Telegram replies.
+    pub fn unmatched() -> RecomputeScope {
+        RecomputeScope { sources: Vec::new(), from: None }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct RecomputeResult {
+    pub scanned: u64,
+    pub updated: u64,
+}
+
+/// Recomputes `matcheddate`, the calendar columns, hour and minute of stored
+/// rows in the zone of their moment: rows of the scope's sources, and rows
+/// that were never matched to a day. Works through the table in batches of
+/// separate transactions, so it can run on a live database; rows that
+/// already match are left alone.
+pub async fn recompute(db: &Db, clock: &DayClock, scope: &RecomputeScope) -> Result<RecomputeResult, sqlx::Error> {
+    let mut result = RecomputeResult::default();
+    let mut after = 0;
+    loop {
+        let rows: Vec<(i32, i64)> = sqlx::query_as(
+            "SELECT id, timestamp FROM raw_data
+            WHERE id > $1 AND timestamp IS NOT NULL
+                AND (matcheddate IS NULL OR source = ANY($2))
+                AND ($3::bigint IS NULL OR timestamp >= $3)
+            ORDER BY id
+            LIMIT $4",
+        )
+        .bind(after)
+        .bind(&scope.sources)
+        .bind(scope.from)
+        .bind(RECOMPUTE_BATCH)
+        .fetch_all(db)
+        .await?;
+        let last = match rows.last() {
+            Some((id, _)) => *id,
+            None => break,
+        };
+
+        let mut batch = RecomputeBatch::default();
+        for (id, timestamp) in &rows {
+            batch.push(*id, clock.localize(*timestamp));
+        }
+        result.scanned += rows.len() as u64;
+        result.updated += batch.write(db).await?;
+        after = last;
+    }
+    Ok(result)
+}
+
+/// Columns of the rows in one recompute statement.
+#[derive(Default)]
+struct RecomputeBatch {
+    ids: Vec<i32>,
+    matcheddates: Vec<NaiveDate>,
+    yearmonths: Vec<i32>,
+    yearweeks: Vec<i32>,
+    years: Vec<i16>,
+    quarters: Vec<i16>,
+    months: Vec<i16>,
+    days: Vec<i16>,
+    hours: Vec<i16>,
+    minutes: Vec<i16>,
+    weeks: Vec<i16>,
+    timezones: Vec<String>,
+}
+
+impl RecomputeBatch {
+    fn push(&mut self, id: i32, moment: LocalMoment) {
+        let columns = DayColumns::of(moment.matcheddate);
+        self.ids.push(id);
+        self.matcheddates.push(moment.matcheddate);
+        self.yearmonths.push(columns.yearmonth);
+        self.yearweeks.push(columns.yearweek);
+        self.years.push(columns.year);
+        self.quarters.push(columns.quarter);
+        self.months.push(columns.month);
+        self.days.push(columns.day);
+        self.hours.push(moment.hour);
+        self.minutes.push(moment.minute);
+        self.weeks.push(columns.week);
+        self.timezones.push(moment.timezone.name().to_string());
+    }
+
+    async fn write(&self, db: &Db) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE raw_data SET matcheddate = u.matcheddate, yearmonth = u.yearmonth,
+                yearweek = u.yearweek, year = u.year, quarter = u.quarter, month = u.month,
+                day = u.day, hour = u.hour, minute = u.minute, week = u.week, timezone = u.timezone
+            FROM UNNEST($1::int[], $2::date[], $3::int[], $4::int[], $5::smallint[], $6::smallint[],
+                $7::smallint[], $8::smallint[], $9::smallint[], $10::smallint[], $11::smallint[], $12::text[])
+                AS u(id, matcheddate, yearmonth, yearweek, year, quarter, month, day, hour, minute, week, timezone)
+            WHERE raw_data.id = u.id
+                AND (raw_data.matcheddate, raw_data.hour, raw_data.minute, raw_data.timezone, raw_data.week)
+                    IS DISTINCT FROM (u.matcheddate, u.hour, u.minute, u.timezone, u.week)",
+        )
+        .bind(&self.ids)
+        .bind(&self.matcheddates)
+        .bind(&self.yearmonths)
+        .bind(&self.yearweeks)
+        .bind(&self.years)
+        .bind(&self.quarters)
+        .bind(&self.months)
+        .bind(&self.days)
+        .bind(&self.hours)
+        .bind(&self.minutes)
+        .bind(&self.weeks)
+        .bind(&self.timezones)
+        .execute(db)
+        .await?;
+        Ok(result.rows_affected())
+    }
+}
@@ -0,0 +1,91 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+
+const NONCE_LEN: usize = 12;
+
+/// OAuth2 credentials of one integration.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OAuthToken {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    /// Expiry in milliseconds since the epoch, when the provider sends one.
+    pub expires_at: Option<i64>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum TokenStoreError {
+    #[error("Token store not configured - {0}")]
+    NotConfigured(&'static str),
+
+    #[error("Token for {0} cannot be decrypted")]
+    Corrupt(String),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// Keeps one AES-256-GCM encrypted token file per integration. The key is the
+/// SHA-256 of a passphrase so it can be set as a plain environment variable.
+pub struct EncryptedFileTokenStore {
+    dir: PathBuf,
+    cipher: Aes256Gcm,
+}
+
+impl EncryptedFileTokenStore {
+    pub fn new(dir: PathBuf, passphrase: &str) -> EncryptedFileTokenStore {
+        let key = Sha256::digest(passphrase.as_bytes());
+        EncryptedFileTokenStore {
+            dir,
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)),
+        }
+    }
+
+    /// Reads `TOKEN_STORE_DIR` (default `tokens`) and `TOKEN_ENCRYPTION_KEY`.
+    pub fn from_env() -> Result<EncryptedFileTokenStore, TokenStoreError> {
+        let passphrase = std::env::var("TOKEN_ENCRYPTION_KEY")
+            .map_err(|_| TokenStoreError::NotConfigured("TOKEN_ENCRYPTION_KEY is not set"))?;
+        let dir = std::env::var("TOKEN_STORE_DIR").unwrap_or_else(|_| "tokens".to_string());
+        Ok(EncryptedFileTokenStore::new(PathBuf::from(dir), &passphrase))
+    }
+
+    pub fn load(&self, integration: &str) -> Result<Option<OAuthToken>, TokenStoreError> {
+        let path = self.path(integration);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let bytes = fs::read(path)?;
+        if bytes.len() < NONCE_LEN {
+            return Err(TokenStoreError::Corrupt(integration.to_string()));
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let plaintext = self.cipher.decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| TokenStoreError::Corrupt(integration.to_string()))?;
+        let token = serde_json::from_slice(&plaintext)
+            .map_err(|_| TokenStoreError::Corrupt(integration.to_string()))?;
+        Ok(Some(token))
+    }
+
+    pub fn save(&self, integration: &str, token: &OAuthToken) -> Result<(), TokenStoreError> {
+        let plaintext = serde_json::to_vec(token).map_err(std::io::Error::from)?;
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self.cipher.encrypt(&nonce, plaintext.as_ref())
+            .map_err(|_| TokenStoreError::Corrupt(integration.to_string()))?;
+
+        fs::create_dir_all(&self.dir)?;
+        let mut bytes = nonce.to_vec();
+        bytes.extend(ciphertext);
+        // Write then rename so a crash never leaves a half-written token behind.
+        let tmp = self.path(integration).with_extension("tmp");
+        fs::write(&tmp, bytes)?;
+        fs::rename(tmp, self.path(integration))?;
+        Ok(())
+    }
+
+    fn path(&self, integration: &str) -> PathBuf {
+        self.dir.join(format!("{}.token", integration.to_lowercase()))
+    }
+}
@@ -0,0 +1,80 @@
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+const UPLOAD_EXTENSION: &str = "upload";
+
+/// Files uploaded to push-based integrations, kept as
+/// `<root>/<integration>/<importid>.upload` so an import can be re-run.
+#[derive(Debug, Clone)]
+pub struct UploadStore {
+    root: PathBuf,
+}
+
+impl UploadStore {
+    pub fn new(root: PathBuf) -> UploadStore {
+        UploadStore { root }
+    }
+
+    /// Reads `UPLOAD_DIR` (default `uploads`).
+    pub fn from_env() -> UploadStore {
+        let root = std::env::var("UPLOAD_DIR").unwrap_or_else(|_| "uploads".to_string());
+        UploadStore::new(PathBuf::from(root))
+    }
+
+    pub fn dir(&self, integration: &str) -> PathBuf {
+        self.root.join(integration.to_lowercase())
+    }
+
+    pub fn path(&self, integration: &str, importid: &str) -> PathBuf {
+        self.dir(integration).join(format!("{}.{}", importid, UPLOAD_EXTENSION))
+    }
+
+    pub fn create(&self, integration: &str, importid: &str) -> std::io::Result<UploadWriter> {
+        fs::create_dir_all(self.dir(integration))?;
+        let path = self.path(integration, importid);
+        let tmp = path.with_extension("part");
+        Ok(UploadWriter { file: fs::File::create(&tmp)?, tmp, path })
+    }
+
+    /// The most recent upload of an integration, as (importid, path).
+    pub fn latest(&self, integration: &str) -> Option<(String, PathBuf)> {
+        let entries = fs::read_dir(self.dir(integration)).ok()?;
+        entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().map(|e| e == UPLOAD_EXTENSION).unwrap_or(false))
+            .filter_map(|path| {
+                let modified = fs::metadata(&path).and_then(|m| m.modified()).unwrap_or(SystemTime::UNIX_EPOCH);
+                let importid = path.file_stem()?.to_string_lossy().to_string();
+                Some((modified, importid, path))
+            })
+            .max_by_key(|(modified, _, _)| *modified)
+            .map(|(_, importid, path)| (importid, path))
+    }
+}
+
+/// Receives an upload chunk by chunk; the file only appears under its final
+/// name once `finish` is called.
+pub struct UploadWriter {
+    file: fs::File,
+    tmp: PathBuf,
+    path: PathBuf,
+}
+
+impl UploadWriter {
+    pub fn write(&mut self, chunk: &[u8]) -> std::io::Result<()> {
+        self.file.write_all(chunk)
+    }
+
+    pub fn finish(self) -> std::io::Result<PathBuf> {
+        self.file.sync_all()?;
+        fs::rename(&self.tmp, &self.path)?;
+        Ok(self.path)
+    }
+
+    pub fn abort(self) {
+        let _ = fs::remove_file(&self.tmp);
+    }
+}
@@ -0,0 +1,213 @@
+use crate::base_integration::{new_import_id, AuthStatus, BaseIntegration, IntegrationError, Observation, ObservationValue};
+use crate::oauth::{Credentials, OAuthClient};
+use crate::token_store::EncryptedFileTokenStore;
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate};
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::Arc;
+
+const WHOOP_API_URL: &str = "https://api.prod.whoop.com";
+const WHOOP_AUTHORIZE_URL: &str = "https://api.prod.whoop.com/oauth/oauth2/auth";
+const WHOOP_TOKEN_URL: &str = "https://api.prod.whoop.com/oauth/oauth2/token";
+// `offline` is what makes WHOOP hand out a refresh token.
+const WHOOP_SCOPES: &[&str] = &["offline", "read:recovery", "read:cycles", "read:sleep", "read:workout", "read:profile"];
+const SOURCE: &str = "whoop";
+const PAGE_LIMIT: &str = "25";
+
+/// (json pointer, raw_data key, question) for one numeric field.
+type FieldMapping = (&'static str, &'static str, &'static str);
+
+/// Collection endpoints, the field each record is timestamped by and the fields
+/// we keep. Keys and questions match the rows written by `whoop/whoop_import.py`.
+const ENDPOINTS: &[(&str, &str, &[FieldMapping])] = &[
+    ("cycle", "start", &[
+        ("/score/strain", "whoopDayStrain", "Whoop Day Strain"),
+    ]),
+    ("recovery", "created_at", &[
+        ("/score/recovery_score", "whoopRecoveryScore", "Whoop Recovery Score"),
+        ("/score/resting_heart_rate", "whoopRHR", "Whoop Resting Heart Rate"),
+        ("/score/hrv_rmssd_milli", "whoopHRV", "Whoop Heart Rate Variability"),
+        ("/score/skin_temp_celsius", "whoopSkinTemp", "Whoop Skin Temperature"),
+        ("/score/spo2_percentage", "whoopSpO2", "Whoop Blood Oxygen (SpO2)"),
+    ]),
+    ("activity/sleep", "start", &[
+        ("/score/sleep_performance_percentage", "whoopSleepPerformance", "Whoop Sleep Performance"),
+        ("/score/sleep_efficiency_percentage", "whoopSleepEfficiency", "Whoop Sleep Efficiency"),
+        ("/score/respiratory_rate", "whoopRespiratoryRate", "Whoop Respiratory Rate"),
+        ("/score/sleep_consistency_percentage", "whoopSleepConsistency", "Whoop Sleep Consistency"),
+    ]),
+    ("activity/workout", "start", &[
+        ("/score/strain", "whoopStrain", "Whoop Strain Score"),
+        ("/score/average_heart_rate", "whoopAvgHeartRate", "Whoop Average Heart Rate"),
+        ("/score/max_heart_rate", "whoopMaxHeartRate", "Whoop Max Heart Rate"),
+        ("/score/kilojoule", "whoopKilojoules", "Whoop Kilojoules"),
+        ("/score/distance_meter", "whoopDistance", "Whoop Distance (meters)"),
+    ]),
+];
+
+#[derive(Debug, Deserialize)]
+struct WhoopPage {
+    records: Vec<Value>,
+    next_token: Option<String>,
+}
+
+pub struct Whoop {
+    base_url: String,
+    credentials: Credentials,
+    agent: ureq::Agent,
+}
+
+impl Whoop {
+    /// Uses the OAuth2 app from `WHOOP_CLIENT_ID`/`WHOOP_CLIENT_SECRET` when one
+    /// is configured together with a token store, else `WHOOP_ACCESS_TOKEN`.
+    pub fn new(store: Option<Arc<EncryptedFileTokenStore>>) -> Whoop {
+        match Credentials::from_env("Whoop", WHOOP_AUTHORIZE_URL, WHOOP_TOKEN_URL, WHOOP_SCOPES, store) {
+            Credentials::AccessToken(token) => Whoop::with_base_url(WHOOP_API_URL.to_string(), token),
+            Credentials::OAuth(oauth) => Whoop::with_oauth(WHOOP_API_URL.to_string(), oauth),
+        }
+    }
+
+    pub fn with_base_url(base_url: String, access_token: String) -> Whoop {
+        Whoop {
+            base_url,
+            credentials: Credentials::AccessToken(access_token),
+            agent: ureq::Agent::new(),
+        }
+    }
+
+    pub fn with_oauth(base_url: String, oauth: OAuthClient) -> Whoop {
+        Whoop {
+            base_url,
+            credentials: Credentials::OAuth(oauth),
+            agent: ureq::Agent::new(),
+        }
+    }
+
+    fn fetch_collection(&self, endpoint: &str, start: &str, end: &str) -> Result<Vec<Value>, IntegrationError> {
+        let url = format!("{}/developer/v2/{}", self.base_url, endpoint);
+        let bearer = self.credentials.bearer(&self.name())?;
+        let mut records = Vec::new();
+        let mut next_token: Option<String> = None;
+
+        loop {
+            let mut request = self.agent.get(&url)
+                .set("Authorization", &bearer)
+                .query("start", start)
+                .query("end", end)
+                .query("limit", PAGE_LIMIT);
+            if let Some(token) = &next_token {
+                request = request.query("nextToken", token);
+            }
+
+            let page: WhoopPage = request.call()
+                .map_err(|e| match e {
+                    ureq::Error::Status(401, _) | ureq::Error::Status(403, _) => {
+                        IntegrationError::Unauthorized(self.name(), AuthStatus::Expired)
+                    }
+                    other => IntegrationError::Http(format!("Whoop {} - {}", endpoint, other)),
+                })?
+                .into_json()
+                .map_err(|e| IntegrationError::InvalidResponse(format!("Whoop {} - {}", endpoint, e)))?;
+            records.extend(page.records);
+
+            match page.next_token {
+                Some(token) if !token.is_empty() => next_token = Some(token),
+                _ => break,
+            }
+        }
+        Ok(records)
+    }
+}
+
+impl BaseIntegration for Whoop {
+
+    fn name(&self) -> String {
+        "Whoop".to_string()
+    }
+
+    fn authorize(&self) -> AuthStatus {
+        let bearer = match self.credentials.bearer(&self.name()) {
+            Ok(bearer) => bearer,
+            Err(IntegrationError::Unauthorized(_, status)) => return status,
+            Err(e) => return AuthStatus::Error(e.to_string()),
+        };
+        let url = format!("{}/developer/v2/user/profile/basic", self.base_url);
+        match self.agent.get(&url).set("Authorization", &bearer).call() {
+            Ok(_) => AuthStatus::Authorized,
+            Err(ureq::Error::Status(401, _)) | Err(ureq::Error::Status(403, _)) => AuthStatus::Expired,
+            Err(e) => AuthStatus::Error(e.to_string()),
+        }
+    }
+
+    fn get_data(&self, start_date: NaiveDate, end_date: NaiveDate) -> Result<Vec<Observation>, IntegrationError> {
+        if start_date > end_date {
+            return Err(IntegrationError::InvalidDateRange(start_date, end_date));
+        }
+        // WHOOP filters on datetimes with an exclusive end.
+        let start = format!("{}T00:00:00.000Z", start_date);
+        let end = format!("{}T00:00:00.000Z", end_date + Duration::days(1));
+        let importid = new_import_id();
+
+        let mut observations = Vec::new();
+        for (endpoint, timestamp_field, fields) in ENDPOINTS {
+            for record in self.fetch_collection(endpoint, &start, &end)? {
+                observations.extend(map_record(&record, timestamp_field, fields, &importid)?);
+            }
+        }
+        Ok(observations)
+    }
+
+    fn oauth(&self) -> Option<&OAuthClient> {
+        self.credentials.oauth()
+    }
+}
+
+fn map_record(
+    record: &Value,
+    timestamp_field: &str,
+    fields: &[FieldMapping],
+    importid: &str,
+) -> Result<Vec<Observation>, IntegrationError> {
+    // Records still being processed by WHOOP have no score yet.
+    if record["score_state"].as_str() != Some("SCORED") {
+        return Ok(Vec::new());
+    }
+    let ts = record[timestamp_field].as_str()
+        .ok_or_else(|| IntegrationError::InvalidResponse(format!("Whoop record without {}", timestamp_field)))?;
+    let datetime = DateTime::parse_from_rfc3339(ts)
+        .map_err(|e| IntegrationError::InvalidResponse(format!("Whoop {} '{}' - {}", timestamp_field, ts, e)))?;
+    let timestamp = datetime.timestamp_millis();
+    // Cycles, sleeps and workouts carry the offset they were recorded in, so a
+    // late-night workout lands on the local day rather than the UTC one.
+    let matcheddate = match record["timezone_offset"].as_str().and_then(parse_offset) {
+        Some(offset) => datetime.with_timezone(&offset).date_naive(),
+        None => datetime.date_naive(),
+    };
+
+    let observations = fields.iter()
+        .filter_map(|(pointer, key, question)| {
+            record.pointer(pointer)
+                .and_then(Value::as_f64)
+                .map(|v| Observation {
+                    key: key.to_string(),
+                    question: question.to_string(),
+                    value: ObservationValue::Number(v),
+                    timestamp,
+                    matcheddate,
+                    source: SOURCE.to_string(),
+                    importid: importid.to_string(),
+                })
+        })
+        .collect();
+    Ok(observations)
+}
+
+/// Parses WHOOP's `timezone_offset`, e.g. `+05:30` or `Z`.
+fn parse_offset(offset: &str) -> Option<FixedOffset> {
+    if offset == "Z" {
+        return FixedOffset::east_opt(0);
+    }
+    DateTime::parse_from_rfc3339(&format!("2000-01-01T00:00:00{}", offset))
+        .ok()
+        .map(|dt| *dt.offset())
+}
@@ -0,0 +1,107 @@
+#![allow(dead_code)]
+#[path = "../src/base_integration.rs"] mod base_integration;
+#[path = "../src/oauth.rs"] mod oauth;
+#[path = "../src/token_store.rs"] mod token_store;
+#[path = "../src/uploads.rs"] mod uploads;
+#[path = "../src/apple_health.rs"] mod apple_health;
+
+#[cfg(test)]
+mod tests {
+
+    use crate::apple_health::{parse_export, AppleHealth};
+    use crate::base_integration::{BaseIntegration, Observation, ObservationValue};
+    use crate::uploads::UploadStore;
+    use chrono::NaiveDate;
+    use std::io::Write;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    const EXPORT: &str = include_str!("fixtures/apple_health/export.xml");
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2023, 3, day).unwrap()
+    }
+
+    fn value(observations: &[Observation], key: &str, matcheddate: NaiveDate) -> Option<ObservationValue> {
+        observations.iter()
+            .find(|o| o.key == key && o.matcheddate == matcheddate)
+            .map(|o| o.value.clone())
+    }
+
+    #[test]
+    fn test_parse_export_aggregates_days() {
+        let observations = parse_export(EXPORT.as_bytes(), "import-1", None).unwrap();
+
+        // Phone 2000 vs Watch 1500 for the same walks: the larger total wins.
+        assert_eq!(value(&observations, "appleSteps", date(1)), Some(ObservationValue::Number(2000.0)));
+        assert_eq!(value(&observations, "appleSteps", date(2)), Some(ObservationValue::Number(4000.0)));
+        assert_eq!(value(&observations, "appleHeartRate", date(1)), Some(ObservationValue::Number(70.0)));
+        assert_eq!(value(&observations, "appleSleepHours", date(2)), Some(ObservationValue::Number(5.5)));
+        assert_eq!(value(&observations, "appleInBedHours", date(2)), Some(ObservationValue::Number(8.0)));
+        assert_eq!(value(&observations, "appleWorkoutMinutes", date(2)), Some(ObservationValue::Number(30.0)));
+        assert_eq!(value(&observations, "appleWorkoutEnergy", date(2)), Some(ObservationValue::Number(320.0)));
+        assert_eq!(value(&observations, "appleWorkoutType", date(2)), Some(ObservationValue::Text("Running".to_string())));
+        assert!(observations.iter().all(|o| o.source == "apple_health" && o.importid == "import-1"));
+        assert!(!observations.iter().any(|o| o.key.contains("Water")));
+    }
+
+    #[test]
+    fn test_daily_timestamps_are_stable_midnights() {
+        let observations = parse_export(EXPORT.as_bytes(), "import-1", None).unwrap();
+        let steps = observations.iter().find(|o| o.key == "appleSteps" && o.matcheddate == date(1)).unwrap();
+
+        assert_eq!(steps.timestamp, 1677628800000);
+    }
+
+    #[test]
+    fn test_parse_export_filters_range() {
+        let observations = parse_export(EXPORT.as_bytes(), "import-1", Some((date(2), date(2)))).unwrap();
+
+        assert!(observations.iter().all(|o| o.matcheddate == date(2)));
+        assert!(value(&observations, "appleSteps", date(2)).is_some());
+    }
+
+    #[test]
+    fn test_import_upload_reads_zip_archive() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("export.zip");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+        zip.start_file("apple_health_export/export_cda.xml", Default::default()).unwrap();
+        zip.write_all(b"<ClinicalDocument/>").unwrap();
+        zip.start_file("apple_health_export/export.xml", Default::default()).unwrap();
+        zip.write_all(EXPORT.as_bytes()).unwrap();
+        zip.finish().unwrap();
+
+        let integration = AppleHealth::new(Arc::new(UploadStore::new(dir.path().to_path_buf())));
+        let observations = integration.import_upload(&path, "import-2").unwrap();
+
+        assert_eq!(value(&observations, "appleSteps", date(1)), Some(ObservationValue::Number(2000.0)));
+    }
+
+    #[test]
+    fn test_get_data_reads_latest_upload() {
+        let dir = TempDir::new().unwrap();
+        let store = Arc::new(UploadStore::new(dir.path().to_path_buf()));
+        let integration = AppleHealth::new(store.clone());
+        assert!(integration.get_data(date(1), date(2)).unwrap().is_empty());
+        assert!(!integration.is_scheduled());
+
+        let mut writer = store.create("AppleHealth", "import-3").unwrap();
+        writer.write(EXPORT.as_bytes()).unwrap();
+        writer.finish().unwrap();
+
+        let observations = integration.get_data(date(1), date(1)).unwrap();
+        assert!(observations.iter().all(|o| o.importid == "import-3" && o.matcheddate == date(1)));
+        assert!(!observations.is_empty());
+    }
+
+    #[test]
+    fn test_import_upload_rejects_garbage() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("bad.upload");
+        std::fs::write(&path, "<HealthData><Record type=").unwrap();
+        let integration = AppleHealth::new(Arc::new(UploadStore::new(dir.path().to_path_buf())));
+
+        assert!(integration.import_upload(&path, "import-4").is_err());
+    }
+}
@@ -0,0 +1,143 @@
+#![allow(dead_code)]
+#[path = "../src/base_integration.rs"] mod base_integration;
+#[path = "../src/oauth.rs"] mod oauth;
+#[path = "../src/token_store.rs"] mod token_store;
+#[path = "../src/uploads.rs"] mod uploads;
+#[path = "../src/finance.rs"] mod finance;
+
+#[cfg(test)]
+mod tests {
+
+    use crate::base_integration::{BaseIntegration, IntegrationError, Observation, ObservationValue};
+    use crate::finance::{balance_key, parse_amount, parse_statement, statement_import_id, Finance, FinanceConfig};
+    use crate::uploads::UploadStore;
+    use chrono::NaiveDate;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    fn fixture(name: &str) -> Vec<u8> {
+        let path = format!("{}/tests/fixtures/finance/{}", env!("CARGO_MANIFEST_DIR"), name);
+        std::fs::read(path).unwrap()
+    }
+
+    fn config_path() -> PathBuf {
+        PathBuf::from(format!("{}/finance.sample.json", env!("CARGO_MANIFEST_DIR")))
+    }
+
+    fn config() -> FinanceConfig {
+        FinanceConfig::load(&config_path()).unwrap()
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap()
+    }
+
+    fn value(rows: &[Observation], key: &str, day: u32) -> Option<ObservationValue> {
+        rows.iter().find(|r| r.key == key && r.matcheddate == date(day)).map(|r| r.value.clone())
+    }
+
+    fn upload(store: &UploadStore, importid: &str, contents: &[u8]) -> PathBuf {
+        let mut writer = store.create("Finance", importid).unwrap();
+        writer.write(contents).unwrap();
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn test_parse_amount() {
+        assert_eq!(parse_amount("1,234.50"), Some(1234.5));
+        assert_eq!(parse_amount("(75.00)"), Some(-75.0));
+        assert_eq!(parse_amount("12.00 Dr"), Some(-12.0));
+        assert_eq!(parse_amount("₹ -20"), Some(-20.0));
+        assert_eq!(parse_amount(""), None);
+    }
+
+    #[test]
+    fn test_balance_key() {
+        assert_eq!(balance_key("HDFC Savings"), "financeBalanceHdfcSavings");
+        assert_eq!(balance_key("000123456789"), "financeBalance000123456789");
+    }
+
+    #[test]
+    fn test_csv_with_preamble_and_newest_first_rows() {
+        let statements = parse_statement(&fixture("hdfc.csv"), &config()).unwrap();
+
+        assert_eq!(statements[0].account, "HDFC Savings");
+        let balances: Vec<(NaiveDate, f64)> = statements[0].balances.clone().into_iter().collect();
+        assert_eq!(balances, vec![(date(1), 3000.0), (date(2), 12000.0), (date(3), 11500.0)]);
+    }
+
+    #[test]
+    fn test_csv_liability_accumulates_amounts() {
+        let statements = parse_statement(&fixture("amex.csv"), &config()).unwrap();
+
+        assert_eq!(statements[0].account, "Amex Card");
+        assert_eq!(statements[0].balances[&date(1)], -24.5);
+        assert_eq!(statements[0].balances[&date(3)].to_string(), "0");
+    }
+
+    #[test]
+    fn test_ofx_balances_are_rebuilt_from_ledger() {
+        let statements = parse_statement(&fixture("statement.ofx"), &config()).unwrap();
+
+        assert_eq!(statements[0].account, "Chase Checking");
+        let balances: Vec<(NaiveDate, f64)> = statements[0].balances.clone().into_iter().collect();
+        assert_eq!(balances, vec![(date(1), 900.0), (date(2), 1150.0), (date(3), 1150.0)]);
+    }
+
+    #[test]
+    fn test_unknown_csv_is_rejected() {
+        let result = parse_statement(b"When,What\n2024-03-01,x\n", &config());
+        assert!(matches!(result, Err(IntegrationError::InvalidUpload(_))), "{:?}", result);
+    }
+
+    #[test]
+    fn test_import_upload_adds_net_worth_across_statements() {
+        let dir = TempDir::new().unwrap();
+        let store = Arc::new(UploadStore::new(dir.path().to_path_buf()));
+        let finance = Finance::new(store.clone(), config_path());
+        upload(&store, "first", &fixture("hdfc.csv"));
+        let path = upload(&store, "second", &fixture("amex.csv"));
+
+        let rows = finance.import_upload(&path, "second").unwrap();
+
+        let importid = statement_import_id(&fixture("amex.csv"));
+        assert!(rows.iter().all(|r| r.source == "finance" && r.importid == importid));
+        assert!(!rows.iter().any(|r| r.key == "financeBalanceHdfcSavings"));
+        assert_eq!(value(&rows, "financeBalanceAmexCard", 1), Some(ObservationValue::Number(-24.5)));
+        assert_eq!(value(&rows, "financeNetWorth", 1), Some(ObservationValue::Number(2975.5)));
+        // the card balance is carried over the day without transactions
+        assert_eq!(value(&rows, "financeNetWorth", 2), Some(ObservationValue::Number(11975.5)));
+        assert_eq!(value(&rows, "financeNetWorth", 3), Some(ObservationValue::Number(11500.0)));
+    }
+
+    #[test]
+    fn test_reuploaded_statement_keeps_its_importid() {
+        let dir = TempDir::new().unwrap();
+        let store = Arc::new(UploadStore::new(dir.path().to_path_buf()));
+        let finance = Finance::new(store.clone(), config_path());
+        let first = upload(&store, "first", &fixture("hdfc.csv"));
+        let second = upload(&store, "second", &fixture("hdfc.csv"));
+
+        let first_rows = finance.import_upload(&first, "first").unwrap();
+        let second_rows = finance.import_upload(&second, "second").unwrap();
+
+        assert_eq!(first_rows, second_rows);
+    }
+
+    #[test]
+    fn test_get_data_filters_range() {
+        let dir = TempDir::new().unwrap();
+        let store = Arc::new(UploadStore::new(dir.path().to_path_buf()));
+        let finance = Finance::new(store.clone(), config_path());
+        upload(&store, "first", &fixture("hdfc.csv"));
+        upload(&store, "second", &fixture("statement.ofx"));
+
+        let rows = finance.get_data(date(2), date(2)).unwrap();
+
+        assert!(rows.iter().all(|r| r.matcheddate == date(2)));
+        assert_eq!(value(&rows, "financeBalanceChaseChecking", 2), Some(ObservationValue::Number(1150.0)));
+        assert_eq!(value(&rows, "financeNetWorth", 2), Some(ObservationValue::Number(13150.0)));
+        assert!(!finance.is_scheduled());
+    }
+}
@@ -0,0 +1,152 @@
+#![allow(dead_code)]
+#[path = "../src/base_integration.rs"] mod base_integration;
+#[path = "../src/oauth.rs"] mod oauth;
+#[path = "../src/token_store.rs"] mod token_store;
+#[path = "../src/db.rs"] mod db;
+#[path = "../src/integration_registry.rs"] mod integration_registry;
+#[path = "../src/raw_data.rs"] mod raw_data;
+#[path = "../src/timezones.rs"] mod timezones;
+#[path = "../src/uploads.rs"] mod uploads;
+#[path = "../src/imports.rs"] mod imports;
+#[path = "../src/api.rs"] mod api;
+
+use crate::base_integration::{AuthStatus, BaseIntegration, IntegrationError, Observation, ObservationValue};
+use chrono::NaiveDate;
+
+/// Returns one observation per requested day, or the configured error.
+struct FakeIntegration {
+    fail_with_unauthorized: bool,
+}
+
+impl BaseIntegration for FakeIntegration {
+    fn name(&self) -> String {
+        "Fake".to_string()
+    }
+
+    fn authorize(&self) -> AuthStatus {
+        if self.fail_with_unauthorized {
+            AuthStatus::Expired
+        } else {
+            AuthStatus::Authorized
+        }
+    }
+
+    fn get_data(&self, start_date: NaiveDate, end_date: NaiveDate) -> Result<Vec<Observation>, IntegrationError> {
+        if self.fail_with_unauthorized {
+            return Err(IntegrationError::Unauthorized(self.name(), AuthStatus::Expired));
+        }
+        Ok(start_date.iter_days()
+            .take_while(|d| *d <= end_date)
+            .map(|d| Observation {
+                key: "fakeSteps".to_string(),
+                question: "Fake Steps".to_string(),
+                value: ObservationValue::Number(1000.0),
+                timestamp: d.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis(),
+                matcheddate: d,
+                source: "fake".to_string(),
+                importid: "import-1".to_string(),
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::integration_registry::IntegrationRegistry;
+    use actix_web::{test as actix_test, web, App};
+    use serde_json::Value;
+
+    fn registry(fail_with_unauthorized: bool) -> IntegrationRegistry {
+        let mut registry = IntegrationRegistry::new();
+        registry.register(FakeIntegration { fail_with_unauthorized });
+        registry
+    }
+
+    async fn call(registry: IntegrationRegistry, uri: &str) -> (u16, Value) {
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(registry))
+                .service(api::get_data),
+        )
+        .await;
+        let resp = actix_test::call_service(&app, actix_test::TestRequest::get().uri(uri).to_request()).await;
+        let status = resp.status().as_u16();
+        let body = actix_test::read_body(resp).await;
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    #[test]
+    fn test_registry_lookup_is_case_insensitive() {
+        let registry = registry(false);
+        assert_eq!(registry.get("fake").unwrap().name(), "Fake");
+        assert_eq!(registry.get("FAKE").unwrap().name(), "Fake");
+        assert!(registry.get("oura").is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_get_data_dispatches_to_integration() {
+        let (status, body) = call(
+            registry(false),
+            "/api/v1/getData?integration_name=fake&start_date=2024-03-01&end_date=2024-03-03",
+        )
+        .await;
+
+        assert_eq!(status, 200);
+        assert_eq!(body["integration_name"], "fake");
+        assert_eq!(body["start_date"], "2024-03-01");
+        assert_eq!(body["end_date"], "2024-03-03");
+        assert_eq!(body["data"].as_array().unwrap().len(), 3);
+        assert_eq!(body["data"][0]["matcheddate"], "2024-03-01");
+        assert_eq!(body["data"][0]["value"], 1000.0);
+    }
+
+    #[actix_web::test]
+    async fn test_get_data_unknown_integration() {
+        let (status, body) = call(
+            registry(false),
+            "/api/v1/getData?integration_name=garmin&start_date=2024-03-01&end_date=2024-03-03",
+        )
+        .await;
+
+        assert_eq!(status, 404);
+        assert_eq!(body["errorMessage"], "Unknown integration 'garmin'");
+    }
+
+    #[actix_web::test]
+    async fn test_get_data_bad_dates() {
+        let (status, _) = call(
+            registry(false),
+            "/api/v1/getData?integration_name=fake&start_date=yesterday&end_date=2024-03-03",
+        )
+        .await;
+        assert_eq!(status, 400);
+
+        let (status, _) = call(registry(false), "/api/v1/getData?integration_name=fake").await;
+        assert_eq!(status, 400);
+    }
+
+    #[actix_web::test]
+    async fn test_get_data_reversed_range() {
+        let (status, body) = call(
+            registry(false),
+            "/api/v1/getData?integration_name=fake&start_date=2024-03-03&end_date=2024-03-01",
+        )
+        .await;
+
+        assert_eq!(status, 400);
+        assert_eq!(body["errorMessage"], "Invalid date range 2024-03-03 - 2024-03-01");
+    }
+
+    #[actix_web::test]
+    async fn test_get_data_integration_error() {
+        let (status, _) = call(
+            registry(true),
+            "/api/v1/getData?integration_name=fake&start_date=2024-03-01&end_date=2024-03-01",
+        )
+        .await;
+
+        assert_eq!(status, 401);
+    }
+}
@@ -0,0 +1,249 @@
+#![allow(dead_code)]
+#[path = "../src/base_integration.rs"] mod base_integration;
+#[path = "../src/oauth.rs"] mod oauth;
+#[path = "../src/token_store.rs"] mod token_store;
+#[path = "../src/db.rs"] mod db;
+#[path = "../src/integration_registry.rs"] mod integration_registry;
+#[path = "../src/raw_data.rs"] mod raw_data;
+#[path = "../src/timezones.rs"] mod timezones;
+#[path = "../src/oura.rs"] mod oura;
+#[path = "../src/uploads.rs"] mod uploads;
+#[path = "../src/imports.rs"] mod imports;
+#[path = "../src/api.rs"] mod api;
+
+#[cfg(test)]
+mod tests {
+
+    use crate::base_integration::{AuthStatus, BaseIntegration, IntegrationError};
+    use crate::integration_registry::IntegrationRegistry;
+    use crate::oauth::{OAuthClient, OAuthConfig, PendingAuthorizations};
+    use crate::oura::Oura;
+    use crate::token_store::{EncryptedFileTokenStore, OAuthToken, TokenStoreError};
+    use actix_web::{test as actix_test, web, App};
+    use chrono::Utc;
+    use mockito::{Matcher, Server, ServerGuard};
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    fn config(server: &ServerGuard) -> OAuthConfig {
+        OAuthConfig {
+            client_id: "client-1".to_string(),
+            client_secret: "secret-1".to_string(),
+            authorize_url: format!("{}/oauth/authorize", server.url()),
+            token_url: format!("{}/oauth/token", server.url()),
+            redirect_uri: "http://localhost:8080/api/v1/oauth/oura/callback".to_string(),
+            scopes: vec!["daily".to_string(), "personal".to_string()],
+        }
+    }
+
+    fn store(dir: &TempDir) -> Arc<EncryptedFileTokenStore> {
+        Arc::new(EncryptedFileTokenStore::new(dir.path().to_path_buf(), "passphrase"))
+    }
+
+    fn token(access_token: &str, refresh_token: Option<&str>, expires_in_ms: i64) -> OAuthToken {
+        OAuthToken {
+            access_token: access_token.to_string(),
+            refresh_token: refresh_token.map(str::to_string),
+            expires_at: Some(Utc::now().timestamp_millis() + expires_in_ms),
+        }
+    }
+
+    #[test]
+    fn test_token_store_roundtrip_is_encrypted() {
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir);
+        let saved = token("access-1", Some("refresh-1"), 3_600_000);
+
+        store.save("Oura", &saved).unwrap();
+
+        assert_eq!(store.load("Oura").unwrap(), Some(saved));
+        let raw = std::fs::read(dir.path().join("oura.token")).unwrap();
+        assert!(!String::from_utf8_lossy(&raw).contains("access-1"));
+
+        let wrong_key = EncryptedFileTokenStore::new(dir.path().to_path_buf(), "other");
+        assert!(matches!(wrong_key.load("Oura"), Err(TokenStoreError::Corrupt(_))));
+        assert_eq!(store.load("Whoop").unwrap(), None);
+    }
+
+    #[test]
+    fn test_authorize_url() {
+        let server = Server::new();
+        let dir = TempDir::new().unwrap();
+        let oauth = OAuthClient::new("Oura", config(&server), store(&dir));
+
+        let url = oauth.authorize_url("state-1").unwrap();
+
+        assert!(url.starts_with(&format!("{}/oauth/authorize?", server.url())));
+        assert!(url.contains("response_type=code"));
+        assert!(url.contains("client_id=client-1"));
+        assert!(url.contains("scope=daily+personal"));
+        assert!(url.contains("state=state-1"));
+        assert!(url.contains("redirect_uri=http%3A%2F%2Flocalhost%3A8080%2Fapi%2Fv1%2Foauth%2Foura%2Fcallback"));
+    }
+
+    #[test]
+    fn test_invalid_authorize_url_is_a_config_error() {
+        let server = Server::new();
+        let dir = TempDir::new().unwrap();
+        let config = OAuthConfig { authorize_url: "not a url".to_string(), ..config(&server) };
+        let oauth = OAuthClient::new("Oura", config, store(&dir));
+
+        let result = oauth.authorize_url("state-1");
+
+        assert!(matches!(result, Err(IntegrationError::InvalidConfig(_))), "{:?}", result);
+    }
+
+    #[test]
+    fn test_exchange_code_stores_tokens() {
+        let mut server = Server::new();
+        let token_endpoint = server.mock("POST", "/oauth/token")
+            .match_body(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("grant_type".into(), "authorization_code".into()),
+                Matcher::UrlEncoded("code".into(), "code-1".into()),
+                Matcher::UrlEncoded("client_id".into(), "client-1".into()),
+                Matcher::UrlEncoded("client_secret".into(), "secret-1".into()),
+            ]))
+            .with_body(r#"{"access_token":"access-1","refresh_token":"refresh-1","expires_in":86400,"token_type":"bearer"}"#)
+            .create();
+        let dir = TempDir::new().unwrap();
+        let oauth = OAuthClient::new("Oura", config(&server), store(&dir));
+        assert_eq!(oauth.status(), AuthStatus::MissingCredentials);
+
+        oauth.exchange_code("code-1").unwrap();
+
+        token_endpoint.assert();
+        assert_eq!(oauth.status(), AuthStatus::Authorized);
+        assert_eq!(oauth.access_token().unwrap(), "access-1");
+    }
+
+    #[test]
+    fn test_expired_token_is_refreshed_and_rotated() {
+        let mut server = Server::new();
+        let refresh = server.mock("POST", "/oauth/token")
+            .match_body(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("grant_type".into(), "refresh_token".into()),
+                Matcher::UrlEncoded("refresh_token".into(), "refresh-1".into()),
+            ]))
+            .with_body(r#"{"access_token":"access-2","refresh_token":"refresh-2","expires_in":86400}"#)
+            .expect(1)
+            .create();
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir);
+        store.save("Oura", &token("access-1", Some("refresh-1"), -1000)).unwrap();
+        let oauth = OAuthClient::new("Oura", config(&server), store.clone());
+
+        assert_eq!(oauth.access_token().unwrap(), "access-2");
+        assert_eq!(oauth.access_token().unwrap(), "access-2");
+
+        refresh.assert();
+        let saved = store.load("Oura").unwrap().unwrap();
+        assert_eq!(saved.refresh_token.as_deref(), Some("refresh-2"));
+    }
+
+    #[test]
+    fn test_refresh_keeps_refresh_token_when_not_rotated() {
+        let mut server = Server::new();
+        server.mock("POST", "/oauth/token")
+            .with_body(r#"{"access_token":"access-2","expires_in":86400}"#)
+            .create();
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir);
+        store.save("Oura", &token("access-1", Some("refresh-1"), -1000)).unwrap();
+        let oauth = OAuthClient::new("Oura", config(&server), store.clone());
+
+        assert_eq!(oauth.access_token().unwrap(), "access-2");
+        let saved = store.load("Oura").unwrap().unwrap();
+        assert_eq!(saved.refresh_token.as_deref(), Some("refresh-1"));
+    }
+
+    #[test]
+    fn test_status_reports_expired_credentials() {
+        let mut server = Server::new();
+        server.mock("POST", "/oauth/token")
+            .with_status(400)
+            .with_body(r#"{"error":"invalid_grant"}"#)
+            .create();
+        let dir = TempDir::new().unwrap();
+        let store = store(&dir);
+        let oauth = OAuthClient::new("Oura", config(&server), store.clone());
+
+        store.save("Oura", &token("access-1", None, -1000)).unwrap();
+        assert_eq!(oauth.status(), AuthStatus::Expired);
+
+        store.save("Oura", &token("access-1", Some("revoked"), -1000)).unwrap();
+        assert_eq!(oauth.status(), AuthStatus::Expired);
+    }
+
+    #[test]
+    fn test_pending_authorization_state_is_single_use() {
+        let pending = PendingAuthorizations::default();
+        let state = pending.begin("Oura");
+
+        assert!(!pending.complete(&state, "whoop"));
+        let state = pending.begin("Oura");
+        assert!(pending.complete(&state, "oura"));
+        assert!(!pending.complete(&state, "oura"));
+        assert!(!pending.complete("forged", "oura"));
+    }
+
+    #[actix_web::test]
+    async fn test_oauth_flow_endpoints() {
+        let mut server = Server::new_async().await;
+        server.mock("POST", "/oauth/token")
+            .with_body(r#"{"access_token":"access-1","refresh_token":"refresh-1","expires_in":86400}"#)
+            .create_async()
+            .await;
+        server.mock("GET", "/v2/usercollection/personal_info")
+            .match_header("authorization", "Bearer access-1")
+            .with_body("{}")
+            .create_async()
+            .await;
+        let dir = TempDir::new().unwrap();
+        let oura = Oura::with_oauth(server.url(), OAuthClient::new("Oura", config(&server), store(&dir)));
+        assert_eq!(oura.authorize(), AuthStatus::MissingCredentials);
+        let mut registry = IntegrationRegistry::new();
+        registry.register(oura);
+
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(registry))
+                .app_data(web::Data::new(PendingAuthorizations::default()))
+                .service(crate::api::list_integrations)
+                .service(crate::api::oauth_start)
+                .service(crate::api::oauth_callback),
+        )
+        .await;
+
+        let resp = actix_test::call_service(
+            &app,
+            actix_test::TestRequest::get().uri("/api/v1/oauth/oura/start").to_request(),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 302);
+        let location = resp.headers().get("location").unwrap().to_str().unwrap().to_string();
+        let state = location.split("state=").nth(1).unwrap().to_string();
+
+        let resp = actix_test::call_service(
+            &app,
+            actix_test::TestRequest::get().uri("/api/v1/oauth/oura/callback?code=code-1&state=forged").to_request(),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 400);
+
+        let uri = format!("/api/v1/oauth/oura/callback?code=code-1&state={}", state);
+        let resp = actix_test::call_service(&app, actix_test::TestRequest::get().uri(&uri).to_request()).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let body: serde_json::Value = actix_test::read_body_json(resp).await;
+        assert_eq!(body["integration"], "Oura");
+        assert_eq!(body["status"], "authorized");
+
+        let resp = actix_test::call_service(
+            &app,
+            actix_test::TestRequest::get().uri("/api/v1/integrations").to_request(),
+        )
+        .await;
+        let body: serde_json::Value = actix_test::read_body_json(resp).await;
+        assert_eq!(body[0]["integration"], "Oura");
+        assert_eq!(body[0]["status"], "authorized");
+    }
+}
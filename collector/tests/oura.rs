@@ -1,5 +1,9 @@
+#![allow(dead_code)]
 #[path = "../src/base_integration.rs"] mod base_integration;
-use crate::base_integration::Oura;
+#[path = "../src/oauth.rs"] mod oauth;
+#[path = "../src/token_store.rs"] mod token_store;
+#[path = "../src/oura.rs"] mod oura;
+use crate::oura::Oura;
 use crate::base_integration::BaseIntegration;
 
 pub fn add(a: i32, b: i32) -> i32 {
@@ -19,13 +23,118 @@ fn bad_add(a: i32, b: i32) -> i32 {
 mod tests {
 
     use super::*;
+    use crate::base_integration::{AuthStatus, IntegrationError, Observation, ObservationValue};
+    use chrono::NaiveDate;
+    use mockito::{Matcher, Server, ServerGuard};
+
+    fn fixture(name: &str) -> String {
+        let path = format!("{}/tests/fixtures/oura/{}.json", env!("CARGO_MANIFEST_DIR"), name);
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    fn mock_collection(server: &mut ServerGuard, endpoint: &str, body: String) -> mockito::Mock {
+        server.mock("GET", format!("/v2/usercollection/{}", endpoint).as_str())
+            .match_header("authorization", "Bearer test-token")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("start_date".into(), "2024-03-01".into()),
+                Matcher::UrlEncoded("end_date".into(), "2024-03-02".into()),
+            ]))
+            .with_header("content-type", "application/json")
+            .with_body(body)
+            .create()
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn find<'a>(rows: &'a [Observation], key: &str, day: &str) -> &'a Observation {
+        rows.iter()
+            .find(|r| r.key == key && r.matcheddate.to_string() == day)
+            .unwrap_or_else(|| panic!("missing {} for {}", key, day))
+    }
 
     #[test]
     fn test_oura() {
-        let oura = Oura::new();
+        let oura = Oura::with_base_url("http://localhost".to_string(), String::new());
         assert_eq!(oura.name(), "Oura");
-        assert_eq!(oura.authorize(), true);
-        assert_eq!(oura.get_data("2020-01-01".to_string(), "2020-01-01".to_string()), "Oura data");
+        assert_eq!(oura.authorize(), AuthStatus::MissingCredentials);
+    }
+
+    #[test]
+    fn test_oura_authorize() {
+        let mut server = Server::new();
+        let info = server.mock("GET", "/v2/usercollection/personal_info")
+            .match_header("authorization", "Bearer test-token")
+            .with_body(fixture("personal_info"))
+            .create();
+        let oura = Oura::with_base_url(server.url(), "test-token".to_string());
+
+        assert_eq!(oura.authorize(), AuthStatus::Authorized);
+        info.assert();
+    }
+
+    #[test]
+    fn test_oura_fetch_maps_daily_documents() {
+        let mut server = Server::new();
+        let sleep_page_2 = server.mock("GET", "/v2/usercollection/daily_sleep")
+            .match_query(Matcher::UrlEncoded("next_token".into(), "c2xlZXAtcGFnZS0y".into()))
+            .with_body(fixture("daily_sleep_page2"))
+            .create();
+        let sleep = mock_collection(&mut server, "daily_sleep", fixture("daily_sleep"));
+        let readiness = mock_collection(&mut server, "daily_readiness", fixture("daily_readiness"));
+        let activity = mock_collection(&mut server, "daily_activity", fixture("daily_activity"));
+        let oura = Oura::with_base_url(server.url(), "test-token".to_string());
+
+        let rows = oura.get_data(date("2024-03-01"), date("2024-03-02")).unwrap();
+
+        sleep.assert();
+        sleep_page_2.assert();
+        readiness.assert();
+        activity.assert();
+        assert!(rows.iter().all(|r| r.source == "oura"));
+        assert!(rows.iter().all(|r| r.importid == rows[0].importid));
+
+        let score = find(&rows, "ouraSleepScore", "2024-03-01");
+        assert_eq!(score.value, ObservationValue::Number(68.0));
+        assert_eq!(score.value.to_raw_value(), "68");
+        assert_eq!(score.value.data_type(), "number");
+        assert_eq!(score.question, "Oura Sleep Score");
+        assert_eq!(score.timestamp, 1_709_247_600_000);
+        assert_eq!(find(&rows, "ouraSleepScore", "2024-03-02").value, ObservationValue::Number(84.0));
+        assert_eq!(find(&rows, "ouraReadinessScore", "2024-03-01").value, ObservationValue::Number(66.0));
+        assert_eq!(find(&rows, "ouraTemperatureDeviation", "2024-03-01").value, ObservationValue::Number(-0.2));
+        assert_eq!(find(&rows, "ouraSteps", "2024-03-01").value, ObservationValue::Number(8912.0));
+        // null contributors are skipped rather than stored as empty values
+        assert!(!rows.iter().any(|r| r.key == "ouraReadinessPreviousDayActivity"));
+    }
+
+    #[test]
+    fn test_oura_get_data_reports_expired_token() {
+        let mut server = Server::new();
+        server.mock("GET", Matcher::Any).with_status(401).create();
+        let oura = Oura::with_base_url(server.url(), "expired".to_string());
+
+        let result = oura.get_data(date("2024-03-01"), date("2024-03-02"));
+        assert!(matches!(result, Err(IntegrationError::Unauthorized(_, AuthStatus::Expired))), "{:?}", result);
+    }
+
+    #[test]
+    fn test_oura_get_data_reports_server_errors() {
+        let mut server = Server::new();
+        server.mock("GET", Matcher::Any).with_status(502).create();
+        let oura = Oura::with_base_url(server.url(), "test-token".to_string());
+
+        let result = oura.get_data(date("2024-03-01"), date("2024-03-02"));
+        assert!(matches!(result, Err(IntegrationError::Http(_))), "{:?}", result);
+    }
+
+    #[test]
+    fn test_oura_get_data_rejects_reversed_range() {
+        let oura = Oura::with_base_url("http://localhost".to_string(), "test-token".to_string());
+
+        let result = oura.get_data(date("2024-03-02"), date("2024-03-01"));
+        assert!(matches!(result, Err(IntegrationError::InvalidDateRange(_, _))), "{:?}", result);
     }
 
 
@@ -40,4 +149,4 @@ mod tests {
         // Please note, that private functions can be tested too!
         assert_eq!(bad_add(1, 2), 3);
     }
-}
\ No newline at end of file
+}
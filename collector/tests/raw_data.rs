@@ -0,0 +1,228 @@
+#![allow(dead_code)]
+#[path = "../src/base_integration.rs"] mod base_integration;
+#[path = "../src/oauth.rs"] mod oauth;
+#[path = "../src/token_store.rs"] mod token_store;
+#[path = "../src/db.rs"] mod db;
+#[path = "../src/raw_data.rs"] mod raw_data;
+#[path = "../src/timezones.rs"] mod timezones;
+#[path = "../src/imports.rs"] mod imports;
+
+#[cfg(test)]
+mod tests {
+
+    use crate::base_integration::{Observation, ObservationValue};
+    use crate::db::{init_db, Db};
+    use crate::imports::{delete_import, rollback_import, ImportError, RollbackResult};
+    use crate::raw_data::{upsert_observations, RawDataRecord};
+    use crate::timezones::{DayClock, DayClockConfig, TimezoneChange};
+    use chrono::NaiveDate;
+
+    fn observation(day: &str, timestamp: i64, value: ObservationValue) -> Observation {
+        Observation {
+            key: "ouraSteps".to_string(),
+            question: "Oura Steps".to_string(),
+            value,
+            timestamp,
+            matcheddate: NaiveDate::parse_from_str(day, "%Y-%m-%d").unwrap(),
+            source: "oura".to_string(),
+            importid: "import-1".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_record_derives_calendar_columns() {
+        // 2024-03-01T13:45:00Z
+        let record = RawDataRecord::from_observation(
+            &observation("2024-03-01", 1_709_300_700_000, ObservationValue::Number(8912.0)),
+            &DayClock::default(),
+        );
+
+        assert_eq!(record.yearmonth, 202403);
+        assert_eq!(record.yearweek, 202409);
+        assert_eq!(record.year, 2024);
+        assert_eq!(record.quarter, 1);
+        assert_eq!(record.month, 3);
+        assert_eq!(record.day, 1);
+        assert_eq!(record.hour, 13);
+        assert_eq!(record.minute, 45);
+        assert_eq!(record.week, 9);
+        assert_eq!(record.data_type, "number");
+        assert_eq!(record.value, "8912");
+        assert_eq!(record.source, "oura");
+        assert_eq!(record.importid, "import-1");
+    }
+
+    #[test]
+    fn test_record_calendar_follows_matched_date() {
+        // 2024-12-31T23:30:00Z, matched to the last day of the year
+        let record = RawDataRecord::from_observation(
+            &observation("2024-12-31", 1_735_687_800_000, ObservationValue::Text("late".to_string())),
+            &DayClock::default(),
+        );
+
+        assert_eq!(record.yearmonth, 202412);
+        assert_eq!(record.quarter, 4);
+        assert_eq!(record.day, 31);
+        assert_eq!(record.hour, 23);
+        assert_eq!(record.data_type, "text");
+        assert_eq!(record.value, "late");
+    }
+
+    #[test]
+    fn test_record_time_is_local_to_the_zone_of_its_moment() {
+        let history = [TimezoneChange { id: 1, starts_at: 1_709_280_000_000, timezone: "Asia/Tokyo".to_string() }];
+        let clock = DayClock::new(DayClockConfig { default_zone: "Europe/Berlin".parse().unwrap(), day_boundary_hour: 0 }, &history);
+        // 2024-03-01T13:45:00Z, after the move to Tokyo
+        let record = RawDataRecord::from_observation(
+            &observation("2024-03-01", 1_709_300_700_000, ObservationValue::Number(8912.0)),
+            &clock,
+        );
+
+        assert_eq!(record.hour, 22);
+        assert_eq!(record.minute, 45);
+        assert_eq!(record.timezone, "Asia/Tokyo");
+        // the day stays the one the integration reported
+        assert_eq!(record.matcheddate.to_string(), "2024-03-01");
+    }
+
+    #[test]
+    fn test_import_rows_page_is_clamped() {
+        assert_eq!(crate::imports::page(None, None), (500, 0));
+        assert_eq!(crate::imports::page(Some(0), Some(-5)), (1, 0));
+        assert_eq!(crate::imports::page(Some(100_000), Some(20)), (5000, 20));
+    }
+
+    /// Connects to `DATABASE_URL`; the DB tests write rows of a key of their
+    /// own and remove them again.
+    async fn test_db(name: &str) -> (Db, String) {
+        let db = init_db().await.expect("DB tests need DATABASE_URL");
+        (db, format!("{}-{}", name, uuid::Uuid::new_v4()))
+    }
+
+    async fn cleanup(db: &Db, key: &str) {
+        sqlx::query("DELETE FROM raw_data WHERE key = $1").bind(key).execute(db).await.unwrap();
+        sqlx::query("DELETE FROM raw_data_history WHERE key = $1").bind(key).execute(db).await.unwrap();
+    }
+
+    async fn stored(db: &Db, key: &str) -> Vec<(String, Option<String>, Option<String>)> {
+        sqlx::query_as("SELECT value, importid, timezone FROM raw_data WHERE key = $1 ORDER BY id")
+            .bind(key)
+            .fetch_all(db)
+            .await
+            .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_upsert_skips_days_the_whoop_script_wrote() {
+        let (db, key) = test_db("whoopRecovery").await;
+        // as whoop_import.py writes it: an importid of its own, no timezone
+        sqlx::query(
+            "INSERT INTO raw_data (timestamp, key, question, type, value, matcheddate, source, importedat, importid)
+            VALUES (1709280000000, $1, 'Recovery', 'number', '61', '2024-03-01', 'whoop', now(), $2)",
+        )
+        .bind(&key)
+        .bind(uuid::Uuid::new_v4().to_string())
+        .execute(&db)
+        .await
+        .unwrap();
+        let whoop = |day: &str, timestamp: i64, value: f64| Observation {
+            key: key.clone(),
+            source: "whoop".to_string(),
+            ..observation(day, timestamp, ObservationValue::Number(value))
+        };
+
+        // the collector timestamps the same day differently
+        let written = upsert_observations(&db, &[whoop("2024-03-01", 1709290000000, 64.0), whoop("2024-03-02", 1709370000000, 70.0)])
+            .await
+            .unwrap();
+
+        let rows = stored(&db, &key).await;
+        cleanup(&db, &key).await;
+        assert_eq!(written, 1);
+        assert_eq!(rows.len(), 2, "{:?}", rows);
+        assert_eq!((rows[0].0.as_str(), rows[0].2.as_deref()), ("61", None));
+        assert_eq!((rows[1].0.as_str(), rows[1].1.as_deref()), ("70", Some("import-1")));
+    }
+
+    #[actix_web::test]
+    async fn test_unchanged_reimport_leaves_row_and_history_alone() {
+        let (db, key) = test_db("ouraSteps").await;
+        let import = |importid: &str, value: f64| Observation {
+            key: key.clone(),
+            importid: importid.to_string(),
+            ..observation("2024-03-01", 1709280000000, ObservationValue::Number(value))
+        };
+
+        let first = upsert_observations(&db, &[import("import-1", 8000.0)]).await.unwrap();
+        let unchanged = upsert_observations(&db, &[import("import-2", 8000.0)]).await.unwrap();
+        let unchanged_rows = stored(&db, &key).await;
+        let changed = upsert_observations(&db, &[import("import-3", 8500.0)]).await.unwrap();
+
+        let rows = stored(&db, &key).await;
+        let history: Vec<(String, String, String)> =
+            sqlx::query_as("SELECT value, importid, replaced_by FROM raw_data_history WHERE key = $1")
+                .bind(&key)
+                .fetch_all(&db)
+                .await
+                .unwrap();
+        cleanup(&db, &key).await;
+        assert_eq!((first, unchanged, changed), (1, 0, 1));
+        assert_eq!(unchanged_rows[0].1.as_deref(), Some("import-1"));
+        assert_eq!((rows[0].0.as_str(), rows[0].1.as_deref()), ("8500", Some("import-3")));
+        assert_eq!(history, vec![("8000".to_string(), "import-1".to_string(), "import-3".to_string())]);
+    }
+
+    #[actix_web::test]
+    async fn test_rollback_is_refused_until_later_imports_are_rolled_back() {
+        let (db, key) = test_db("ouraSteps").await;
+        let ids: Vec<String> = (0..3).map(|_| uuid::Uuid::new_v4().to_string()).collect();
+        for (importid, value) in ids.iter().zip([1000.0, 2000.0, 3000.0]) {
+            let observation = Observation {
+                key: key.clone(),
+                importid: importid.clone(),
+                ..observation("2024-03-01", 1709280000000, ObservationValue::Number(value))
+            };
+            upsert_observations(&db, &[observation]).await.unwrap();
+        }
+
+        let refused = rollback_import(&db, &ids[1]).await;
+        let refused_delete = delete_import(&db, &ids[1]).await;
+        let newest = rollback_import(&db, &ids[2]).await.unwrap();
+        let after_newest = stored(&db, &key).await;
+        let middle = rollback_import(&db, &ids[1]).await.unwrap();
+
+        let rows = stored(&db, &key).await;
+        cleanup(&db, &key).await;
+        assert!(matches!(&refused, Err(ImportError::OverwrittenLater(later)) if *later == vec![ids[2].clone()]), "{:?}", refused);
+        assert!(matches!(refused_delete, Err(ImportError::OverwrittenLater(_))));
+        assert_eq!(newest, RollbackResult { deleted: 1, restored: 1 });
+        assert_eq!(after_newest[0].0, "2000");
+        assert_eq!(middle, RollbackResult { deleted: 1, restored: 1 });
+        assert_eq!((rows[0].0.as_str(), rows[0].1.as_deref()), ("1000", Some(ids[0].as_str())));
+    }
+
+    #[actix_web::test]
+    async fn test_deleted_import_can_still_be_rolled_back() {
+        let (db, key) = test_db("ouraSteps").await;
+        let ids: Vec<String> = (0..2).map(|_| uuid::Uuid::new_v4().to_string()).collect();
+        for (importid, value) in ids.iter().zip([1000.0, 2000.0]) {
+            let observation = Observation {
+                key: key.clone(),
+                importid: importid.clone(),
+                ..observation("2024-03-01", 1709280000000, ObservationValue::Number(value))
+            };
+            upsert_observations(&db, &[observation]).await.unwrap();
+        }
+
+        let deleted = delete_import(&db, &ids[1]).await.unwrap();
+        let after_delete = stored(&db, &key).await;
+        let undone = rollback_import(&db, &ids[1]).await.unwrap();
+
+        let rows = stored(&db, &key).await;
+        cleanup(&db, &key).await;
+        assert_eq!(deleted, 1);
+        assert!(after_delete.is_empty());
+        assert_eq!(undone, RollbackResult { deleted: 0, restored: 1 });
+        assert_eq!((rows[0].0.as_str(), rows[0].1.as_deref()), ("1000", Some(ids[0].as_str())));
+    }
+}
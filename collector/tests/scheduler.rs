@@ -0,0 +1,44 @@
+#![allow(dead_code)]
+#[path = "../src/base_integration.rs"] mod base_integration;
+#[path = "../src/oauth.rs"] mod oauth;
+#[path = "../src/token_store.rs"] mod token_store;
+#[path = "../src/db.rs"] mod db;
+#[path = "../src/integration_registry.rs"] mod integration_registry;
+#[path = "../src/raw_data.rs"] mod raw_data;
+#[path = "../src/timezones.rs"] mod timezones;
+#[path = "../src/scheduler.rs"] mod scheduler;
+
+#[cfg(test)]
+mod tests {
+
+    use crate::scheduler::next_range;
+    use chrono::NaiveDate;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn test_first_sync_backfills() {
+        let range = next_range(None, date("2024-03-31"), 30);
+        assert_eq!(range, (date("2024-03-01"), date("2024-03-31")));
+    }
+
+    #[test]
+    fn test_sync_refetches_high_water_mark_day() {
+        let range = next_range(Some(date("2024-03-31")), date("2024-03-31"), 30);
+        assert_eq!(range, (date("2024-03-31"), date("2024-03-31")));
+    }
+
+    #[test]
+    fn test_sync_catches_up_after_downtime() {
+        let range = next_range(Some(date("2024-03-10")), date("2024-03-31"), 30);
+        assert_eq!(range, (date("2024-03-10"), date("2024-03-31")));
+    }
+
+    #[test]
+    fn test_sync_ignores_future_high_water_mark() {
+        let range = next_range(Some(date("2024-04-02")), date("2024-03-31"), 30);
+        assert_eq!(range, (date("2024-03-31"), date("2024-03-31")));
+    }
+}
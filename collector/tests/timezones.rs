@@ -0,0 +1,126 @@
+#![allow(dead_code)]
+#[path = "../src/base_integration.rs"] mod base_integration;
+#[path = "../src/oauth.rs"] mod oauth;
+#[path = "../src/token_store.rs"] mod token_store;
+#[path = "../src/db.rs"] mod db;
+#[path = "../src/raw_data.rs"] mod raw_data;
+#[path = "../src/timezones.rs"] mod timezones;
+
+#[cfg(test)]
+mod tests {
+
+    use crate::db::init_db;
+    use crate::timezones::{recompute, DayClock, DayClockConfig, RecomputeScope, TimezoneChange};
+    use chrono::{DateTime, NaiveDate};
+    use chrono_tz::Tz;
+
+    fn millis(rfc3339: &str) -> i64 {
+        DateTime::parse_from_rfc3339(rfc3339).unwrap().timestamp_millis()
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn change(id: i32, starts_at: &str, timezone: &str) -> TimezoneChange {
+        TimezoneChange { id, starts_at: millis(starts_at), timezone: timezone.to_string() }
+    }
+
+    /// Berlin until a flight to New York on 2024-03-10 and on to Tokyo on 2024-03-20.
+    fn clock(day_boundary_hour: u32) -> DayClock {
+        let config = DayClockConfig { default_zone: Tz::Europe__Berlin, day_boundary_hour };
+        let history = [
+            change(2, "2024-03-20T02:00:00Z", "Asia/Tokyo"),
+            change(1, "2024-03-10T16:00:00Z", "America/New_York"),
+            change(3, "2024-03-21T00:00:00Z", "Not/AZone"),
+        ];
+        DayClock::new(config, &history)
+    }
+
+    #[test]
+    fn test_zone_follows_history() {
+        let clock = clock(0);
+        assert_eq!(clock.zone_at(millis("2024-03-01T12:00:00Z")), Tz::Europe__Berlin);
+        assert_eq!(clock.zone_at(millis("2024-03-10T16:00:00Z")), Tz::America__New_York);
+        assert_eq!(clock.zone_at(millis("2024-03-15T12:00:00Z")), Tz::America__New_York);
+        // unknown zone names are skipped
+        assert_eq!(clock.zone_at(millis("2024-04-01T12:00:00Z")), Tz::Asia__Tokyo);
+    }
+
+    #[test]
+    fn test_same_instant_is_matched_to_the_local_day() {
+        // late evenings stay on the local day even where UTC has moved on
+        let berlin = clock(0).localize(millis("2024-03-05T22:30:00Z"));
+        assert_eq!((berlin.matcheddate, berlin.hour, berlin.minute), (date("2024-03-05"), 23, 30));
+
+        let new_york = clock(0).localize(millis("2024-03-16T01:30:00Z"));
+        assert_eq!((new_york.matcheddate, new_york.hour), (date("2024-03-15"), 21));
+        assert_eq!(new_york.timezone, Tz::America__New_York);
+
+        let tokyo = clock(0).localize(millis("2024-03-20T16:00:00Z"));
+        assert_eq!((tokyo.matcheddate, tokyo.hour), (date("2024-03-21"), 1));
+    }
+
+    #[test]
+    fn test_day_boundary_keeps_late_nights_on_the_day_before() {
+        // 01:30 in Tokyo on the 21st
+        let moment = millis("2024-03-20T16:30:00Z");
+        assert_eq!(clock(0).localize(moment).matcheddate, date("2024-03-21"));
+
+        let late = clock(4).localize(moment);
+        assert_eq!(late.matcheddate, date("2024-03-20"));
+        // the hour stays the wall-clock hour
+        assert_eq!(late.hour, 1);
+
+        assert_eq!(clock(4).localize(millis("2024-03-20T19:00:00Z")).matcheddate, date("2024-03-21"));
+    }
+
+    #[test]
+    fn test_dst_shift_changes_the_local_hour() {
+        // Berlin switches to summer time on 2024-03-31
+        let berlin = DayClock::new(DayClockConfig { default_zone: Tz::Europe__Berlin, day_boundary_hour: 0 }, &[]);
+        assert_eq!(berlin.localize(millis("2024-03-30T08:00:00Z")).hour, 9);
+        assert_eq!(berlin.localize(millis("2024-03-31T08:00:00Z")).hour, 10);
+    }
+
+    #[test]
+    fn test_recompute_scopes() {
+        assert_eq!(RecomputeScope::default().sources, vec!["telegram".to_string(), "viz".to_string()]);
+        assert!(RecomputeScope::unmatched().sources.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_recompute_rematches_rows_written_through_viz() {
+        let db = init_db().await.expect("DB tests need DATABASE_URL");
+        let key = format!("recomputeViz-{}", uuid::Uuid::new_v4());
+        // far enough ahead that no other row is in the recompute's range
+        let timestamp = millis("2090-03-20T23:30:00Z");
+        for source in ["viz", "oura"] {
+            sqlx::query(
+                "INSERT INTO raw_data (timestamp, key, value, matcheddate, hour, source, timezone)
+                VALUES ($1, $2, '1', '2090-03-20', 23, $3, 'UTC')",
+            )
+            .bind(timestamp)
+            .bind(&key)
+            .bind(source)
+            .execute(&db)
+            .await
+            .unwrap();
+        }
+        let tokyo = DayClock::new(DayClockConfig::default(), &[change(1, "2090-01-01T00:00:00Z", "Asia/Tokyo")]);
+
+        let result = recompute(&db, &tokyo, &RecomputeScope { from: Some(timestamp), ..RecomputeScope::default() }).await;
+
+        let rows: Vec<(String, NaiveDate, i16, String)> =
+            sqlx::query_as("SELECT source, matcheddate, hour, timezone FROM raw_data WHERE key = $1 ORDER BY source")
+                .bind(&key)
+                .fetch_all(&db)
+                .await
+                .unwrap();
+        sqlx::query("DELETE FROM raw_data WHERE key = $1").bind(&key).execute(&db).await.unwrap();
+        assert_eq!(result.unwrap().updated, 1);
+        // integrations keep the day they reported
+        assert_eq!(rows[0], ("oura".to_string(), date("2090-03-20"), 23, "UTC".to_string()));
+        assert_eq!(rows[1], ("viz".to_string(), date("2090-03-21"), 8, "Asia/Tokyo".to_string()));
+    }
+}
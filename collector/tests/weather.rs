@@ -0,0 +1,213 @@
+#![allow(dead_code)]
+#[path = "../src/base_integration.rs"] mod base_integration;
+#[path = "../src/oauth.rs"] mod oauth;
+#[path = "../src/token_store.rs"] mod token_store;
+#[path = "../src/db.rs"] mod db;
+#[path = "../src/weather.rs"] mod weather;
+
+#[cfg(test)]
+mod tests {
+
+    use crate::base_integration::{AuthStatus, BaseIntegration, IntegrationError, Observation, ObservationValue};
+    use crate::weather::{daily_fixes, daylight_hours, parse_meteostat_csv, season, DailyWeather, LocationRow, Weather, WeatherConfig};
+    use chrono::NaiveDate;
+    use mockito::{Matcher, Server};
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    fn fixture(name: &str) -> Vec<u8> {
+        let path = format!("{}/tests/fixtures/weather/{}", env!("CARGO_MANIFEST_DIR"), name);
+        std::fs::read(path).unwrap()
+    }
+
+    /// A config in a temp dir next to a copy of the Berlin station CSV, with
+    /// Berlin as home and a Cape Town station without a CSV.
+    fn config(dir: &TempDir, provider: Option<String>) -> PathBuf {
+        std::fs::write(dir.path().join("10382.csv"), fixture("10382.csv")).unwrap();
+        let provider = provider
+            .map(|url| format!(r#", "provider": {{ "url": "{}", "headers": {{ "x-api-key": "secret" }} }}"#, url))
+            .unwrap_or_default();
+        let json = format!(
+            r#"{{
+                "home": {{ "name": "Berlin", "lat": 52.52, "lng": 13.405, "csv": "10382.csv" }},
+                "stations": [{{ "name": "Cape Town", "lat": -33.92, "lng": 18.42 }}]
+                {}
+            }}"#,
+            provider
+        );
+        let path = dir.path().join("weather.json");
+        std::fs::write(&path, json).unwrap();
+        path
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn fixes(fixes: &[(&str, f64, f64)]) -> BTreeMap<NaiveDate, (f64, f64)> {
+        fixes.iter().map(|(day, lat, lng)| (date(day), (*lat, *lng))).collect()
+    }
+
+    fn text(value: &str) -> Option<ObservationValue> {
+        Some(ObservationValue::Text(value.to_string()))
+    }
+
+    fn value(rows: &[Observation], key: &str, day: &str) -> Option<ObservationValue> {
+        rows.iter().find(|r| r.key == key && r.matcheddate == date(day)).map(|r| r.value.clone())
+    }
+
+    #[test]
+    fn test_daylight_hours() {
+        let berlin = |day: &str| daylight_hours(date(day), 52.52, 13.405);
+        assert!((berlin("2024-06-21") - 16.8).abs() < 0.1, "{}", berlin("2024-06-21"));
+        assert!((berlin("2024-12-21") - 7.6).abs() < 0.1, "{}", berlin("2024-12-21"));
+        assert!((daylight_hours(date("2024-03-20"), 0.0, 0.0) - 12.1).abs() < 0.1);
+        // Tromsø has polar night in December and midnight sun in June
+        assert_eq!(daylight_hours(date("2024-12-21"), 69.65, 18.96), 0.0);
+        assert_eq!(daylight_hours(date("2024-06-21"), 69.65, 18.96), 24.0);
+    }
+
+    #[test]
+    fn test_season_by_hemisphere() {
+        assert_eq!(season(date("2024-01-15"), 52.52), "winter");
+        assert_eq!(season(date("2024-12-01"), 52.52), "winter");
+        assert_eq!(season(date("2024-04-15"), 52.52), "spring");
+        assert_eq!(season(date("2024-01-15"), -33.92), "summer");
+        assert_eq!(season(date("2024-10-15"), -33.92), "spring");
+    }
+
+    #[test]
+    fn test_parse_meteostat_csv_with_and_without_header() {
+        let bulk = parse_meteostat_csv(&fixture("10382.csv")).unwrap();
+        assert_eq!(bulk.len(), 3);
+        assert_eq!(bulk[&date("2024-01-02")], DailyWeather { tavg: Some(1.5), tmin: Some(-1.0), tmax: Some(3.8), prcp: None });
+
+        let export = parse_meteostat_csv(b"date,tavg,tmin,tmax,prcp,snow\n2024-01-01 00:00:00,3.1,,5.2,2.3,\n").unwrap();
+        assert_eq!(export[&date("2024-01-01")], DailyWeather { tavg: Some(3.1), tmin: None, tmax: Some(5.2), prcp: Some(2.3) });
+    }
+
+    #[test]
+    fn test_get_data_without_db_stays_at_home() {
+        let dir = TempDir::new().unwrap();
+        let weather = Weather::new(config(&dir, None), None);
+        assert_eq!(weather.authorize(), AuthStatus::Authorized);
+
+        let rows = weather.get_data(date("2023-12-31"), date("2024-01-03")).unwrap();
+
+        assert!(rows.iter().all(|r| r.source == "weather"));
+        assert_eq!(value(&rows, "weatherLocation", "2023-12-31"), text("Berlin"));
+        // the CSV starts on 2024-01-01
+        assert_eq!(value(&rows, "weatherTemperature", "2023-12-31"), None);
+        assert_eq!(value(&rows, "weatherLocation", "2024-01-03"), text("Berlin"));
+        assert_eq!(value(&rows, "weatherTemperature", "2024-01-03"), Some(ObservationValue::Number(-0.7)));
+        assert_eq!(value(&rows, "weatherPrecipitation", "2024-01-02"), None);
+        assert_eq!(value(&rows, "weatherSeason", "2024-01-03"), text("winter"));
+        assert_eq!(value(&rows, "weatherDaylight", "2024-01-03"), Some(ObservationValue::Number(7.78)));
+    }
+
+    #[test]
+    fn test_observations_follow_location_fixes() {
+        let dir = TempDir::new().unwrap();
+        let path = config(&dir, None);
+        let config = WeatherConfig::load(&path).unwrap();
+        let weather = Weather::new(path, None);
+        let fixes = fixes(&[("2024-01-04", -33.93, 18.43), ("2024-01-10", 48.857, 2.352)]);
+
+        let rows = weather.observations(&config, &fixes, date("2024-01-01"), date("2024-01-11")).unwrap();
+
+        assert_eq!(value(&rows, "weatherLocation", "2024-01-03"), text("Berlin"));
+        // a fix near a station takes its name and coordinates
+        assert_eq!(value(&rows, "weatherLocation", "2024-01-04"), text("Cape Town"));
+        assert_eq!(value(&rows, "weatherLocation", "2024-01-09"), text("Cape Town"));
+        assert_eq!(value(&rows, "weatherSeason", "2024-01-05"), text("summer"));
+        assert!(value(&rows, "weatherDaylight", "2024-01-05").is_some());
+        assert_eq!(value(&rows, "weatherTemperature", "2024-01-05"), None);
+        // anywhere else is named by its rounded coordinates
+        assert_eq!(value(&rows, "weatherLocation", "2024-01-11"), text("48.9,2.4"));
+        assert_eq!(value(&rows, "weatherSeason", "2024-01-11"), text("winter"));
+    }
+
+    #[test]
+    fn test_fixes_carry_for_a_week_then_home() {
+        let dir = TempDir::new().unwrap();
+        let config = WeatherConfig::load(&config(&dir, None)).unwrap();
+        let fixes = fixes(&[("2024-01-01", -33.92, 18.42)]);
+
+        assert_eq!(config.location_on(date("2023-12-31"), &fixes).unwrap().name, "Berlin");
+        assert_eq!(config.location_on(date("2024-01-08"), &fixes).unwrap().name, "Cape Town");
+        assert_eq!(config.location_on(date("2024-01-09"), &fixes).unwrap().name, "Berlin");
+        assert_eq!(WeatherConfig::default().location_on(date("2024-01-09"), &fixes), None);
+    }
+
+    #[test]
+    fn test_daily_fixes_pair_lat_and_lng_rows() {
+        let row = |timestamp: i64, day: &str, key: &str, value: &str| LocationRow {
+            timestamp,
+            matcheddate: date(day),
+            key: key.to_string(),
+            value: value.to_string(),
+        };
+        let rows = vec![
+            row(1, "2024-01-01", "locationLat", "52.5"),
+            row(1, "2024-01-01", "locationLng", "13.4"),
+            // the last fix of the day wins
+            row(2, "2024-01-01", "locationLat", "52.6"),
+            row(2, "2024-01-01", "locationLng", "13.5"),
+            // a lng without the lat of the same message is no fix
+            row(3, "2024-01-02", "locationLat", "48.8"),
+            row(4, "2024-01-02", "locationLng", "2.3"),
+            // answers to location questions hold both
+            row(5, "2024-01-03", "whereAmI", "-33.92, 18.42"),
+            row(6, "2024-01-04", "whereAmI", "91,0"),
+        ];
+
+        let fixes = daily_fixes(&rows);
+
+        assert_eq!(fixes.len(), 2);
+        assert_eq!(fixes[&date("2024-01-01")], (52.6, 13.5));
+        assert_eq!(fixes[&date("2024-01-03")], (-33.92, 18.42));
+    }
+
+    #[test]
+    fn test_provider_fills_days_without_csv() {
+        let mut server = Server::new();
+        let mock = server.mock("GET", "/daily")
+            .match_header("x-api-key", "secret")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("lat".into(), "-33.92".into()),
+                Matcher::UrlEncoded("start".into(), "2024-01-04".into()),
+                Matcher::UrlEncoded("end".into(), "2024-01-05".into()),
+            ]))
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"data": [
+                {"date": "2024-01-04", "tavg": 22.4, "tmin": 17.0, "tmax": 27.9, "prcp": 0.0},
+                {"date": "2024-01-05", "tavg": 23.1, "tmin": null, "tmax": 29.2, "prcp": 1.2}
+            ]}"#)
+            .create();
+        let dir = TempDir::new().unwrap();
+        let url = format!("{}/daily?lat={{lat}}&lon={{lng}}&start={{start}}&end={{end}}", server.url());
+        let path = config(&dir, Some(url));
+        let config = WeatherConfig::load(&path).unwrap();
+        let weather = Weather::new(path, None);
+        let fixes = fixes(&[("2024-01-04", -33.93, 18.43)]);
+
+        // Berlin is fully covered by its CSV, so only Cape Town is requested.
+        let rows = weather.observations(&config, &fixes, date("2024-01-01"), date("2024-01-05")).unwrap();
+
+        mock.assert();
+        assert_eq!(value(&rows, "weatherTemperature", "2024-01-01"), Some(ObservationValue::Number(3.1)));
+        assert_eq!(value(&rows, "weatherTemperature", "2024-01-05"), Some(ObservationValue::Number(23.1)));
+        assert_eq!(value(&rows, "weatherTemperatureMin", "2024-01-05"), None);
+        assert_eq!(value(&rows, "weatherPrecipitation", "2024-01-05"), Some(ObservationValue::Number(1.2)));
+    }
+
+    #[test]
+    fn test_missing_config_and_invalid_range() {
+        let dir = TempDir::new().unwrap();
+        let weather = Weather::new(dir.path().join("weather.json"), None);
+        assert_eq!(weather.authorize(), AuthStatus::MissingCredentials);
+        let result = weather.get_data(date("2024-01-02"), date("2024-01-01"));
+        assert!(matches!(result, Err(IntegrationError::InvalidDateRange(_, _))), "{:?}", result);
+    }
+}
@@ -0,0 +1,130 @@
+#![allow(dead_code)]
+#[path = "../src/base_integration.rs"] mod base_integration;
+#[path = "../src/oauth.rs"] mod oauth;
+#[path = "../src/token_store.rs"] mod token_store;
+#[path = "../src/whoop.rs"] mod whoop;
+
+#[cfg(test)]
+mod tests {
+
+    use crate::base_integration::{AuthStatus, BaseIntegration, IntegrationError, Observation, ObservationValue};
+    use crate::whoop::Whoop;
+    use chrono::NaiveDate;
+    use mockito::{Matcher, Server, ServerGuard};
+
+    fn fixture(name: &str) -> String {
+        let path = format!("{}/tests/fixtures/whoop/{}.json", env!("CARGO_MANIFEST_DIR"), name);
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    fn mock_collection(server: &mut ServerGuard, endpoint: &str, body: String) -> mockito::Mock {
+        server.mock("GET", format!("/developer/v2/{}", endpoint).as_str())
+            .match_header("authorization", "Bearer test-token")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("start".into(), "2024-03-01T00:00:00.000Z".into()),
+                Matcher::UrlEncoded("end".into(), "2024-03-03T00:00:00.000Z".into()),
+                Matcher::UrlEncoded("limit".into(), "25".into()),
+            ]))
+            .with_header("content-type", "application/json")
+            .with_body(body)
+            .create()
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn find<'a>(rows: &'a [Observation], key: &str, day: &str) -> &'a Observation {
+        rows.iter()
+            .find(|r| r.key == key && r.matcheddate.to_string() == day)
+            .unwrap_or_else(|| panic!("missing {} for {}", key, day))
+    }
+
+    #[test]
+    fn test_whoop() {
+        let whoop = Whoop::with_base_url("http://localhost".to_string(), String::new());
+        assert_eq!(whoop.name(), "Whoop");
+        assert_eq!(whoop.authorize(), AuthStatus::MissingCredentials);
+    }
+
+    #[test]
+    fn test_whoop_authorize() {
+        let mut server = Server::new();
+        let profile = server.mock("GET", "/developer/v2/user/profile/basic")
+            .match_header("authorization", "Bearer test-token")
+            .with_body(fixture("profile"))
+            .create();
+        let whoop = Whoop::with_base_url(server.url(), "test-token".to_string());
+
+        assert_eq!(whoop.authorize(), AuthStatus::Authorized);
+        profile.assert();
+    }
+
+    #[test]
+    fn test_whoop_fetch_maps_records() {
+        let mut server = Server::new();
+        let sleep_page_2 = server.mock("GET", "/developer/v2/activity/sleep")
+            .match_query(Matcher::UrlEncoded("nextToken".into(), "page-2".into()))
+            .with_body(fixture("sleep_page2"))
+            .create();
+        let cycle = mock_collection(&mut server, "cycle", fixture("cycle"));
+        let recovery = mock_collection(&mut server, "recovery", fixture("recovery"));
+        let sleep = mock_collection(&mut server, "activity/sleep", fixture("sleep"));
+        let workout = mock_collection(&mut server, "activity/workout", fixture("workout"));
+        let whoop = Whoop::with_base_url(server.url(), "test-token".to_string());
+
+        let rows = whoop.get_data(date("2024-03-01"), date("2024-03-02")).unwrap();
+
+        cycle.assert();
+        recovery.assert();
+        sleep.assert();
+        sleep_page_2.assert();
+        workout.assert();
+        assert!(rows.iter().all(|r| r.source == "whoop"));
+        assert!(rows.iter().all(|r| r.importid == rows[0].importid));
+
+        let recovery_score = find(&rows, "whoopRecoveryScore", "2024-03-02");
+        assert_eq!(recovery_score.value, ObservationValue::Number(44.0));
+        assert_eq!(recovery_score.question, "Whoop Recovery Score");
+        assert_eq!(recovery_score.timestamp, 1_709_346_344_774);
+        assert_eq!(find(&rows, "whoopHRV", "2024-03-02").value, ObservationValue::Number(31.813562));
+        assert_eq!(find(&rows, "whoopSpO2", "2024-03-02").value, ObservationValue::Number(95.6875));
+        // unscored recoveries are skipped
+        assert_eq!(rows.iter().filter(|r| r.key == "whoopRecoveryScore").count(), 1);
+
+        assert_eq!(find(&rows, "whoopDayStrain", "2024-03-01").value, ObservationValue::Number(5.2951527));
+        assert_eq!(find(&rows, "whoopSleepPerformance", "2024-03-01").value, ObservationValue::Number(98.0));
+        assert_eq!(find(&rows, "whoopSleepPerformance", "2024-03-02").value, ObservationValue::Number(12.0));
+        // 19:00 UTC on the 2nd is already the 3rd in +05:30
+        assert_eq!(find(&rows, "whoopStrain", "2024-03-03").value, ObservationValue::Number(8.2463));
+        assert_eq!(find(&rows, "whoopDistance", "2024-03-03").question, "Whoop Distance (meters)");
+    }
+
+    #[test]
+    fn test_whoop_get_data_reports_expired_token() {
+        let mut server = Server::new();
+        server.mock("GET", Matcher::Any).with_status(401).create();
+        let whoop = Whoop::with_base_url(server.url(), "expired".to_string());
+
+        let result = whoop.get_data(date("2024-03-01"), date("2024-03-02"));
+        assert!(matches!(result, Err(IntegrationError::Unauthorized(_, AuthStatus::Expired))), "{:?}", result);
+    }
+
+    #[test]
+    fn test_whoop_get_data_reports_rate_limits() {
+        let mut server = Server::new();
+        server.mock("GET", Matcher::Any).with_status(429).create();
+        let whoop = Whoop::with_base_url(server.url(), "test-token".to_string());
+
+        let result = whoop.get_data(date("2024-03-01"), date("2024-03-02"));
+        assert!(matches!(result, Err(IntegrationError::Http(_))), "{:?}", result);
+    }
+
+    #[test]
+    fn test_whoop_get_data_rejects_reversed_range() {
+        let whoop = Whoop::with_base_url("http://localhost".to_string(), "test-token".to_string());
+
+        let result = whoop.get_data(date("2024-03-02"), date("2024-03-01"));
+        assert!(matches!(result, Err(IntegrationError::InvalidDateRange(_, _))), "{:?}", result);
+    }
+}
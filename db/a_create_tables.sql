@@ -22,7 +22,67 @@ CREATE TABLE IF NOT EXISTS raw_data (
     "matcheddate" date,
     "source" text,
     "importedat" timestamp,
-    "importid" text
+    "importid" text,
+    "timezone" text
+);
+
+-- The IANA zone the calendar columns were computed in; NULL for rows the
+-- collector hasn't matched to a local day yet.
+ALTER TABLE raw_data ADD COLUMN IF NOT EXISTS timezone text;
+
+-- The WHOOP script only skipped rows with an identical value, so a re-scored
+-- day can hold several imported rows per source, key and timestamp. Keep the
+-- newest of each so the index below can be built.
+DELETE FROM raw_data r USING raw_data newer
+WHERE r.importid IS NOT NULL AND newer.importid IS NOT NULL
+    AND r.source = newer.source AND r.key = newer.key AND r.timestamp = newer.timestamp
+    AND (COALESCE(newer.importedat, '-infinity'), newer.id) > (COALESCE(r.importedat, '-infinity'), r.id);
+
+-- Imported rows (those with an importid) are unique per source, key and
+-- timestamp so the collector can upsert re-imported date ranges.
+CREATE UNIQUE INDEX IF NOT EXISTS raw_data_source_key_timestamp
+    ON raw_data (source, key, timestamp) WHERE importid IS NOT NULL;
+
+-- Imported rows as they were before a later import overwrote them, so that
+-- import can be rolled back.
+CREATE TABLE IF NOT EXISTS raw_data_history (
+    id SERIAL PRIMARY KEY,
+    timestamp bigint,
+    "yearmonth" int,
+    "yearweek" int,
+    "year" smallint,
+    "quarter" smallint,
+    "month" smallint,
+    "day" smallint,
+    "hour" smallint,
+    "minute" smallint,
+    "week" smallint,
+    "key" text,
+    "question" text,
+    "type" text,
+    "value" text,
+    "matcheddate" date,
+    "source" text,
+    "importedat" timestamp,
+    "importid" text,
+    "replaced_by" text,
+    "replaced_at" timestamp,
+    "timezone" text
+);
+
+ALTER TABLE raw_data_history ADD COLUMN IF NOT EXISTS timezone text;
+
+CREATE INDEX IF NOT EXISTS raw_data_history_replaced_by ON raw_data_history (replaced_by);
+CREATE INDEX IF NOT EXISTS raw_data_importid ON raw_data (importid);
+
+-- The zones you lived in: each applies from starts_at (milliseconds, like
+-- raw_data.timestamp) until the next one. Before the first, the collector's
+-- TIMEZONE applies.
+CREATE TABLE IF NOT EXISTS timezone_history (
+    id SERIAL PRIMARY KEY,
+    starts_at bigint NOT NULL,
+    timezone text NOT NULL,
+    UNIQUE (starts_at)
 );
 
 -- DDL generated by Postico 1.5.8
@@ -38,22 +98,111 @@ CREATE TABLE IF NOT EXISTS last_run (
     UNIQUE (command)
 );
 
+-- High-water mark of the collector's scheduled sync, one row per integration.
+CREATE TABLE IF NOT EXISTS integration_sync (
+    id SERIAL PRIMARY KEY,
+    integration text,
+    synced_until date,
+    last_run bigint,
+    last_error text,
+    UNIQUE (integration)
+);
+
 
 CREATE TABLE IF NOT EXISTS metadata(
     id SERIAL PRIMARY KEY,
     key text,
     value text,
+    is_json BOOLEAN NOT NULL DEFAULT FALSE,
     UNIQUE (key)
 );
 
+-- Set through the viz backend's /api/metadata; JSON values hold structured settings.
+ALTER TABLE metadata ADD COLUMN IF NOT EXISTS is_json BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Values of metadata keys before a write replaced or deleted them, so settings can be reverted.
+-- A NULL value means the key didn't exist yet.
+CREATE TABLE IF NOT EXISTS metadata_history(
+    id SERIAL PRIMARY KEY,
+    key text NOT NULL,
+    value text,
+    is_json BOOLEAN NOT NULL DEFAULT FALSE,
+    replaced_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
 CREATE TABLE IF NOT EXISTS category(
     id SERIAL PRIMARY KEY,
     name text,
     priority int,
     description text,
+    is_private BOOLEAN NOT NULL DEFAULT FALSE,
     UNIQUE (name)
 );
 
+-- Private categories, and their questions, are only served to authenticated callers.
+ALTER TABLE category ADD COLUMN IF NOT EXISTS is_private BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Managed through the viz backend's /api/questions, or loaded by db/questionDump.py.
+CREATE TABLE IF NOT EXISTS questions (
+    key VARCHAR(255),
+    question VARCHAR(255),
+    question_type VARCHAR(255),
+    max_value int,
+    min_value int,
+    is_visible_in_visualizer BOOLEAN,
+    buttons VARCHAR(255),
+    category VARCHAR(255),
+    display_name VARCHAR(255),
+    is_positive BOOLEAN,
+    is_reverse BOOLEAN,
+    cadence VARCHAR(255),
+    graph_type VARCHAR(255),
+    is_private BOOLEAN NOT NULL DEFAULT FALSE,
+    is_archived BOOLEAN NOT NULL DEFAULT FALSE,
+    replies TEXT,
+    command VARCHAR(255),
+    position int NOT NULL DEFAULT 0,
+    priority int NOT NULL DEFAULT 0
+);
+
+ALTER TABLE questions ADD COLUMN IF NOT EXISTS is_private BOOLEAN NOT NULL DEFAULT FALSE;
+-- Archived questions are no longer shown or asked; their raw_data is kept.
+ALTER TABLE questions ADD COLUMN IF NOT EXISTS is_archived BOOLEAN NOT NULL DEFAULT FALSE;
+-- Where the question sits in lifesheet.json, so the catalogue can be exported as one.
+ALTER TABLE questions ADD COLUMN IF NOT EXISTS replies TEXT;
+ALTER TABLE questions ADD COLUMN IF NOT EXISTS command VARCHAR(255);
+ALTER TABLE questions ADD COLUMN IF NOT EXISTS position int NOT NULL DEFAULT 0;
+-- Order of the question within its category in the visualizer.
+ALTER TABLE questions ADD COLUMN IF NOT EXISTS priority int NOT NULL DEFAULT 0;
+CREATE UNIQUE INDEX IF NOT EXISTS questions_key ON questions (key);
+
+-- The commands of lifesheet.json, which the telegram bot schedules.
+CREATE TABLE IF NOT EXISTS commands (
+    name VARCHAR(255),
+    description VARCHAR(255),
+    schedule VARCHAR(255)
+);
+
+-- Targets for questions, e.g. count >= 3 per week; streaks are computed from raw_data.
+CREATE TABLE IF NOT EXISTS goals (
+    id SERIAL PRIMARY KEY,
+    key VARCHAR(255) NOT NULL,
+    aggregate VARCHAR(16) NOT NULL DEFAULT 'count',
+    op VARCHAR(2) NOT NULL DEFAULT '>=',
+    target DOUBLE PRECISION NOT NULL,
+    period VARCHAR(16) NOT NULL DEFAULT 'day'
+);
+
+-- Named places that location fixes are matched to, for grouping days by city or country.
+CREATE TABLE IF NOT EXISTS places (
+    id SERIAL PRIMARY KEY,
+    city text NOT NULL,
+    country text NOT NULL,
+    lat DOUBLE PRECISION NOT NULL,
+    lng DOUBLE PRECISION NOT NULL,
+    radius_km DOUBLE PRECISION NOT NULL DEFAULT 30
+);
+
 INSERT INTO category (name, priority, description) VALUES
 ('Mental Health', 1, 'Health and wellbeing'),
 ('Physical Health', 2, 'Health and wellbeing'),
@@ -61,6 +210,7 @@ INSERT INTO category (name, priority, description) VALUES
 ('Productivity', 4, 'Work and hobbies'),
 ('Hobbies', 5, 'Work and hobbies'),
 ('Social', 6, 'Relationships')
+ON CONFLICT (name) DO NOTHING;
 
 -- View needed for metrics
 
@@ -7,7 +7,7 @@ import requests
 
 
 class Question:
-    def __init__(self, key, question, type, maxValue, minValue, isVisibleInVisualizer, buttons, category, displayName, isPositive, isReverse, cadence, graphType):
+    def __init__(self, key, question, type, maxValue, minValue, isVisibleInVisualizer, buttons, category, displayName, isPositive, isReverse, cadence, graphType, isPrivate):
         self.key = key
         self.question = question
         self.questionType = type
@@ -21,6 +21,7 @@ class Question:
         self.isReverse = isReverse
         self.cadence = cadence
         self.graphType = graphType
+        self.isPrivate = isPrivate
 
     @classmethod
     def from_json(cls, data):
@@ -72,17 +73,22 @@ class Question:
                     max_value = 0
                     min_value = 0
 
+            if 'isPrivate' in data:
+                is_private = data['isPrivate']
+            else:
+                is_private = False
+
             if 'isVisibleInVisualizer' in data:
                 is_visible_in_visualizer = data['isVisibleInVisualizer']
             else:
                 is_visible_in_visualizer = False
 
-            return cls(key, question, questions_type, max_value, min_value, is_visible_in_visualizer, buttons, category, display_name, is_positive, is_reverse, cadence, graph_type)
+            return cls(key, question, questions_type, max_value, min_value, is_visible_in_visualizer, buttons, category, display_name, is_positive, is_reverse, cadence, graph_type, is_private)
         except KeyError:
             raise ValueError('Invalid data structure')
 
     def __repr__(self):
-        return f"Question(key={self.key}, question={self.question}, type={self.questionType}, maxValue={self.maxValue}, minValue={self.minValue}, isVisibleInVisualizer={self.isVisibleInVisualizer}, options={self.buttons}, category={self.category}, displayName={self.displayName}, isPositive={self.isPositive}, isReverse={self.isReverse}, cadence={self.cadence}, graphType={self.graphType})"
+        return f"Question(key={self.key}, question={self.question}, type={self.questionType}, maxValue={self.maxValue}, minValue={self.minValue}, isVisibleInVisualizer={self.isVisibleInVisualizer}, options={self.buttons}, category={self.category}, displayName={self.displayName}, isPositive={self.isPositive}, isReverse={self.isReverse}, cadence={self.cadence}, graphType={self.graphType}, isPrivate={self.isPrivate})"
 
 
 class Command:
@@ -117,11 +123,15 @@ commands = [Command.from_json(data, name) for name in data]
 for command in commands:
     # print(data[command.name]['questions'])
     # if question type not header
-    commandQuestions = ([Question.from_json(
-        question) for question in data[command.name]['questions'] if question['type'] != 'header'])
-
-    for question in commandQuestions:
-        questions.append(question)
+    for position, question in enumerate(data[command.name]['questions']):
+        if question['type'] == 'header':
+            continue
+        item = Question.from_json(question)
+        # Kept so the viz backend can export the catalogue as lifesheet.json again
+        item.command = command.name
+        item.position = position
+        item.replies = json.dumps(question['replies']) if 'replies' in question else None
+        questions.append(item)
 
 # exit()
 # Connect to the database
@@ -132,21 +142,33 @@ cursor = conn.cursor()
 # Create the table if it doesn't exist
 table_name = 'questions'
 # create_table_query = f"CREATE TABLE IF NOT EXISTS {table_name} (column1 key, column2 question, column3 type, column4 maxValue, column5 minValue, column6 isVisibleInVisualizer, column7 buttons);"
-create_table_query = f"CREATE TABLE IF NOT EXISTS {table_name} (key VARCHAR(255), question VARCHAR(255), question_type VARCHAR(255), max_value int, min_value int, is_visible_in_visualizer BOOLEAN, buttons VARCHAR(255), category VARCHAR(255), display_name VARCHAR(255), is_positive BOOLEAN, is_reverse BOOLEAN, cadence VARCHAR(255), graph_type VARCHAR(255));"
+create_table_query = f"CREATE TABLE IF NOT EXISTS {table_name} (key VARCHAR(255), question VARCHAR(255), question_type VARCHAR(255), max_value int, min_value int, is_visible_in_visualizer BOOLEAN, buttons VARCHAR(255), category VARCHAR(255), display_name VARCHAR(255), is_positive BOOLEAN, is_reverse BOOLEAN, cadence VARCHAR(255), graph_type VARCHAR(255), is_private BOOLEAN NOT NULL DEFAULT FALSE, is_archived BOOLEAN NOT NULL DEFAULT FALSE, replies TEXT, command VARCHAR(255), position int NOT NULL DEFAULT 0);"
 cursor.execute(create_table_query)
+cursor.execute(f"ALTER TABLE {table_name} ADD COLUMN IF NOT EXISTS is_private BOOLEAN NOT NULL DEFAULT FALSE;")
+cursor.execute(f"ALTER TABLE {table_name} ADD COLUMN IF NOT EXISTS is_archived BOOLEAN NOT NULL DEFAULT FALSE;")
+cursor.execute(f"ALTER TABLE {table_name} ADD COLUMN IF NOT EXISTS replies TEXT;")
+cursor.execute(f"ALTER TABLE {table_name} ADD COLUMN IF NOT EXISTS command VARCHAR(255);")
+cursor.execute(f"ALTER TABLE {table_name} ADD COLUMN IF NOT EXISTS position int NOT NULL DEFAULT 0;")
+cursor.execute(f"ALTER TABLE {table_name} ADD COLUMN IF NOT EXISTS priority int NOT NULL DEFAULT 0;")
+cursor.execute(f"CREATE UNIQUE INDEX IF NOT EXISTS questions_key ON {table_name} (key);")
 conn.commit()
 
-# Clear the table if it exists
-clear_table_query = f"DELETE FROM {table_name};"
-cursor.execute(clear_table_query)
-conn.commit()
-
+# Upsert by key rather than clearing the table, so questions created through the
+# viz backend's /api/questions, archived flags and the visualizer's priority
+# order survive a reload. The viz backend's POST /api/lifesheet/import does the
+# same and is the preferred way to load lifesheet.json.
 for item in questions:
     # insert_query = f"INSERT INTO {table_name} VALUES ({item.key}, {item.question}, {item.type}, {item.maxValue}, {item.minValue}, {item.isVisibleInVisualizer}, {item.buttons});"
     # insert_query = f"INSERT INTO {table_name} VALUES ('{item.key}', '{item.question}', '{item.type}', '{item.maxValue}', '{item.minValue}', '{item.isVisibleInVisualizer}', '{item.buttons}');"
-    insert_query = f"INSERT INTO {table_name} VALUES(%s, %s, %s, %s, %s, %s, %s, %s, %s, %s, %s, %s, %s);"
+    insert_query = f"""INSERT INTO {table_name} (key, question, question_type, max_value, min_value, is_visible_in_visualizer, buttons, category, display_name, is_positive, is_reverse, cadence, graph_type, is_private, replies, command, position) VALUES(%s, %s, %s, %s, %s, %s, %s, %s, %s, %s, %s, %s, %s, %s, %s, %s, %s)
+        ON CONFLICT (key) DO UPDATE SET question = EXCLUDED.question, question_type = EXCLUDED.question_type,
+            max_value = EXCLUDED.max_value, min_value = EXCLUDED.min_value, is_visible_in_visualizer = EXCLUDED.is_visible_in_visualizer,
+            buttons = EXCLUDED.buttons, category = EXCLUDED.category, display_name = EXCLUDED.display_name,
+            is_positive = EXCLUDED.is_positive, is_reverse = EXCLUDED.is_reverse, cadence = EXCLUDED.cadence,
+            graph_type = EXCLUDED.graph_type, is_private = EXCLUDED.is_private, replies = EXCLUDED.replies,
+            command = EXCLUDED.command, position = EXCLUDED.position;"""
     cursor.execute(insert_query, (item.key, item.question, item.questionType,
-                   item.maxValue, item.minValue, item.isVisibleInVisualizer, item.buttons, item.category, item.displayName, item.isPositive, item.isReverse, item.cadence, item.graphType))
+                   item.maxValue, item.minValue, item.isVisibleInVisualizer, item.buttons, item.category, item.displayName, item.isPositive, item.isReverse, item.cadence, item.graphType, item.isPrivate, item.replies, item.command, item.position))
 
 
 table_name = 'commands'
@@ -0,0 +1,100 @@
+use super::{best_split, find_anomalies, find_changepoints, noise, AnomalyMethod, AnomalyQuery, ChangePointQuery, Deviation};
+
+fn series(values: &[f64]) -> Vec<(i64, f64)> {
+	values.iter().enumerate().map(|(i, value)| (i as i64 * 1000, *value)).collect()
+}
+
+fn anomaly_query(method: AnomalyMethod, threshold: f64) -> AnomalyQuery {
+	AnomalyQuery { method, threshold, window: 5, from: None, to: None }
+}
+
+const CHANGEPOINT_QUERY: ChangePointQuery = ChangePointQuery { threshold: 4.0, min_size: 3, from: None, to: None };
+
+/// Ten values around 2, then ten around 5.
+const STEP: [f64; 20] = [
+	2.1, 1.9, 2.0, 2.2, 1.8, 2.0, 2.1, 1.9, 2.0, 2.0,
+	5.0, 4.8, 5.2, 5.1, 4.9, 5.0, 5.1, 4.9, 5.0, 5.0,
+];
+
+#[test]
+fn model_anomaly_best_split() {
+	// -- ACTION
+	let split = best_split(&[1.0, 1.0, 1.0, 1.0, 5.0, 5.0, 5.0, 5.0], 1.0, 2);
+
+	// -- CHECK - a shift of 4 with 4 values either side is 4 / sqrt(1/4 + 1/4) standard errors
+	let (index, score) = split.unwrap();
+	assert_eq!(4, index);
+	assert!((score - 4.0 / 0.5f64.sqrt()).abs() < 1e-12, "{}", score);
+	assert_eq!(None, best_split(&[1.0, 5.0, 5.0], 1.0, 2), "too short for two regimes");
+}
+
+#[test]
+fn model_anomaly_changepoint_at_step() {
+	// -- ACTION
+	let page = find_changepoints(&series(&STEP), &CHANGEPOINT_QUERY, true);
+
+	// -- CHECK
+	assert_eq!(1, page.data.len(), "change points");
+	let changepoint = &page.data[0];
+	assert_eq!(10_000, changepoint.timestamp, "first value of the new level");
+	assert!((changepoint.mean_before - 2.0).abs() < 1e-9, "{}", changepoint.mean_before);
+	assert!((changepoint.mean_after - 5.0).abs() < 1e-9, "{}", changepoint.mean_after);
+	assert!(changepoint.score > CHANGEPOINT_QUERY.threshold);
+	assert_eq!(Deviation::Good, changepoint.deviation);
+
+	assert_eq!(2, page.segments.len());
+	assert_eq!((0, 9_000, 10), (page.segments[0].start, page.segments[0].end, page.segments[0].count));
+	assert_eq!((10_000, 19_000, 10), (page.segments[1].start, page.segments[1].end, page.segments[1].count));
+}
+
+#[test]
+fn model_anomaly_constant_series() {
+	// -- FIXTURE
+	let constant = series(&[3.0; 12]);
+
+	// -- CHECK - nothing to score, and nothing divides by zero
+	assert_eq!(None, noise(&[3.0; 12]));
+	assert!(find_anomalies(&constant, &anomaly_query(AnomalyMethod::ZScore, 0.1), true).is_empty());
+	assert!(find_anomalies(&constant, &anomaly_query(AnomalyMethod::RollingMad, 0.1), true).is_empty());
+	let page = find_changepoints(&constant, &CHANGEPOINT_QUERY, true);
+	assert!(page.data.is_empty());
+	assert_eq!(1, page.segments.len());
+	assert_eq!(12, page.segments[0].count);
+
+	// -- CHECK - nor does an empty one
+	assert!(find_anomalies(&[], &anomaly_query(AnomalyMethod::RollingMad, 0.1), true).is_empty());
+	assert!(find_changepoints(&[], &CHANGEPOINT_QUERY, true).segments.is_empty());
+}
+
+#[test]
+fn model_anomaly_rolling_mad_window() {
+	// -- FIXTURE
+	let values = series(&[10.0, 11.0, 9.0, 10.0, 11.0, 9.0, 10.0, 30.0, 10.0]);
+
+	// -- ACTION
+	let anomalies = find_anomalies(&values, &anomaly_query(AnomalyMethod::RollingMad, 0.5), true);
+
+	// -- CHECK - the first five values have no full window; the spike doesn't
+	// move the median of the window after it
+	let timestamps: Vec<i64> = anomalies.iter().map(|anomaly| anomaly.timestamp).collect();
+	assert_eq!(vec![5_000, 7_000], timestamps);
+	// 9 is one MAD below the median of 10, 30 is twenty above
+	assert!((anomalies[0].score + 1.0 / 1.4826).abs() < 1e-9, "{}", anomalies[0].score);
+	assert!((anomalies[1].score - 20.0 / 1.4826).abs() < 1e-9, "{}", anomalies[1].score);
+}
+
+#[test]
+fn model_anomaly_deviation_follows_is_positive() {
+	// -- FIXTURE
+	let spike = series(&[10.0, 11.0, 9.0, 10.0, 11.0, 9.0, 10.0, 30.0, 2.0]);
+	let query = anomaly_query(AnomalyMethod::RollingMad, 3.0);
+
+	// -- ACTION
+	let higher_is_good = find_anomalies(&spike, &query, true);
+	let lower_is_good = find_anomalies(&spike, &query, false);
+
+	// -- CHECK - a spike in mood is good news, a spike in stress isn't
+	assert_eq!(vec![Deviation::Good, Deviation::Bad], higher_is_good.iter().map(|anomaly| anomaly.deviation).collect::<Vec<_>>());
+	assert_eq!(vec![Deviation::Bad, Deviation::Good], lower_is_good.iter().map(|anomaly| anomaly.deviation).collect::<Vec<_>>());
+	assert_eq!(Deviation::Bad, find_changepoints(&series(&STEP), &CHANGEPOINT_QUERY, false).data[0].deviation);
+}
@@ -6,8 +6,10 @@ async fn model_db_init_db() -> Result<(), Box<dyn std::error::Error>> {
 	let db = init_db().await?;
 
 	// CHECK
-	let result = sqlx::query("SELECT * from todo").fetch_all(&db).await?;
-	assert_eq!(2, result.len(), "number of seed todos");
+	let result = sqlx::query("SELECT name FROM category WHERE name IN ('Mental Health', 'Physical Health', 'Workout', 'Productivity', 'Hobbies', 'Social')")
+		.fetch_all(&db)
+		.await?;
+	assert_eq!(6, result.len(), "number of seed categories");
 
 	Ok(())
 }
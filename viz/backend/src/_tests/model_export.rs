@@ -0,0 +1,37 @@
+use super::{Export, ExportQuery};
+use crate::model::db::init_db;
+use crate::security::UserCtx;
+
+#[tokio::test]
+async fn model_export_leaves_location_fixes_out_for_anonymous_callers() -> Result<(), Box<dyn std::error::Error>> {
+	// -- FIXTURE
+	let db = init_db().await?;
+	let (fix_key, note_key) = ("modelExportFix", "modelExportNote");
+	let ids: Vec<(i32,)> = sqlx::query_as(
+		"INSERT INTO raw_data (timestamp, key, type, value, matcheddate, source)
+		VALUES (1709280000000, $1, 'location', '52.52,13.405', '2024-03-01', 'test'),
+			(1709280000000, $2, NULL, 'Went hiking', '2024-03-01', 'test'),
+			(1709280000000, 'locationLat', 'number', '52.52', '2024-03-01', 'test')
+		RETURNING id",
+	)
+	.bind(fix_key)
+	.bind(note_key)
+	.fetch_all(&db)
+	.await?;
+	let ids: Vec<i32> = ids.into_iter().map(|(id,)| id).collect();
+	let query = ExportQuery { keys: vec![fix_key.to_string(), note_key.to_string(), "locationLat".to_string()], from: None, to: None };
+
+	// -- ACTION
+	let anonymous = Export::batch(&db, None, &query, 0).await;
+	let authenticated = Export::batch(&db, Some(&UserCtx), &query, 0).await;
+	sqlx::query("DELETE FROM raw_data WHERE id = ANY($1)").bind(&ids).execute(&db).await?;
+
+	// -- CHECK
+	let keys = |rows: Vec<super::ExportRow>| rows.into_iter().filter_map(|row| row.key).collect::<Vec<_>>();
+	assert_eq!(vec![note_key.to_string()], keys(anonymous?));
+	let authenticated = keys(authenticated?);
+	assert!(authenticated.contains(&fix_key.to_string()));
+	assert!(authenticated.contains(&"locationLat".to_string()));
+
+	Ok(())
+}
@@ -0,0 +1,133 @@
+use super::{periods, state, streaks, GoalOp, GoalSpec, GoalState};
+use crate::model::raw_data_dao::{AggregateFn, Bucket};
+use chrono::NaiveDate;
+
+fn date(value: &str) -> NaiveDate {
+	NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+}
+
+fn values(days: &[(&str, f64)]) -> Vec<(NaiveDate, f64)> {
+	days.iter().map(|(day, value)| (date(day), *value)).collect()
+}
+
+/// "count >= 3 per week", e.g. working out three times a week.
+const THREE_A_WEEK: GoalSpec = GoalSpec { func: AggregateFn::Count, op: GoalOp::Ge, target: 3.0, period: Bucket::Week };
+
+#[test]
+fn model_goals_periods_split_by_week() {
+	// -- FIXTURE - weeks start on Monday 2024-02-26, 03-04 and 03-11
+	let values = values(&[
+		("2024-02-27", 1.0), ("2024-02-29", 1.0), ("2024-03-03", 1.0),
+		("2024-03-04", 1.0), ("2024-03-05", 0.0), ("2024-03-06", 1.0), ("2024-03-10", 1.0),
+		("2024-03-11", 1.0),
+	]);
+
+	// -- ACTION
+	let periods = periods(&THREE_A_WEEK, &values, date("2024-03-13"));
+
+	// -- CHECK
+	let starts: Vec<NaiveDate> = periods.iter().map(|period| period.start).collect();
+	assert_eq!(vec![date("2024-02-26"), date("2024-03-04"), date("2024-03-11")], starts);
+	// Sunday 03-03 still belongs to the first week, and the 0 isn't counted
+	assert_eq!(vec![Some(3.0), Some(3.0), Some(1.0)], periods.iter().map(|period| period.value).collect::<Vec<_>>());
+	assert_eq!(vec![3, 4, 1], periods.iter().map(|period| period.days).collect::<Vec<_>>());
+	assert_eq!(vec![true, true, false], periods.iter().map(|period| period.met).collect::<Vec<_>>());
+}
+
+#[test]
+fn model_goals_periods_without_values() {
+	// -- ACTION
+	let periods = periods(&THREE_A_WEEK, &[], date("2024-03-13"));
+
+	// -- CHECK - only the current period, with nothing counted yet
+	assert_eq!(1, periods.len());
+	assert_eq!(date("2024-03-11"), periods[0].start);
+	assert_eq!(Some(0.0), periods[0].value);
+	assert!(!periods[0].met);
+}
+
+#[test]
+fn model_goals_streaks_with_unmet_current_period() {
+	// -- FIXTURE - met, missed, met, met, and a current week not met yet
+	let values = values(&[
+		("2024-02-12", 1.0), ("2024-02-13", 1.0), ("2024-02-14", 1.0),
+		("2024-02-19", 1.0),
+		("2024-02-26", 1.0), ("2024-02-27", 1.0), ("2024-02-28", 1.0),
+		("2024-03-04", 1.0), ("2024-03-05", 1.0), ("2024-03-06", 1.0),
+		("2024-03-11", 1.0),
+	]);
+	let periods = periods(&THREE_A_WEEK, &values, date("2024-03-13"));
+
+	// -- ACTION
+	let (current, longest) = streaks(&periods);
+
+	// -- CHECK - the week in progress neither extends nor breaks the streak
+	assert_eq!(5, periods.len());
+	assert_eq!(2, current);
+	assert_eq!(2, longest);
+}
+
+#[test]
+fn model_goals_streaks_with_met_current_period() {
+	// -- FIXTURE
+	let values = values(&[
+		("2024-02-26", 1.0), ("2024-02-27", 1.0), ("2024-02-28", 1.0),
+		("2024-03-11", 1.0), ("2024-03-12", 1.0), ("2024-03-13", 1.0),
+	]);
+	let periods = periods(&THREE_A_WEEK, &values, date("2024-03-13"));
+
+	// -- ACTION
+	let (current, longest) = streaks(&periods);
+
+	// -- CHECK - the empty week of 03-04 broke the first streak
+	assert_eq!((1, 1), (current, longest));
+	assert_eq!(GoalState::Met, state(&THREE_A_WEEK, periods.last().unwrap(), date("2024-03-13"), true));
+}
+
+#[test]
+fn model_goals_state_of_weekly_count() {
+	// -- FIXTURE - one workout on Monday 03-11, two more needed by Sunday 03-17
+	let current = |today: &str| {
+		let periods = periods(&THREE_A_WEEK, &values(&[("2024-03-11", 1.0)]), date(today));
+		periods.last().unwrap().clone()
+	};
+
+	// -- CHECK
+	assert_eq!(GoalState::OnTrack, state(&THREE_A_WEEK, &current("2024-03-13"), date("2024-03-13"), false));
+	// Saturday and Sunday are left, and both are needed
+	assert_eq!(GoalState::AtRisk, state(&THREE_A_WEEK, &current("2024-03-16"), date("2024-03-16"), false));
+	assert_eq!(GoalState::Missed, state(&THREE_A_WEEK, &current("2024-03-17"), date("2024-03-17"), false));
+	// Saturday already logged without a workout leaves only Sunday
+	assert_eq!(GoalState::Missed, state(&THREE_A_WEEK, &current("2024-03-16"), date("2024-03-16"), true));
+}
+
+#[test]
+fn model_goals_state_of_daily_average() {
+	// -- FIXTURE - "avg >= 4 per day", e.g. a mood score
+	let spec = GoalSpec { func: AggregateFn::Avg, op: GoalOp::Ge, target: 4.0, period: Bucket::Day };
+	let today = date("2024-03-13");
+	let not_logged = periods(&spec, &values(&[("2024-03-12", 5.0)]), today);
+	let logged_low = periods(&spec, &values(&[("2024-03-12", 5.0), ("2024-03-13", 2.0)]), today);
+
+	// -- CHECK
+	assert_eq!(2, not_logged.len());
+	assert_eq!(None, not_logged[1].value);
+	assert_eq!(GoalState::AtRisk, state(&spec, &not_logged[1], today, false));
+	assert_eq!(GoalState::Missed, state(&spec, &logged_low[1], today, true));
+	// today only ends the streak once the day is over
+	assert_eq!((1, 1), streaks(&not_logged));
+	assert_eq!((1, 1), streaks(&logged_low));
+}
+
+#[test]
+fn model_goals_state_of_limits() {
+	// -- FIXTURE - "count <= 1 per week", e.g. drinking at most once a week
+	let spec = GoalSpec { func: AggregateFn::Count, op: GoalOp::Le, target: 1.0, period: Bucket::Week };
+	let today = date("2024-03-13");
+	let once = periods(&spec, &values(&[("2024-03-11", 1.0)]), today);
+	let twice = periods(&spec, &values(&[("2024-03-11", 1.0), ("2024-03-12", 1.0)]), today);
+
+	// -- CHECK - a count can't go down again
+	assert_eq!(GoalState::Met, state(&spec, &once[0], today, false));
+	assert_eq!(GoalState::Missed, state(&spec, &twice[0], today, false));
+}
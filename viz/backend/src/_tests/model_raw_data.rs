@@ -0,0 +1,166 @@
+use super::{parse_rows, validate_value, DateColumns, LocalMoment, RawDataRow, RawValue, ValueType};
+use crate::model::viz_questions_dao::VizQuestionsObj;
+use chrono::NaiveDate;
+use chrono_tz::Tz;
+use serde_json::json;
+
+fn question(question_type: &str, min_value: Option<i32>, max_value: Option<i32>, buttons: Option<&str>) -> VizQuestionsObj {
+	VizQuestionsObj {
+		key: "fixture".to_string(),
+		question: "Fixture?".to_string(),
+		question_type: question_type.to_string(),
+		max_value,
+		min_value,
+		buttons: buttons.map(str::to_string),
+		is_positive: true,
+		is_reverse: false,
+		display_name: "Fixture".to_string(),
+		graph_type: "line".to_string(),
+		cadence: "daily".to_string(),
+		priority: 0,
+		is_private: false,
+	}
+}
+
+fn row(id: i32, value: &str, data_type: Option<&str>) -> RawDataRow {
+	RawDataRow { id, timestamp: 1_700_000_000_000 + id as i64, value: value.to_string(), data_type: data_type.map(str::to_string) }
+}
+
+#[test]
+fn model_raw_data_parse_boolean() {
+	// -- CHECK
+	assert_eq!(Ok(RawValue::Boolean(true)), ValueType::Boolean.parse("1"));
+	assert_eq!(Ok(RawValue::Boolean(false)), ValueType::Boolean.parse("0"));
+	assert_eq!(Ok(RawValue::Boolean(true)), ValueType::Boolean.parse(" TRUE "));
+	assert_eq!(Ok(RawValue::Boolean(false)), ValueType::Boolean.parse("no"));
+	assert!(ValueType::Boolean.parse("2").is_err());
+	assert!(ValueType::Boolean.parse("").is_err());
+}
+
+#[test]
+fn model_raw_data_parse_range() {
+	// -- FIXTURE
+	let range = ValueType::Range { min: Some(0), max: Some(5) };
+
+	// -- CHECK
+	assert_eq!(Ok(RawValue::Number(0.0)), range.parse("0"));
+	assert_eq!(Ok(RawValue::Number(5.0)), range.parse(" 5 "));
+	assert!(range.parse("6").is_err(), "above max");
+	assert!(range.parse("-1").is_err(), "below min");
+	assert!(range.parse("2.5").is_err(), "not a whole number");
+	// questions without buttons store 0..0, which doesn't bound anything
+	assert_eq!(Ok(RawValue::Number(42.0)), ValueType::Range { min: Some(0), max: Some(0) }.parse("42"));
+}
+
+#[test]
+fn model_raw_data_parse_number() {
+	// -- CHECK
+	assert_eq!(Ok(RawValue::Number(-3.25)), ValueType::Number.parse("-3.25"));
+	assert_eq!(Ok(RawValue::Number(1500.0)), ValueType::Number.parse("1.5e3"));
+	assert!(ValueType::Number.parse("abc").is_err());
+	assert!(ValueType::Number.parse("NaN").is_err());
+	assert!(ValueType::Number.parse("inf").is_err());
+}
+
+#[test]
+fn model_raw_data_parse_location_and_text() {
+	// -- CHECK - location
+	assert_eq!(Ok(RawValue::Location { lat: 52.52, lng: 13.405 }), ValueType::Location.parse("52.52, 13.405"));
+	// the telegram bot stores lat and lng as separate rows
+	assert_eq!(Ok(RawValue::Number(52.52)), ValueType::Location.parse("52.52"));
+	assert!(ValueType::Location.parse("91,0").is_err());
+	assert!(ValueType::Location.parse("0,181").is_err());
+
+	// -- CHECK - text is kept as is, whitespace included
+	assert_eq!(Ok(RawValue::Text(" Went hiking ".to_string())), ValueType::Text.parse(" Went hiking "));
+	assert_eq!(ValueType::Text, ValueType::from_name("unknown"));
+}
+
+#[test]
+fn model_raw_data_parse_rows_collects_invalid() {
+	// -- FIXTURE
+	let rows = vec![row(1, "3", None), row(2, "abc", None), row(3, "7", None)];
+
+	// -- ACTION
+	let (data, invalid) = parse_rows(rows, Some(ValueType::Range { min: Some(0), max: Some(5) }));
+
+	// -- CHECK
+	assert_eq!(1, data.len());
+	assert_eq!(RawValue::Number(3.0), data[0].value);
+	assert_eq!(vec![2, 3], invalid.iter().map(|row| row.id).collect::<Vec<_>>());
+	assert_eq!("abc", invalid[0].value);
+	assert_eq!("'abc' is not a number", invalid[0].error);
+}
+
+#[test]
+fn model_raw_data_parse_rows_without_question_uses_row_type() {
+	// -- FIXTURE
+	let rows = vec![row(1, "1", Some("boolean")), row(2, "12.5", Some("number")), row(3, "12.5", None)];
+
+	// -- ACTION
+	let (data, invalid) = parse_rows(rows, None);
+
+	// -- CHECK
+	assert!(invalid.is_empty());
+	assert_eq!(RawValue::Boolean(true), data[0].value);
+	assert_eq!(RawValue::Number(12.5), data[1].value);
+	assert_eq!(RawValue::Text("12.5".to_string()), data[2].value);
+}
+
+#[test]
+fn model_raw_data_validate_value() {
+	// -- FIXTURE
+	let boolean = question("boolean", None, None, None);
+	let range = question("range", Some(0), Some(5), Some(r#"{"0": "Bad", "5": "Great"}"#));
+	let location = question("location", None, None, None);
+	let text = question("text", None, None, None);
+
+	// -- CHECK - values are stored in their canonical form
+	assert_eq!(Ok("1".to_string()), validate_value(&boolean, &json!(true)));
+	assert_eq!(Ok("0".to_string()), validate_value(&boolean, &json!("false")));
+	assert_eq!(Ok("5".to_string()), validate_value(&range, &json!(5)));
+	assert_eq!(Ok("52.52,13.405".to_string()), validate_value(&location, &json!({"lat": 52.52, "lng": 13.405})));
+	assert_eq!(Ok("Went hiking".to_string()), validate_value(&text, &json!("Went hiking")));
+
+	// -- CHECK - rejected values
+	assert!(validate_value(&range, &json!(6)).is_err(), "out of range");
+	assert_eq!(Err("'3' is not one of 0, 5".to_string()), validate_value(&range, &json!(3)));
+	assert!(validate_value(&boolean, &json!("maybe")).is_err());
+	assert!(validate_value(&location, &json!({"lat": 52.52})).is_err());
+	assert!(validate_value(&text, &json!(null)).is_err());
+}
+
+#[test]
+fn model_raw_data_local_moment() {
+	// -- FIXTURE - 2024-03-31 23:30 UTC, the night Berlin moves to summer time
+	let timestamp = 1_711_927_800_000;
+	let date = |day: u32| NaiveDate::from_ymd_opt(2024, 4, day).unwrap();
+
+	// -- ACTION
+	let utc = LocalMoment::new(timestamp, Tz::UTC, 0);
+	let berlin = LocalMoment::new(timestamp, Tz::Europe__Berlin, 0);
+	let late_berlin = LocalMoment::new(timestamp, Tz::Europe__Berlin, 4);
+
+	// -- CHECK
+	assert_eq!((NaiveDate::from_ymd_opt(2024, 3, 31).unwrap(), 23, 30), (utc.matcheddate, utc.hour, utc.minute));
+	assert_eq!((date(1), 1, 30), (berlin.matcheddate, berlin.hour, berlin.minute));
+	assert_eq!("Europe/Berlin", berlin.timezone.name());
+	// before the day boundary the entry still counts for the evening before
+	assert_eq!(NaiveDate::from_ymd_opt(2024, 3, 31).unwrap(), late_berlin.matcheddate);
+	assert_eq!(1, late_berlin.hour);
+}
+
+#[test]
+fn model_raw_data_date_columns_follow_matcheddate() {
+	// -- FIXTURE
+	let moment = LocalMoment::new(1_711_927_800_000, Tz::Europe__Berlin, 4);
+
+	// -- ACTION
+	let columns = DateColumns::new(&moment, moment.matcheddate);
+
+	// -- CHECK
+	assert_eq!(
+		DateColumns { yearmonth: 202403, yearweek: 202413, year: 2024, quarter: 1, month: 3, day: 31, hour: 1, minute: 30, week: 13 },
+		columns
+	);
+}
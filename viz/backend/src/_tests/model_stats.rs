@@ -0,0 +1,78 @@
+use super::{correlation_p_value, mad, median, pearson, ranks, spearman, std_dev, t_critical};
+
+// Reference values are scipy.stats' pearsonr/spearmanr and Student's t
+// quantiles from scipy.stats.t.ppf.
+const XS: [f64; 5] = [1.0, 2.0, 3.0, 4.0, 5.0];
+const YS: [f64; 5] = [2.0, 4.0, 5.0, 4.0, 5.0];
+
+fn assert_close(expected: f64, actual: Option<f64>, tolerance: f64) {
+	let actual = actual.expect("a value");
+	assert!((expected - actual).abs() < tolerance, "expected {} but got {}", expected, actual);
+}
+
+#[test]
+fn model_stats_pearson() {
+	// -- ACTION
+	let r = pearson(&XS, &YS);
+
+	// -- CHECK
+	assert_close(0.7745966692414834, r, 1e-12);
+	assert_close(0.1240270626575547, correlation_p_value(r.unwrap(), XS.len()), 1e-9);
+	assert_close(-1.0, pearson(&XS, &[5.0, 4.0, 3.0, 2.0, 1.0]), 1e-12);
+}
+
+#[test]
+fn model_stats_pearson_degenerate() {
+	// -- CHECK
+	assert_eq!(None, pearson(&XS, &[3.0; 5]), "constant side");
+	assert_eq!(None, pearson(&XS, &YS[..4]), "different lengths");
+	assert_eq!(None, pearson(&[1.0], &[2.0]), "a single pair");
+	assert_eq!(None, pearson(&[], &[]));
+}
+
+#[test]
+fn model_stats_spearman_with_ties() {
+	// -- CHECK - ties share their average rank
+	assert_eq!(vec![1.0, 2.5, 4.5, 2.5, 4.5], ranks(&YS));
+	assert_eq!(vec![2.0, 3.5, 3.5, 1.0], ranks(&[10.0, 20.0, 20.0, 5.0]));
+
+	// -- ACTION
+	let rho = spearman(&XS, &YS);
+
+	// -- CHECK
+	assert_close(0.7378647873726218, rho, 1e-12);
+	assert_close(0.15461852312844926, correlation_p_value(rho.unwrap(), XS.len()), 1e-9);
+}
+
+#[test]
+fn model_stats_correlation_p_value() {
+	// -- CHECK
+	// one degree of freedom, where the continued fraction converges slowest
+	assert_close(2.0 / 3.0, correlation_p_value(0.5, 3), 1e-9);
+	assert_close(0.10724594805793342, correlation_p_value(0.3, 30), 1e-9);
+	assert_close(1.0, correlation_p_value(0.0, 30), 1e-12);
+	assert_eq!(Some(0.0), correlation_p_value(1.0, 10));
+	assert_eq!(None, correlation_p_value(0.5, 2));
+}
+
+#[test]
+fn model_stats_t_critical() {
+	// -- CHECK
+	assert_close(2.045229642132703, Some(t_critical(0.05, 29.0)), 1e-6);
+	assert_close(2.228138851986274, Some(t_critical(0.05, 10.0)), 1e-6);
+	assert_close(12.706204736174698, Some(t_critical(0.05, 1.0)), 1e-6);
+	assert_close(4.032142983557536, Some(t_critical(0.01, 5.0)), 1e-6);
+}
+
+#[test]
+fn model_stats_median_mad_std_dev() {
+	// -- CHECK
+	assert_eq!(Some(3.0), median(&[5.0, 1.0, 3.0]));
+	assert_eq!(Some(2.5), median(&[4.0, 1.0, 3.0, 2.0]));
+	assert_eq!(None, median(&[]));
+	// deviations from the median 3 are 2, 1, 0, 1 and 97
+	assert_eq!(Some(1.0), mad(&[1.0, 2.0, 3.0, 4.0, 100.0]));
+	assert_eq!(Some(0.0), mad(&[7.0; 4]));
+	assert_close(2.138089935299395, std_dev(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]), 1e-12);
+	assert_eq!(None, std_dev(&[1.0]));
+}
@@ -3,6 +3,7 @@ use std::env;
 use std::sync::Arc;
 use web::start_web;
 mod model;
+mod security;
 mod web;
 
 const DEFAULT_WEB_PORT: u16 = 8080;
@@ -0,0 +1,270 @@
+use super::db::Db;
+use super::raw_data_dao::{RawData, RawDataQuery, RawValue, TimeBound};
+use super::stats;
+use crate::model;
+use crate::security::UserCtx;
+use serde::Serialize;
+
+/// How unusual a value is measured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnomalyMethod {
+	/// Distance from the mean of the whole range, in standard deviations.
+	ZScore,
+	/// Distance from the median of the preceding `window` values, in robust
+	/// standard deviations estimated from their median absolute deviation.
+	RollingMad,
+}
+
+impl AnomalyMethod {
+	pub fn parse(value: &str) -> Option<AnomalyMethod> {
+		match value {
+			"zscore" => Some(AnomalyMethod::ZScore),
+			"mad" => Some(AnomalyMethod::RollingMad),
+			_ => None,
+		}
+	}
+
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			AnomalyMethod::ZScore => "zscore",
+			AnomalyMethod::RollingMad => "mad",
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct AnomalyQuery {
+	pub method: AnomalyMethod,
+	/// Scores at or beyond this, either way, are anomalies.
+	pub threshold: f64,
+	/// Values the rolling median looks back over.
+	pub window: usize,
+	pub from: Option<TimeBound>,
+	pub to: Option<TimeBound>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChangePointQuery {
+	/// How many standard errors the means before and after must differ by.
+	pub threshold: f64,
+	/// Fewest values a regime can have.
+	pub min_size: usize,
+	pub from: Option<TimeBound>,
+	pub to: Option<TimeBound>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Deviation {
+	Good,
+	Bad,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AnomalyObj {
+	pub timestamp: i64,
+	pub value: f64,
+	/// Signed; positive when the value is higher than expected.
+	pub score: f64,
+	/// Whether the deviation is good or bad news, by the question's `is_positive`.
+	pub deviation: Deviation,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangePointObj {
+	/// First value of the new regime.
+	pub timestamp: i64,
+	pub mean_before: f64,
+	pub mean_after: f64,
+	pub score: f64,
+	pub deviation: Deviation,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SegmentObj {
+	pub start: i64,
+	pub end: i64,
+	pub mean: f64,
+	pub count: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangePointPage {
+	pub data: Vec<ChangePointObj>,
+	/// The regimes between change points, oldest first.
+	pub segments: Vec<SegmentObj>,
+}
+
+pub struct Anomalies;
+
+impl Anomalies {
+	/// Rolling windows shorter than this give no score.
+	const MIN_WINDOW: usize = 5;
+	/// MAD × this estimates the standard deviation of normal data.
+	const MAD_SCALE: f64 = 1.4826;
+}
+
+impl Anomalies {
+	/// Unusual values of a key, oldest first.
+	pub async fn detect(db: &Db, utx: Option<&UserCtx>, key: String, query: AnomalyQuery) -> Result<Vec<AnomalyObj>, model::Error> {
+		let (higher_is_good, series) = Self::series(db, utx, key, query.from, query.to).await?;
+		Ok(find_anomalies(&series, &query, higher_is_good))
+	}
+
+	/// Shifts in a key's level, found by binary segmentation: each segment is
+	/// split where the CUSUM statistic of its mean shift peaks, for as long as
+	/// the shift stands out from the noise.
+	pub async fn changepoints(db: &Db, utx: Option<&UserCtx>, key: String, query: ChangePointQuery) -> Result<ChangePointPage, model::Error> {
+		let (higher_is_good, series) = Self::series(db, utx, key, query.from, query.to).await?;
+		Ok(find_changepoints(&series, &query, higher_is_good))
+	}
+
+	/// The numeric values of a key as `RawData::get_by_key` returns them, booleans
+	/// as 1 or 0, and whether a higher value is good news.
+	async fn series(
+		db: &Db,
+		utx: Option<&UserCtx>,
+		key: String,
+		from: Option<TimeBound>,
+		to: Option<TimeBound>,
+	) -> Result<(bool, Vec<(i64, f64)>), model::Error> {
+		let higher_is_good = RawData::readable_question(db, utx, &key).await?.is_none_or(|question| question.is_positive);
+		let page = RawData::get_by_key(db, utx, key, RawDataQuery { from, to, limit: None, after: None }).await?;
+		let series = page.data.into_iter()
+			.filter_map(|row| match row.value {
+				RawValue::Number(value) => Some((row.timestamp, value)),
+				RawValue::Boolean(value) => Some((row.timestamp, value as i32 as f64)),
+				_ => None,
+			})
+			.collect();
+		Ok((higher_is_good, series))
+	}
+}
+
+// region:    Utils
+fn find_anomalies(series: &[(i64, f64)], query: &AnomalyQuery, higher_is_good: bool) -> Vec<AnomalyObj> {
+	let values: Vec<f64> = series.iter().map(|(_, value)| *value).collect();
+	let scores: Vec<Option<f64>> = match query.method {
+		AnomalyMethod::ZScore => match (stats::mean(&values), stats::std_dev(&values)) {
+			(Some(mean), Some(std_dev)) if std_dev > 0.0 => values.iter().map(|value| Some((value - mean) / std_dev)).collect(),
+			_ => vec![None; values.len()],
+		},
+		AnomalyMethod::RollingMad => (0..values.len())
+			.map(|i| {
+				let window = &values[i.saturating_sub(query.window)..i];
+				if window.len() < Anomalies::MIN_WINDOW.min(query.window) {
+					return None;
+				}
+				let median = stats::median(window)?;
+				let spread = stats::mad(window)? * Anomalies::MAD_SCALE;
+				(spread > 0.0).then(|| (values[i] - median) / spread)
+			})
+			.collect(),
+	};
+
+	series.iter().zip(scores)
+		.filter_map(|(&(timestamp, value), score)| {
+			let score = score.filter(|score| score.abs() >= query.threshold)?;
+			Some(AnomalyObj { timestamp, value, score, deviation: deviation(score, higher_is_good) })
+		})
+		.collect()
+}
+
+fn find_changepoints(series: &[(i64, f64)], query: &ChangePointQuery, higher_is_good: bool) -> ChangePointPage {
+	let values: Vec<f64> = series.iter().map(|(_, value)| *value).collect();
+	let min_size = query.min_size.max(1);
+
+	let mut splits = Vec::new();
+	if let Some(sigma) = noise(&values) {
+		let mut pending = vec![(0, values.len())];
+		while let Some((start, end)) = pending.pop() {
+			if let Some((split, score)) = best_split(&values[start..end], sigma, min_size) {
+				if score >= query.threshold {
+					splits.push(start + split);
+					pending.push((start, start + split));
+					pending.push((start + split, end));
+				}
+			}
+		}
+	}
+	splits.sort_unstable();
+
+	let bounds: Vec<usize> = std::iter::once(0).chain(splits.iter().copied()).chain(std::iter::once(values.len())).collect();
+	let segments: Vec<SegmentObj> = bounds.windows(2)
+		.filter(|bound| bound[0] < bound[1])
+		.map(|bound| SegmentObj {
+			start: series[bound[0]].0,
+			end: series[bound[1] - 1].0,
+			mean: stats::mean(&values[bound[0]..bound[1]]).unwrap_or_default(),
+			count: bound[1] - bound[0],
+		})
+		.collect();
+	let sigma = noise(&values).unwrap_or(1.0);
+	let data = segments.windows(2)
+		.map(|pair| {
+			let (before, after) = (&pair[0], &pair[1]);
+			let score = (after.mean - before.mean) / (sigma * (1.0 / before.count as f64 + 1.0 / after.count as f64).sqrt());
+			ChangePointObj {
+				timestamp: after.start,
+				mean_before: before.mean,
+				mean_after: after.mean,
+				score,
+				deviation: deviation(score, higher_is_good),
+			}
+		})
+		.collect();
+	ChangePointPage { data, segments }
+}
+
+fn deviation(score: f64, higher_is_good: bool) -> Deviation {
+	if (score > 0.0) == higher_is_good {
+		Deviation::Good
+	} else {
+		Deviation::Bad
+	}
+}
+
+/// Standard deviation of the noise around the level, estimated from the MAD of
+/// successive differences so that the level shifts themselves don't inflate it.
+fn noise(values: &[f64]) -> Option<f64> {
+	let differences: Vec<f64> = values.windows(2).map(|pair| pair[1] - pair[0]).collect();
+	let sigma = stats::mad(&differences)? * Anomalies::MAD_SCALE / std::f64::consts::SQRT_2;
+	if sigma > 0.0 {
+		Some(sigma)
+	} else {
+		// Mostly constant series; fall back to the plain spread.
+		stats::std_dev(values).filter(|sigma| *sigma > 0.0)
+	}
+}
+
+/// Where in `values` the mean shifts the most, as (index of the first value
+/// after the shift, shift in standard errors).
+fn best_split(values: &[f64], sigma: f64, min_size: usize) -> Option<(usize, f64)> {
+	let n = values.len();
+	if n < 2 * min_size {
+		return None;
+	}
+	let total: f64 = values.iter().sum();
+	let mut left = 0.0;
+	let mut best: Option<(usize, f64)> = None;
+	for (i, value) in values.iter().enumerate().take(n - min_size) {
+		left += value;
+		let k = i + 1;
+		if k < min_size {
+			continue;
+		}
+		let shift = (total - left) / (n - k) as f64 - left / k as f64;
+		let score = shift.abs() / (sigma * (1.0 / k as f64 + 1.0 / (n - k) as f64).sqrt());
+		if best.is_none_or(|(_, best)| score > best) {
+			best = Some((k, score));
+		}
+	}
+	best
+}
+// endregion: Utils
+
+// region:    Test
+#[cfg(test)]
+#[path = "../_tests/model_anomaly.rs"]
+mod tests;
+// endregion: Test
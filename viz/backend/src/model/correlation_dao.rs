@@ -0,0 +1,115 @@
+use super::db::Db;
+use super::raw_data_dao::{DailySeries, RawData, TimeBound};
+use super::stats;
+use crate::model;
+use crate::security::UserCtx;
+use chrono::{Duration, NaiveDate};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+#[derive(Debug, Clone)]
+pub struct CorrelationQuery {
+	pub a: String,
+	pub b: String,
+	/// Days `b` is shifted after `a`: lag 1 pairs `a` of a day with `b` of the next.
+	pub lags: RangeInclusive<i64>,
+	pub from: Option<TimeBound>,
+	pub to: Option<TimeBound>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CorrelationPage {
+	pub a: String,
+	pub b: String,
+	/// Whether each side was flipped because its question is `is_reverse`.
+	pub a_reversed: bool,
+	pub b_reversed: bool,
+	pub data: Vec<CorrelationObj>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CorrelationObj {
+	pub lag: i64,
+	/// Aligned pairs.
+	pub n: usize,
+	pub pearson: Option<f64>,
+	pub pearson_p_value: Option<f64>,
+	pub spearman: Option<f64>,
+	pub spearman_p_value: Option<f64>,
+	pub pairs: Vec<CorrelationPair>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CorrelationPair {
+	/// The day of `a`; `b` is from `lag` days later.
+	pub date: NaiveDate,
+	pub a: f64,
+	pub b: f64,
+}
+
+pub struct Correlation;
+
+impl Correlation {
+	/// Largest lag, in days, either way.
+	pub const MAX_LAG: i64 = 30;
+}
+
+impl Correlation {
+	/// Correlates the daily values of two keys, aligned on `matcheddate`, for
+	/// each lag. Values of `is_reverse` questions are flipped first so that
+	/// higher always means the same thing.
+	pub async fn correlate(db: &Db, utx: Option<&UserCtx>, query: CorrelationQuery) -> Result<CorrelationPage, model::Error> {
+		let a = RawData::daily_values(db, utx, &query.a, query.from, query.to).await?;
+		// `b` is read past `to` so the last days of `a` still get a lagged partner.
+		let (b_from, b_to) = (shift(query.from, *query.lags.start()), shift(query.to, *query.lags.end()));
+		let b = RawData::daily_values(db, utx, &query.b, b_from, b_to).await?;
+		let (a_reversed, a_values) = oriented(a);
+		let (b_reversed, b_values) = oriented(b);
+		let b_by_date: HashMap<NaiveDate, f64> = b_values.into_iter().collect();
+
+		let data = query.lags.clone().map(|lag| {
+			let pairs: Vec<CorrelationPair> = a_values.iter()
+				.filter_map(|&(date, a)| b_by_date.get(&(date + Duration::days(lag))).map(|&b| CorrelationPair { date, a, b }))
+				.collect();
+			let xs: Vec<f64> = pairs.iter().map(|pair| pair.a).collect();
+			let ys: Vec<f64> = pairs.iter().map(|pair| pair.b).collect();
+			let pearson = stats::pearson(&xs, &ys);
+			let spearman = stats::spearman(&xs, &ys);
+			CorrelationObj {
+				lag,
+				n: pairs.len(),
+				pearson,
+				pearson_p_value: pearson.and_then(|r| stats::correlation_p_value(r, pairs.len())),
+				spearman,
+				spearman_p_value: spearman.and_then(|r| stats::correlation_p_value(r, pairs.len())),
+				pairs,
+			}
+		}).collect();
+
+		Ok(CorrelationPage { a: query.a, b: query.b, a_reversed, b_reversed, data })
+	}
+}
+
+// region:    Utils
+/// The values of a series, flipped within the question's range when it is
+/// `is_reverse` (negated when it has none).
+fn oriented(series: DailySeries) -> (bool, Vec<(NaiveDate, f64)>) {
+	let Some(question) = series.question.filter(|question| question.is_reverse) else {
+		return (false, series.values);
+	};
+	let flip = match (question.min_value, question.max_value) {
+		(Some(min), Some(max)) if min < max => (min + max) as f64,
+		_ => 0.0,
+	};
+	(true, series.values.into_iter().map(|(date, value)| (date, flip - value)).collect())
+}
+
+fn shift(bound: Option<TimeBound>, days: i64) -> Option<TimeBound> {
+	match bound {
+		Some(TimeBound::Date(date)) => Some(TimeBound::Date(date + Duration::days(days))),
+		Some(TimeBound::Timestamp(timestamp)) => Some(TimeBound::Timestamp(timestamp + days * 86_400_000)),
+		None => None,
+	}
+}
+// endregion: Utils
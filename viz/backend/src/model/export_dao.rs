@@ -0,0 +1,83 @@
+use super::db::Db;
+use super::location_dao::Locations;
+use super::raw_data_dao::{push_time_bound, TimeBound};
+use super::viz_questions_dao::VizQuestions;
+use crate::model;
+use crate::security::UserCtx;
+use chrono::NaiveDate;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Default)]
+pub struct ExportQuery {
+	/// Every key when empty.
+	pub keys: Vec<String>,
+	pub from: Option<TimeBound>,
+	pub to: Option<TimeBound>,
+}
+
+/// A `raw_data` row with its question's metadata, when it has a question.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ExportRow {
+	pub id: i32,
+	pub timestamp: Option<i64>,
+	pub matcheddate: Option<NaiveDate>,
+	pub key: Option<String>,
+	pub value: Option<String>,
+	#[serde(rename = "type")]
+	pub data_type: Option<String>,
+	pub source: Option<String>,
+	pub importid: Option<String>,
+	pub timezone: Option<String>,
+	/// The question's current wording, else the one stored with the row.
+	pub question: Option<String>,
+	pub category: Option<String>,
+	pub question_type: Option<String>,
+	pub min_value: Option<i32>,
+	pub max_value: Option<i32>,
+	pub is_positive: Option<bool>,
+	pub is_reverse: Option<bool>,
+}
+
+pub struct Export;
+
+impl Export {
+	/// Rows read per query, so an export holds at most this many in memory.
+	pub const BATCH_SIZE: i64 = 2_000;
+}
+
+impl Export {
+	/// The next `BATCH_SIZE` rows after the row with id `after`, in id order;
+	/// exports page through with the last id of each batch. Rows of private
+	/// questions and location fixes are left out for anonymous callers, as
+	/// `/api/locations` keeps the fixes to authenticated ones.
+	pub async fn batch(db: &Db, utx: Option<&UserCtx>, query: &ExportQuery, after: i32) -> Result<Vec<ExportRow>, model::Error> {
+		let mut sb = sqlx::QueryBuilder::new(
+			"SELECT raw_data.id, raw_data.timestamp, raw_data.matcheddate, raw_data.key, raw_data.value,
+				raw_data.type AS data_type, raw_data.source, raw_data.importid, raw_data.timezone,
+				COALESCE(questions.question, raw_data.question) AS question, questions.category, questions.question_type,
+				questions.min_value, questions.max_value, questions.is_positive, questions.is_reverse
+			FROM raw_data
+			LEFT JOIN questions ON questions.key = raw_data.key
+			WHERE raw_data.id > ",
+		);
+		sb.push_bind(after);
+		if !query.keys.is_empty() {
+			sb.push(" AND raw_data.key = ANY(").push_bind(query.keys.clone()).push(")");
+		}
+		push_time_bound(&mut sb, query.from, ">=");
+		push_time_bound(&mut sb, query.to, "<=");
+		if utx.is_none() {
+			sb.push(format!(" AND {} IS NOT TRUE AND {} IS NOT TRUE", VizQuestions::IS_PRIVATE, Locations::IS_FIX));
+		}
+		sb.push(" ORDER BY raw_data.id LIMIT ").push_bind(Self::BATCH_SIZE);
+
+		let rows = sb.build_query_as().fetch_all(db).await?;
+		Ok(rows)
+	}
+}
+
+// region:    Test
+#[cfg(test)]
+#[path = "../_tests/model_export.rs"]
+mod tests;
+// endregion: Test
@@ -0,0 +1,366 @@
+use super::db::Db;
+use super::raw_data_dao::{local_today, AggregateFn, Bucket, RawData, TimeBound};
+use super::stats;
+use super::viz_questions_dao::VizQuestions;
+use crate::model;
+use crate::security::UserCtx;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+/// A target for one question, e.g. "count >= 3 per week" for a workout, or
+/// "avg >= 4 per day" for a mood score.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct GoalPatch {
+	pub key: Option<String>,
+	/// How the daily values of a period are combined; `count` counts the days
+	/// with a value above zero. Defaults to `count`.
+	pub aggregate: Option<String>,
+	/// Defaults to `>=`, or `<=` for questions that aren't `is_positive`.
+	pub op: Option<String>,
+	pub target: Option<f64>,
+	/// Defaults to the question's cadence.
+	pub period: Option<String>,
+}
+
+#[derive(sqlx::FromRow, Debug, Clone)]
+struct GoalRow {
+	id: i32,
+	key: String,
+	aggregate: String,
+	op: String,
+	target: f64,
+	period: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GoalOp {
+	Ge,
+	Gt,
+	Le,
+	Lt,
+	Eq,
+}
+
+impl GoalOp {
+	pub fn parse(value: &str) -> Option<GoalOp> {
+		match value {
+			">=" => Some(GoalOp::Ge),
+			">" => Some(GoalOp::Gt),
+			"<=" => Some(GoalOp::Le),
+			"<" => Some(GoalOp::Lt),
+			"=" | "==" => Some(GoalOp::Eq),
+			_ => None,
+		}
+	}
+
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			GoalOp::Ge => ">=",
+			GoalOp::Gt => ">",
+			GoalOp::Le => "<=",
+			GoalOp::Lt => "<",
+			GoalOp::Eq => "=",
+		}
+	}
+
+	fn holds(&self, value: f64, target: f64) -> bool {
+		match self {
+			GoalOp::Ge => value >= target,
+			GoalOp::Gt => value > target,
+			GoalOp::Le => value <= target,
+			GoalOp::Lt => value < target,
+			GoalOp::Eq => value == target,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GoalState {
+	Met,
+	OnTrack,
+	/// Can still be met, but only without slack, e.g. a daily habit not done yet today.
+	AtRisk,
+	/// Can no longer be met this period.
+	Missed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GoalObj {
+	pub id: i32,
+	pub key: String,
+	pub aggregate: &'static str,
+	pub op: &'static str,
+	pub target: f64,
+	pub period: &'static str,
+	pub current_streak: u32,
+	pub longest_streak: u32,
+	/// Share of finished periods whose goal was met.
+	pub completion_rate: Option<f64>,
+	/// The period in progress.
+	pub current: GoalPeriodObj,
+	pub state: GoalState,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub periods: Option<Vec<GoalPeriodObj>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GoalPeriodObj {
+	pub start: NaiveDate,
+	pub value: Option<f64>,
+	/// Days of the period that have a value.
+	pub days: usize,
+	pub met: bool,
+}
+
+/// A validated goal.
+#[derive(Debug, Clone, Copy)]
+struct GoalSpec {
+	func: AggregateFn,
+	op: GoalOp,
+	target: f64,
+	period: Bucket,
+}
+
+pub struct Goals;
+
+impl Goals {
+	const TABLE: &'static str = "goals";
+	const COLUMNS: &'static [&'static str] = &["id", "key", "aggregate", "op", "target", "period"];
+}
+
+impl Goals {
+	/// Every goal with its streaks, without the ones on private questions for
+	/// anonymous callers.
+	pub async fn list(db: &Db, utx: Option<&UserCtx>, at_risk_only: bool) -> Result<Vec<GoalObj>, model::Error> {
+		let rows: Vec<GoalRow> = sqlx::query_as(&format!("SELECT {} FROM {} ORDER BY id", Self::COLUMNS.join(", "), Self::TABLE))
+			.fetch_all(db)
+			.await?;
+		let mut goals = Vec::with_capacity(rows.len());
+		for row in rows {
+			let goal = match Self::evaluate(db, utx, row, false).await {
+				Err(model::Error::EntityNotFound(..)) => continue,
+				goal => goal?,
+			};
+			if !at_risk_only || matches!(goal.state, GoalState::AtRisk | GoalState::Missed) {
+				goals.push(goal);
+			}
+		}
+		Ok(goals)
+	}
+
+	/// A goal with every period since the question's first value.
+	pub async fn get(db: &Db, utx: Option<&UserCtx>, id: i32) -> Result<GoalObj, model::Error> {
+		let row = Self::get_row(db, id).await?;
+		Self::evaluate(db, utx, row, true).await
+	}
+
+	pub async fn create(db: &Db, utx: &UserCtx, data: GoalPatch) -> Result<GoalObj, model::Error> {
+		let row = Self::validate(db, GoalRow {
+			id: 0,
+			key: data.key.unwrap_or_default(),
+			aggregate: data.aggregate.unwrap_or_else(|| AggregateFn::Count.as_str().to_string()),
+			op: data.op.unwrap_or_default(),
+			target: data.target.ok_or_else(|| model::Error::InvalidValue("target is required".to_string()))?,
+			period: data.period.unwrap_or_default(),
+		})
+		.await?;
+		let id: i32 = sqlx::query_scalar(&format!(
+			"INSERT INTO {} (key, aggregate, op, target, period) VALUES ($1, $2, $3, $4, $5) RETURNING id",
+			Self::TABLE
+		))
+		.bind(&row.key)
+		.bind(&row.aggregate)
+		.bind(&row.op)
+		.bind(row.target)
+		.bind(&row.period)
+		.fetch_one(db)
+		.await?;
+		Self::get(db, Some(utx), id).await
+	}
+
+	pub async fn update(db: &Db, utx: &UserCtx, id: i32, data: GoalPatch) -> Result<GoalObj, model::Error> {
+		let current = Self::get_row(db, id).await?;
+		let row = Self::validate(db, GoalRow {
+			id,
+			key: data.key.unwrap_or(current.key),
+			aggregate: data.aggregate.unwrap_or(current.aggregate),
+			op: data.op.unwrap_or(current.op),
+			target: data.target.unwrap_or(current.target),
+			period: data.period.unwrap_or(current.period),
+		})
+		.await?;
+		sqlx::query(&format!(
+			"UPDATE {} SET key = $1, aggregate = $2, op = $3, target = $4, period = $5 WHERE id = $6",
+			Self::TABLE
+		))
+		.bind(&row.key)
+		.bind(&row.aggregate)
+		.bind(&row.op)
+		.bind(row.target)
+		.bind(&row.period)
+		.bind(id)
+		.execute(db)
+		.await?;
+		Self::get(db, Some(utx), id).await
+	}
+
+	pub async fn delete(db: &Db, utx: &UserCtx, id: i32) -> Result<GoalObj, model::Error> {
+		let goal = Self::get(db, Some(utx), id).await?;
+		sqlx::query(&format!("DELETE FROM {} WHERE id = $1", Self::TABLE)).bind(id).execute(db).await?;
+		Ok(goal)
+	}
+
+	async fn get_row(db: &Db, id: i32) -> Result<GoalRow, model::Error> {
+		let row = sqlx::query_as(&format!("SELECT {} FROM {} WHERE id = $1", Self::COLUMNS.join(", "), Self::TABLE))
+			.bind(id)
+			.fetch_optional(db)
+			.await?;
+		row.ok_or_else(|| model::Error::EntityNotFound(Self::TABLE, id.to_string()))
+	}
+
+	/// Fills in the defaults from the goal's question and checks the rest.
+	async fn validate(db: &Db, mut row: GoalRow) -> Result<GoalRow, model::Error> {
+		let question = VizQuestions::get_by_key(db, &row.key)
+			.await?
+			.ok_or_else(|| model::Error::EntityNotFound("questions", row.key.clone()))?;
+		let cadence = Bucket::parse(&question.cadence).unwrap_or(Bucket::Day);
+		if row.op.is_empty() {
+			row.op = if question.is_positive { GoalOp::Ge } else { GoalOp::Le }.as_str().to_string();
+		}
+		if row.period.is_empty() {
+			row.period = cadence.as_str().to_string();
+		}
+		let spec = spec(&row).map_err(model::Error::InvalidValue)?;
+		if spec.period < cadence {
+			return Err(model::Error::InvalidValue(format!(
+				"'{}' is answered once a {}, so its goal can't be per {}",
+				row.key,
+				cadence.as_str(),
+				spec.period.as_str()
+			)));
+		}
+		if !spec.target.is_finite() {
+			return Err(model::Error::InvalidValue("target must be a number".to_string()));
+		}
+		row.op = spec.op.as_str().to_string();
+		Ok(row)
+	}
+
+	async fn evaluate(db: &Db, utx: Option<&UserCtx>, row: GoalRow, with_periods: bool) -> Result<GoalObj, model::Error> {
+		let spec = spec(&row).map_err(model::Error::InvalidValue)?;
+		let today = local_today(db).await?;
+		let series = RawData::daily_values(db, utx, &row.key, None, Some(TimeBound::Date(today))).await?;
+		let periods = periods(&spec, &series.values, today);
+		let (current_streak, longest_streak) = streaks(&periods);
+		let (current, finished) = periods.split_last().expect("there is always a current period");
+		let completion_rate = if finished.is_empty() {
+			None
+		} else {
+			Some(finished.iter().filter(|period| period.met).count() as f64 / finished.len() as f64)
+		};
+		let today_logged = series.values.last().is_some_and(|(date, _)| *date == today);
+		let state = state(&spec, current, today, today_logged);
+
+		Ok(GoalObj {
+			id: row.id,
+			key: row.key,
+			aggregate: spec.func.as_str(),
+			op: spec.op.as_str(),
+			target: spec.target,
+			period: spec.period.as_str(),
+			current_streak,
+			longest_streak,
+			completion_rate,
+			current: current.clone(),
+			state,
+			periods: with_periods.then_some(periods),
+		})
+	}
+}
+
+// region:    Utils
+fn spec(row: &GoalRow) -> Result<GoalSpec, String> {
+	Ok(GoalSpec {
+		func: AggregateFn::parse(&row.aggregate).ok_or_else(|| format!("unknown aggregate '{}'", row.aggregate))?,
+		op: GoalOp::parse(&row.op).ok_or_else(|| format!("unknown op '{}'", row.op))?,
+		target: row.target,
+		period: Bucket::parse(&row.period).ok_or_else(|| format!("unknown period '{}'", row.period))?,
+	})
+}
+
+/// Every period from the one of the first value through the current one.
+fn periods(spec: &GoalSpec, values: &[(NaiveDate, f64)], today: NaiveDate) -> Vec<GoalPeriodObj> {
+	let first = values.first().map_or(today, |(date, _)| *date).min(today);
+	let mut periods = Vec::new();
+	let mut start = spec.period.start_of(first);
+	let mut rest = values;
+	while start <= today {
+		let end = spec.period.next_start(start);
+		let split = rest.iter().position(|(date, _)| *date >= end).unwrap_or(rest.len());
+		let days: Vec<f64> = rest[..split].iter().map(|(_, value)| *value).collect();
+		rest = &rest[split..];
+		let value = match spec.func {
+			AggregateFn::Count => Some(days.iter().filter(|value| **value > 0.0).count() as f64),
+			AggregateFn::Sum => Some(days.iter().sum()),
+			AggregateFn::Avg => stats::mean(&days),
+			AggregateFn::Min => days.iter().copied().reduce(f64::min),
+			AggregateFn::Max => days.iter().copied().reduce(f64::max),
+			AggregateFn::Median => stats::median(&days),
+		};
+		periods.push(GoalPeriodObj {
+			start,
+			value,
+			days: days.len(),
+			met: value.is_some_and(|value| spec.op.holds(value, spec.target)),
+		});
+		start = end;
+	}
+	periods
+}
+
+/// (current, longest) run of met periods. The current period only extends the
+/// streak once it is met; until then the streak runs through the last one.
+fn streaks(periods: &[GoalPeriodObj]) -> (u32, u32) {
+	let (mut run, mut longest) = (0, 0);
+	for period in periods {
+		run = if period.met { run + 1 } else { 0 };
+		longest = longest.max(run);
+	}
+	let current = match periods.split_last() {
+		Some((current, finished)) if !current.met => finished.iter().rev().take_while(|period| period.met).count() as u32,
+		_ => run,
+	};
+	(current, longest)
+}
+
+fn state(spec: &GoalSpec, current: &GoalPeriodObj, today: NaiveDate, today_logged: bool) -> GoalState {
+	if current.met {
+		return GoalState::Met;
+	}
+	// Days left to log a value in, today included unless it already has one.
+	let left = (spec.period.next_start(current.start) - today).num_days() - today_logged as i64;
+	let value = current.value.unwrap_or(0.0);
+	match (spec.func, spec.op) {
+		(AggregateFn::Count, GoalOp::Ge | GoalOp::Gt) => {
+			let needed = if spec.op == GoalOp::Gt { spec.target.floor() + 1.0 } else { spec.target.ceil() } - value;
+			match needed as i64 {
+				needed if needed > left => GoalState::Missed,
+				needed if needed == left => GoalState::AtRisk,
+				_ => GoalState::OnTrack,
+			}
+		}
+		// Counts and sums only grow.
+		(AggregateFn::Count | AggregateFn::Sum, GoalOp::Le | GoalOp::Lt) => GoalState::Missed,
+		_ if left <= 0 => GoalState::Missed,
+		_ if current.value.is_some() || left == 1 => GoalState::AtRisk,
+		_ => GoalState::OnTrack,
+	}
+}
+// endregion: Utils
+
+// region:    Test
+#[cfg(test)]
+#[path = "../_tests/model_goals.rs"]
+mod tests;
+// endregion: Test
@@ -0,0 +1,316 @@
+use super::db::Db;
+use super::viz_questions_dao::{QuestionRow, VizQuestions, VizQuestionsPatch};
+use crate::model;
+use crate::security::UserCtx;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// One command of `lifesheet.json`: a named, scheduled list of questions the
+/// telegram bot asks. Entries of type `header` only structure the file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LifesheetCommand {
+	pub description: String,
+	pub schedule: String,
+	pub questions: Vec<Value>,
+}
+
+/// A `lifesheet.json` document, keyed by command name.
+pub type LifesheetObj = BTreeMap<String, LifesheetCommand>;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LifesheetImport {
+	pub lifesheet: LifesheetObj,
+	/// Old key to new key. Renamed questions take their raw_data and goals with them.
+	#[serde(default)]
+	pub renames: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QuestionChange {
+	pub key: String,
+	pub fields: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QuestionRename {
+	pub from: String,
+	pub to: String,
+	pub fields: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LifesheetDiff {
+	pub added: Vec<String>,
+	pub changed: Vec<QuestionChange>,
+	pub renamed: Vec<QuestionRename>,
+	/// Questions missing from the import; they are archived, not deleted.
+	pub removed: Vec<String>,
+	pub categories_added: Vec<String>,
+	pub warnings: Vec<String>,
+	pub applied: bool,
+}
+
+#[derive(sqlx::FromRow)]
+struct CommandRow {
+	name: String,
+	description: Option<String>,
+	schedule: Option<String>,
+}
+
+/// (name, description, schedule) of a command.
+type CommandEntry = (String, String, String);
+
+/// What an import writes, worked out before anything is written.
+struct ImportPlan {
+	diff: LifesheetDiff,
+	commands: Vec<CommandEntry>,
+	/// (stored key, row) of renamed and changed questions.
+	updates: Vec<(String, QuestionRow)>,
+	inserts: Vec<QuestionRow>,
+	/// Keys of updated questions that change category.
+	moved: HashSet<String>,
+}
+
+pub struct Lifesheet;
+
+impl Lifesheet {
+	const COMMANDS_TABLE: &'static str = "commands";
+	/// Command of questions that were created without one.
+	const DEFAULT_COMMAND: &'static str = "other";
+	const DEFAULT_SCHEDULE: &'static str = "manual";
+}
+
+impl Lifesheet {
+	/// The question catalogue as `lifesheet.json`, without archived questions,
+	/// and without private ones for anonymous callers.
+	pub async fn export(db: &Db, utx: Option<&UserCtx>) -> Result<LifesheetObj, model::Error> {
+		let commands: Vec<CommandRow> = sqlx::query_as(&format!("SELECT name, description, schedule FROM {}", Self::COMMANDS_TABLE))
+			.fetch_all(db)
+			.await?;
+		let private_categories: HashSet<String> = sqlx::query_scalar("SELECT name FROM category WHERE is_private")
+			.fetch_all(db)
+			.await?
+			.into_iter()
+			.collect();
+
+		let mut lifesheet = LifesheetObj::new();
+		for command in commands {
+			lifesheet.insert(command.name, LifesheetCommand {
+				description: command.description.unwrap_or_default(),
+				schedule: command.schedule.unwrap_or_else(|| Self::DEFAULT_SCHEDULE.to_string()),
+				questions: Vec::new(),
+			});
+		}
+		for row in VizQuestions::list_rows(db).await? {
+			let is_private = row.is_private || private_categories.contains(&row.category);
+			if row.is_archived || (is_private && utx.is_none()) {
+				continue;
+			}
+			let command = row.command.clone().unwrap_or_else(|| Self::DEFAULT_COMMAND.to_string());
+			let question = export_question(row);
+			lifesheet
+				.entry(command)
+				.or_insert_with(|| LifesheetCommand {
+					description: String::new(),
+					schedule: Self::DEFAULT_SCHEDULE.to_string(),
+					questions: Vec::new(),
+				})
+				.questions
+				.push(question);
+		}
+		Ok(lifesheet)
+	}
+
+	/// What importing would change, without changing it.
+	pub async fn diff(db: &Db, import: LifesheetImport) -> Result<LifesheetDiff, model::Error> {
+		Ok(Self::plan(db, import).await?.diff)
+	}
+
+	/// Applies an import in one transaction and returns what it changed.
+	pub async fn apply(db: &Db, import: LifesheetImport) -> Result<LifesheetDiff, model::Error> {
+		let mut plan = Self::plan(db, import).await?;
+		let mut tx = db.begin().await?;
+
+		sqlx::query(&format!("DELETE FROM {}", Self::COMMANDS_TABLE)).execute(&mut tx).await?;
+		for (name, description, schedule) in &plan.commands {
+			sqlx::query(&format!("INSERT INTO {} (name, description, schedule) VALUES ($1, $2, $3)", Self::COMMANDS_TABLE))
+				.bind(name)
+				.bind(description)
+				.bind(schedule)
+				.execute(&mut tx)
+				.await?;
+		}
+		for category in &plan.diff.categories_added {
+			sqlx::query(
+				"INSERT INTO category (name, priority, description)
+				SELECT $1, COALESCE(MAX(priority), 0) + 1, '' FROM category
+				ON CONFLICT (name) DO NOTHING",
+			)
+			.bind(category)
+			.execute(&mut tx)
+			.await?;
+		}
+		for rename in &plan.diff.renamed {
+			for table in ["raw_data", "raw_data_history", "goals"] {
+				sqlx::query(&format!("UPDATE {} SET key = $1 WHERE key = $2", table))
+					.bind(&rename.to)
+					.bind(&rename.from)
+					.execute(&mut tx)
+					.await?;
+			}
+		}
+		for (key, row) in &plan.updates {
+			VizQuestions::update_row(&mut tx, key, row).await?;
+			if plan.moved.contains(&row.key) {
+				VizQuestions::place_last(&mut tx, &row.key).await?;
+			}
+		}
+		for row in &plan.inserts {
+			VizQuestions::insert_row(&mut tx, row).await?;
+			VizQuestions::place_last(&mut tx, &row.key).await?;
+		}
+		sqlx::query("UPDATE questions SET is_archived = TRUE WHERE key = ANY($1)")
+			.bind(&plan.diff.removed)
+			.execute(&mut tx)
+			.await?;
+
+		tx.commit().await?;
+		plan.diff.applied = true;
+		Ok(plan.diff)
+	}
+
+	async fn plan(db: &Db, import: LifesheetImport) -> Result<ImportPlan, model::Error> {
+		let (commands, rows) = parse_lifesheet(&import.lifesheet).map_err(model::Error::InvalidValue)?;
+		let stored: HashMap<String, QuestionRow> = VizQuestions::list_rows(db).await?.into_iter().map(|row| (row.key.clone(), row)).collect();
+		let imported: HashSet<String> = rows.iter().map(|row| row.key.clone()).collect();
+
+		let mut renamed_to: HashMap<&str, &str> = HashMap::new();
+		for (from, to) in &import.renames {
+			let error = |reason: &str| Err(model::Error::InvalidValue(format!("can't rename '{}' to '{}': {}", from, to, reason)));
+			if !stored.contains_key(from) {
+				return error("no such question");
+			}
+			if stored.contains_key(to) {
+				return error("the new key is already taken");
+			}
+			if !imported.contains(to) {
+				return error("the new key is not in the import");
+			}
+			if imported.contains(from) {
+				return error("the old key is still in the import");
+			}
+			renamed_to.insert(to.as_str(), from.as_str());
+		}
+
+		let mut diff = LifesheetDiff::default();
+		let mut updates = Vec::new();
+		let mut inserts = Vec::new();
+		let mut moved = HashSet::new();
+		for row in rows {
+			let from = renamed_to.get(row.key.as_str()).copied().unwrap_or(&row.key).to_string();
+			match stored.get(&from) {
+				Some(current) => {
+					let fields = changed_fields(&normalized(current), &row);
+					if current.category != row.category {
+						moved.insert(row.key.clone());
+					}
+					if from != row.key {
+						diff.renamed.push(QuestionRename { from: from.clone(), to: row.key.clone(), fields });
+						updates.push((from, row));
+					} else if !fields.is_empty() {
+						diff.changed.push(QuestionChange { key: row.key.clone(), fields });
+						updates.push((row.key.clone(), row));
+					}
+				}
+				None => {
+					diff.added.push(row.key.clone());
+					inserts.push(row);
+				}
+			}
+		}
+
+		let mut removed: Vec<&QuestionRow> = stored.values()
+			.filter(|row| !row.is_archived && !imported.contains(&row.key) && !import.renames.contains_key(&row.key))
+			.collect();
+		removed.sort_by(|a, b| a.key.cmp(&b.key));
+		for row in removed {
+			let rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM raw_data WHERE key = $1")
+				.bind(&row.key)
+				.fetch_one(db)
+				.await?;
+			if rows > 0 {
+				diff.warnings.push(format!(
+					"'{}' has {} raw_data rows and will be archived; add it to renames if it was renamed",
+					row.key, rows
+				));
+			}
+			diff.removed.push(row.key.clone());
+		}
+
+		let categories: HashSet<String> = sqlx::query_scalar("SELECT name FROM category").fetch_all(db).await?.into_iter().collect();
+		let mut categories_added: Vec<String> = updates.iter().map(|(_, row)| row).chain(inserts.iter())
+			.map(|row| row.category.clone())
+			.filter(|category| !categories.contains(category))
+			.collect::<HashSet<_>>()
+			.into_iter()
+			.collect();
+		categories_added.sort();
+		diff.categories_added = categories_added;
+
+		Ok(ImportPlan { diff, commands, updates, inserts, moved })
+	}
+}
+
+// region:    Utils
+/// Validates every question of a lifesheet, in the order the bot asks them.
+fn parse_lifesheet(lifesheet: &LifesheetObj) -> Result<(Vec<CommandEntry>, Vec<QuestionRow>), String> {
+	let mut commands = Vec::new();
+	let mut rows: Vec<QuestionRow> = Vec::new();
+	let mut keys = HashSet::new();
+	for (name, command) in lifesheet {
+		commands.push((name.clone(), command.description.clone(), command.schedule.clone()));
+		for (position, question) in command.questions.iter().enumerate() {
+			if question["type"].as_str() == Some("header") {
+				continue;
+			}
+			let mut patch: VizQuestionsPatch = serde_json::from_value(question.clone())
+				.map_err(|e| format!("{} question {}: {}", name, position + 1, e))?;
+			patch.command = Some(name.clone());
+			patch.position = Some(position as i32);
+			let row = patch.validate().map_err(|e| format!("{} question {}: {}", name, position + 1, e))?;
+			if !keys.insert(row.key.clone()) {
+				return Err(format!("key '{}' is used more than once", row.key));
+			}
+			rows.push(row);
+		}
+	}
+	Ok((commands, rows))
+}
+
+/// A stored row as the import would write it, so formatting differences from
+/// `db/questionDump.py` (e.g. spacing in `buttons`) don't count as changes.
+fn normalized(row: &QuestionRow) -> QuestionRow {
+	VizQuestionsPatch::default().merged_onto(row.clone()).validate().unwrap_or_else(|_| row.clone())
+}
+
+fn changed_fields(current: &QuestionRow, new: &QuestionRow) -> Vec<String> {
+	let (Ok(Value::Object(current)), Ok(Value::Object(new))) = (serde_json::to_value(current), serde_json::to_value(new)) else {
+		return Vec::new();
+	};
+	new.iter()
+		.filter(|(field, value)| field.as_str() != "key" && current.get(field.as_str()) != Some(value))
+		.map(|(field, _)| field.clone())
+		.collect()
+}
+
+/// A question as written in `lifesheet.json`; the command is implied by where it is.
+fn export_question(row: QuestionRow) -> Value {
+	let patch = VizQuestionsPatch::default().merged_onto(row);
+	let mut question = serde_json::to_value(patch).unwrap_or(Value::Null);
+	if let Value::Object(fields) = &mut question {
+		fields.retain(|field, value| !value.is_null() && field != "command" && field != "isArchived");
+	}
+	question
+}
+// endregion: Utils
@@ -0,0 +1,340 @@
+use super::db::Db;
+use super::raw_data_dao::{RawData, RawValue, TimeBound, ValueType};
+use super::stats;
+use crate::model;
+use crate::security::UserCtx;
+use chrono::{Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlb::HasFields;
+use std::collections::{BTreeMap, HashMap};
+
+/// A named place that location fixes within `radius_km` of it belong to.
+#[derive(sqlx::FromRow, Debug, Clone, Serialize, Deserialize)]
+pub struct PlaceObj {
+	pub id: i32,
+	pub city: String,
+	pub country: String,
+	pub lat: f64,
+	pub lng: f64,
+	pub radius_km: f64,
+}
+
+#[derive(sqlb::Fields, Debug, Clone, Default, Deserialize)]
+pub struct PlacePatch {
+	pub city: Option<String>,
+	pub country: Option<String>,
+	pub lat: Option<f64>,
+	pub lng: Option<f64>,
+	pub radius_km: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DayLocationObj {
+	pub date: NaiveDate,
+	pub lat: f64,
+	pub lng: f64,
+	/// The nearest place within its radius, if any.
+	pub city: Option<String>,
+	pub country: Option<String>,
+	/// Whether the location was carried over from an earlier day without a fix of its own.
+	pub carried: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LocationGroupBy {
+	City,
+	Country,
+}
+
+impl LocationGroupBy {
+	pub fn parse(value: &str) -> Option<LocationGroupBy> {
+		match value {
+			"city" => Some(LocationGroupBy::City),
+			"country" => Some(LocationGroupBy::Country),
+			_ => None,
+		}
+	}
+
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			LocationGroupBy::City => "city",
+			LocationGroupBy::Country => "country",
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct LocationDaysQuery {
+	/// Days a location is assumed to hold after its last fix.
+	pub carry_days: i64,
+	pub from: Option<TimeBound>,
+	pub to: Option<TimeBound>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LocationCompareQuery {
+	pub key: String,
+	pub by: LocationGroupBy,
+	/// Level of the confidence interval of each group's mean, e.g. 0.95.
+	pub confidence: f64,
+	pub days: LocationDaysQuery,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LocationComparePage {
+	pub key: String,
+	pub by: &'static str,
+	pub confidence: f64,
+	pub groups: Vec<LocationGroupObj>,
+	/// Days with a value but no known place.
+	pub unplaced_days: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LocationGroupObj {
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub city: Option<String>,
+	pub country: String,
+	/// Days with a value of the key.
+	pub n: usize,
+	pub mean: f64,
+	pub median: f64,
+	pub std_dev: Option<f64>,
+	pub min: f64,
+	pub max: f64,
+	/// Confidence interval of the mean, from Student's t; needs two days or more.
+	pub ci_low: Option<f64>,
+	pub ci_high: Option<f64>,
+}
+
+#[derive(sqlx::FromRow)]
+struct FixRow {
+	timestamp: i64,
+	matcheddate: NaiveDate,
+	key: String,
+	value: String,
+}
+
+pub struct Places;
+
+impl Places {
+	const TABLE: &'static str = "places";
+	const COLUMNS: &'static [&'static str] = &["id", "city", "country", "lat", "lng", "radius_km"];
+	const DEFAULT_RADIUS_KM: f64 = 30.0;
+}
+
+impl Places {
+	pub async fn list(db: &Db) -> Result<Vec<PlaceObj>, model::Error> {
+		let sb = sqlb::select().table(Self::TABLE).columns(Self::COLUMNS).order_bys(&["country", "city"]);
+		let places = sb.fetch_all(db).await?;
+		Ok(places)
+	}
+
+	pub async fn create(db: &Db, mut data: PlacePatch) -> Result<PlaceObj, model::Error> {
+		data.radius_km.get_or_insert(Self::DEFAULT_RADIUS_KM);
+		if data.city.is_none() || data.country.is_none() || data.lat.is_none() || data.lng.is_none() {
+			return Err(model::Error::InvalidValue("city, country, lat and lng are required".to_string()));
+		}
+		validate(&data).map_err(model::Error::InvalidValue)?;
+		let sb = sqlb::insert().table(Self::TABLE).data(data.fields()).returning(Self::COLUMNS);
+		let place = sb.fetch_one(db).await?;
+		Ok(place)
+	}
+
+	pub async fn update(db: &Db, id: i32, data: PlacePatch) -> Result<PlaceObj, model::Error> {
+		validate(&data).map_err(model::Error::InvalidValue)?;
+		let fields = data.fields();
+		if fields.is_empty() {
+			return Self::get(db, id).await;
+		}
+		let sb = sqlb::update().table(Self::TABLE).data(fields).and_where_eq("id", id).returning(Self::COLUMNS);
+		handle_fetch_one_result(sb.fetch_one(db).await, Self::TABLE, id)
+	}
+
+	pub async fn delete(db: &Db, id: i32) -> Result<PlaceObj, model::Error> {
+		let sb = sqlb::delete().table(Self::TABLE).and_where_eq("id", id).returning(Self::COLUMNS);
+		handle_fetch_one_result(sb.fetch_one(db).await, Self::TABLE, id)
+	}
+
+	async fn get(db: &Db, id: i32) -> Result<PlaceObj, model::Error> {
+		let sb = sqlb::select().table(Self::TABLE).columns(Self::COLUMNS).and_where_eq("id", id);
+		handle_fetch_one_result(sb.fetch_one(db).await, Self::TABLE, id)
+	}
+}
+
+pub struct Locations;
+
+impl Locations {
+	/// Keys the telegram bot stores a shared location under, one row each with the same timestamp.
+	const LAT_KEY: &'static str = "locationLat";
+	const LNG_KEY: &'static str = "locationLng";
+	/// The `raw_data` rows holding a location fix, whatever key or question they belong to.
+	pub(super) const IS_FIX: &'static str = "(raw_data.type = 'location' OR raw_data.key IN ('locationLat', 'locationLng')
+		OR raw_data.key IN (SELECT key FROM questions WHERE question_type = 'location'))";
+}
+
+impl Locations {
+	/// Where each day was spent: the last location fix of the day, matched to
+	/// the nearest place. Days without a fix keep the previous day's location
+	/// for up to `carry_days`. Only for authenticated callers, as the fixes
+	/// trace your movements whether or not their questions are private.
+	pub async fn days(db: &Db, _utx: &UserCtx, query: &LocationDaysQuery) -> Result<Vec<DayLocationObj>, model::Error> {
+		let places = Places::list(db).await?;
+		let mut sb = sqlx::QueryBuilder::new(format!(
+			"SELECT timestamp, matcheddate, key, value FROM raw_data WHERE matcheddate IS NOT NULL AND {}",
+			Self::IS_FIX
+		));
+		// Earlier fixes are read too, as they carry into the range.
+		if let Some(to) = query.to {
+			sb.push(" AND matcheddate <= ").push_bind(to.date());
+		}
+		sb.push(" ORDER BY timestamp, key");
+		let rows: Vec<FixRow> = sb.build_query_as().fetch_all(db).await?;
+
+		let mut fixes: BTreeMap<NaiveDate, (f64, f64)> = BTreeMap::new();
+		let mut pending_lat: Option<(i64, f64)> = None;
+		for row in rows {
+			let fix = match row.key.as_str() {
+				Self::LAT_KEY => {
+					pending_lat = row.value.trim().parse().ok().map(|lat| (row.timestamp, lat));
+					None
+				}
+				Self::LNG_KEY => match (pending_lat.take(), row.value.trim().parse::<f64>()) {
+					(Some((timestamp, lat)), Ok(lng)) if timestamp == row.timestamp => Some((lat, lng)),
+					_ => None,
+				},
+				_ => match ValueType::Location.parse(&row.value) {
+					Ok(RawValue::Location { lat, lng }) => Some((lat, lng)),
+					_ => None,
+				},
+			};
+			if let Some(fix) = fix {
+				fixes.insert(row.matcheddate, fix);
+			}
+		}
+
+		let today = Utc::now().date_naive();
+		let first = query.from.map(|from| from.date());
+		let last = query.to.map_or(today, |to| to.date().min(today));
+		let mut days = Vec::new();
+		let mut fix_days = fixes.into_iter().peekable();
+		let mut current: Option<(NaiveDate, (f64, f64))> = None;
+		let Some(mut date) = fix_days.peek().map(|(date, _)| *date) else {
+			return Ok(days);
+		};
+		while date <= last {
+			let carried = match fix_days.next_if(|(fix_date, _)| *fix_date == date) {
+				Some(fix) => {
+					current = Some(fix);
+					false
+				}
+				None => true,
+			};
+			if let Some((fix_date, (lat, lng))) = current {
+				if (date - fix_date).num_days() <= query.carry_days && first.is_none_or(|first| date >= first) {
+					let place = nearest(&places, lat, lng);
+					days.push(DayLocationObj {
+						date,
+						lat,
+						lng,
+						city: place.map(|place| place.city.clone()),
+						country: place.map(|place| place.country.clone()),
+						carried,
+					});
+				}
+			}
+			date += Duration::days(1);
+		}
+		Ok(days)
+	}
+
+	/// The daily values of a key grouped by the city or country they were recorded in.
+	pub async fn compare(db: &Db, utx: &UserCtx, query: LocationCompareQuery) -> Result<LocationComparePage, model::Error> {
+		let series = RawData::daily_values(db, Some(utx), &query.key, query.days.from, query.days.to).await?;
+		let places: HashMap<NaiveDate, (Option<String>, Option<String>)> = Self::days(db, utx, &query.days)
+			.await?
+			.into_iter()
+			.map(|day| (day.date, (day.city, day.country)))
+			.collect();
+
+		let mut groups: BTreeMap<(String, Option<String>), Vec<f64>> = BTreeMap::new();
+		let mut unplaced_days = 0;
+		for (date, value) in series.values {
+			match places.get(&date) {
+				Some((city, Some(country))) => {
+					let city = match query.by {
+						LocationGroupBy::City => city.clone(),
+						LocationGroupBy::Country => None,
+					};
+					groups.entry((country.clone(), city)).or_default().push(value);
+				}
+				_ => unplaced_days += 1,
+			}
+		}
+
+		let groups = groups.into_iter()
+			.filter_map(|((country, city), values)| {
+				let mean = stats::mean(&values)?;
+				let std_dev = stats::std_dev(&values);
+				let margin = std_dev.map(|std_dev| {
+					stats::t_critical(1.0 - query.confidence, (values.len() - 1) as f64) * std_dev / (values.len() as f64).sqrt()
+				});
+				Some(LocationGroupObj {
+					city,
+					country,
+					n: values.len(),
+					mean,
+					median: stats::median(&values)?,
+					std_dev,
+					min: values.iter().copied().fold(f64::INFINITY, f64::min),
+					max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+					ci_low: margin.map(|margin| mean - margin),
+					ci_high: margin.map(|margin| mean + margin),
+				})
+			})
+			.collect();
+
+		Ok(LocationComparePage { key: query.key, by: query.by.as_str(), confidence: query.confidence, groups, unplaced_days })
+	}
+}
+
+// region:    Utils
+fn validate(data: &PlacePatch) -> Result<(), String> {
+	for (name, value) in [("city", &data.city), ("country", &data.country)] {
+		if value.as_deref().is_some_and(|value| value.trim().is_empty()) {
+			return Err(format!("{} can't be empty", name));
+		}
+	}
+	if data.lat.is_some_and(|lat| !(-90.0..=90.0).contains(&lat)) || data.lng.is_some_and(|lng| !(-180.0..=180.0).contains(&lng)) {
+		return Err("lat must be within -90..90 and lng within -180..180".to_string());
+	}
+	if data.radius_km.is_some_and(|radius| !(radius.is_finite() && radius > 0.0)) {
+		return Err("radius_km must be positive".to_string());
+	}
+	Ok(())
+}
+
+/// The closest place that (lat, lng) lies within the radius of.
+fn nearest(places: &[PlaceObj], lat: f64, lng: f64) -> Option<&PlaceObj> {
+	places.iter()
+		.map(|place| (place, distance_km(lat, lng, place.lat, place.lng)))
+		.filter(|(place, distance)| *distance <= place.radius_km)
+		.min_by(|(_, a), (_, b)| a.total_cmp(b))
+		.map(|(place, _)| place)
+}
+
+/// Great-circle distance by the haversine formula.
+fn distance_km(lat1: f64, lng1: f64, lat2: f64, lng2: f64) -> f64 {
+	const EARTH_RADIUS_KM: f64 = 6371.0;
+	let (d_lat, d_lng) = ((lat2 - lat1).to_radians(), (lng2 - lng1).to_radians());
+	let a = (d_lat / 2.0).sin().powi(2) + lat1.to_radians().cos() * lat2.to_radians().cos() * (d_lng / 2.0).sin().powi(2);
+	2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+}
+
+fn handle_fetch_one_result(result: Result<PlaceObj, sqlx::Error>, typ: &'static str, id: i32) -> Result<PlaceObj, model::Error> {
+	result.map_err(|sqlx_error| match sqlx_error {
+		sqlx::Error::RowNotFound => model::Error::EntityNotFound(typ, id.to_string()),
+		other => model::Error::Sqlx(other),
+	})
+}
+// endregion: Utils
@@ -1,16 +1,29 @@
+mod anomaly_dao;
+mod correlation_dao;
 mod db;
+mod export_dao;
+mod goals_dao;
+mod lifesheet_dao;
+mod location_dao;
 mod raw_data_dao;
+mod stats;
 mod viz_metadata_dao;
 mod viz_questions_dao;
 mod viz_categories_dao;
 
 // re-export
+pub use anomaly_dao::{Anomalies, AnomalyMethod, AnomalyQuery, ChangePointQuery};
+pub use correlation_dao::{Correlation, CorrelationQuery};
 pub use db::init_db;
 pub use db::Db;
-pub use raw_data_dao::RawData;
+pub use export_dao::{Export, ExportQuery, ExportRow};
+pub use goals_dao::{GoalPatch, Goals};
+pub use lifesheet_dao::{Lifesheet, LifesheetImport};
+pub use location_dao::{LocationCompareQuery, LocationDaysQuery, LocationGroupBy, Locations, PlacePatch, Places};
+pub use raw_data_dao::{AggregateFn, AggregateQuery, Bucket, RawData, RawDataCursor, RawDataPatch, RawDataQuery, TimeBound};
 pub use viz_metadata_dao::VizMetadata;
-pub use viz_questions_dao::VizQuestions;
-pub use viz_categories_dao::VizCategories;
+pub use viz_questions_dao::{VizQuestions, VizQuestionsPatch};
+pub use viz_categories_dao::{VizCategories, VizCategoriesPatch};
 
 // region:    Error
 #[derive(thiserror::Error, Debug)]
@@ -18,6 +31,12 @@ pub enum Error {
 	#[error("Entity Not Found - {0}[{1}] ")]
 	EntityNotFound(&'static str, String),
 
+	#[error("Entity Already Exists - {0}[{1}] ")]
+	EntityAlreadyExists(&'static str, String),
+
+	#[error("Invalid value - {0}")]
+	InvalidValue(String),
+
 	#[error(transparent)]
 	Sqlx(#[from] sqlx::Error),
 
@@ -1,11 +1,395 @@
 use super::db::Db;
+use super::viz_questions_dao::{VizQuestions, VizQuestionsObj};
 use crate::model;
+use crate::security::UserCtx;
+use chrono::{Datelike, Duration, Months, NaiveDate, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-#[derive(sqlx::FromRow, Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RawDataObj {
+	pub timestamp: i64,
+	pub value: RawValue,
+}
+
+/// A row whose stored value does not parse as its question's type.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InvalidRawDataObj {
+	pub id: i32,
+	pub timestamp: i64,
+	pub value: String,
+	pub error: String,
+}
+
+/// A `raw_data.value` parsed according to its `ValueType`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RawValue {
+	Number(f64),
+	Boolean(bool),
+	Location { lat: f64, lng: f64 },
+	Text(String),
+}
+
+/// How the values of a key are parsed, from the question's `question_type`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValueType {
+	/// Whole numbers, within `min..=max` when the question has a range.
+	Range { min: Option<i32>, max: Option<i32> },
+	Boolean,
+	Number,
+	Text,
+	/// `lat,lng`, or a single coordinate as stored by the telegram bot.
+	Location,
+}
+
+impl ValueType {
+	pub fn from_question(question: &VizQuestionsObj) -> ValueType {
+		match question.question_type.as_str() {
+			"range" => ValueType::Range { min: question.min_value, max: question.max_value },
+			other => ValueType::from_name(other),
+		}
+	}
+
+	/// Types without further settings; anything unknown is kept as text.
+	pub fn from_name(name: &str) -> ValueType {
+		match name {
+			"range" => ValueType::Range { min: None, max: None },
+			"boolean" => ValueType::Boolean,
+			"number" => ValueType::Number,
+			"location" => ValueType::Location,
+			_ => ValueType::Text,
+		}
+	}
+
+	pub fn parse(&self, value: &str) -> Result<RawValue, String> {
+		let trimmed = value.trim();
+		match self {
+			ValueType::Text => Ok(RawValue::Text(value.to_string())),
+			ValueType::Number => parse_number(trimmed).map(RawValue::Number),
+			ValueType::Range { min, max } => {
+				let number = parse_number(trimmed)?;
+				if number.fract() != 0.0 {
+					return Err(format!("'{}' is not a whole number", value));
+				}
+				// Questions without buttons store 0..0, which is no range at all.
+				if let (Some(min), Some(max)) = (min, max) {
+					if min < max && (number < *min as f64 || number > *max as f64) {
+						return Err(format!("{} is outside {}..={}", number, min, max));
+					}
+				}
+				Ok(RawValue::Number(number))
+			}
+			ValueType::Boolean => match trimmed.to_lowercase().as_str() {
+				"1" | "true" | "yes" => Ok(RawValue::Boolean(true)),
+				"0" | "false" | "no" => Ok(RawValue::Boolean(false)),
+				_ => Err(format!("'{}' is not a boolean", value)),
+			},
+			ValueType::Location => match trimmed.split_once(',') {
+				Some((lat, lng)) => {
+					let lat = parse_number(lat.trim())?;
+					let lng = parse_number(lng.trim())?;
+					if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lng) {
+						return Err(format!("'{}' is not a valid location", value));
+					}
+					Ok(RawValue::Location { lat, lng })
+				}
+				None => parse_number(trimmed).map(RawValue::Number),
+			},
+		}
+	}
+}
+
+/// A stored row of a key, as returned by the write endpoints.
+#[derive(sqlx::FromRow, Debug, Clone, Serialize)]
+pub struct RawDataEntryObj {
+	pub id: i32,
+	pub key: String,
 	pub timestamp: i64,
 	pub value: String,
+	pub matcheddate: Option<NaiveDate>,
+}
+
+/// Fields of an entry to create or correct. `value` is checked against the
+/// key's question; JSON numbers, booleans, strings and `{lat, lng}` are accepted.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RawDataPatch {
+	pub value: Option<Value>,
+	/// Milliseconds since the epoch, defaults to now on create.
+	pub timestamp: Option<i64>,
+	/// Defaults to the local day of `timestamp`, see `LocalMoment`. The
+	/// collector rematches it when the timezone history changes.
+	pub matcheddate: Option<NaiveDate>,
+}
+
+/// Checks `value` against the question's type, range and buttons and returns
+/// it as stored in `raw_data.value`, e.g. `1` for `true`.
+pub fn validate_value(question: &VizQuestionsObj, value: &Value) -> Result<String, String> {
+	let raw = match value {
+		Value::String(s) => s.clone(),
+		Value::Number(n) => n.to_string(),
+		Value::Bool(b) => if *b { "1" } else { "0" }.to_string(),
+		Value::Object(o) => match (o.get("lat").and_then(Value::as_f64), o.get("lng").and_then(Value::as_f64)) {
+			(Some(lat), Some(lng)) => format!("{},{}", lat, lng),
+			_ => return Err("objects need numeric lat and lng".to_string()),
+		},
+		_ => return Err(format!("unsupported value {}", value)),
+	};
+	let stored = match ValueType::from_question(question).parse(&raw)? {
+		RawValue::Number(n) => n.to_string(),
+		RawValue::Boolean(b) => if b { "1" } else { "0" }.to_string(),
+		RawValue::Location { lat, lng } => format!("{},{}", lat, lng),
+		RawValue::Text(s) => s,
+	};
+
+	// Buttons are a JSON object keyed by the allowed values, or a list of them.
+	let allowed: Option<Vec<String>> = match question.buttons.as_deref().map(serde_json::from_str::<Value>) {
+		Some(Ok(Value::Object(buttons))) => Some(buttons.keys().cloned().collect()),
+		Some(Ok(Value::Array(buttons))) => Some(buttons.iter().map(|b| b.as_str().map(str::to_string).unwrap_or_else(|| b.to_string())).collect()),
+		_ => None,
+	};
+	match allowed {
+		Some(allowed) if !allowed.is_empty() && !allowed.contains(&stored) => {
+			Err(format!("'{}' is not one of {}", stored, allowed.join(", ")))
+		}
+		_ => Ok(stored),
+	}
+}
+
+fn parse_number(value: &str) -> Result<f64, String> {
+	match value.parse::<f64>() {
+		Ok(number) if number.is_finite() => Ok(number),
+		_ => Err(format!("'{}' is not a number", value)),
+	}
+}
+
+/// One end of a date range, either a millisecond timestamp or a calendar day
+/// matched against `matcheddate`. Both ends are inclusive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeBound {
+	Timestamp(i64),
+	Date(NaiveDate),
+}
+
+impl TimeBound {
+	/// Parses `1700000000000` or `2024-03-01`.
+	pub fn parse(value: &str) -> Option<TimeBound> {
+		if let Ok(timestamp) = value.parse::<i64>() {
+			return Some(TimeBound::Timestamp(timestamp));
+		}
+		NaiveDate::parse_from_str(value, "%Y-%m-%d").ok().map(TimeBound::Date)
+	}
+
+	/// The calendar day of the bound, timestamps taken as UTC.
+	pub fn date(&self) -> NaiveDate {
+		match self {
+			TimeBound::Timestamp(timestamp) => utc_date(*timestamp),
+			TimeBound::Date(date) => *date,
+		}
+	}
+}
+
+/// Position after the last row of a page; rows are ordered by (timestamp, id).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RawDataCursor {
+	pub timestamp: i64,
+	pub id: i32,
+}
+
+impl RawDataCursor {
+	pub fn encode(&self) -> String {
+		format!("{}_{}", self.timestamp, self.id)
+	}
+
+	pub fn decode(cursor: &str) -> Option<RawDataCursor> {
+		let (timestamp, id) = cursor.split_once('_')?;
+		Some(RawDataCursor { timestamp: timestamp.parse().ok()?, id: id.parse().ok()? })
+	}
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RawDataQuery {
+	pub from: Option<TimeBound>,
+	pub to: Option<TimeBound>,
+	pub limit: Option<i64>,
+	pub after: Option<RawDataCursor>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RawDataPage {
+	pub data: Vec<RawDataObj>,
+	/// Rows of the page that failed to parse, left out of `data`.
+	pub invalid: Vec<InvalidRawDataObj>,
+	/// Cursor for the next page, None on the last one.
+	pub next_cursor: Option<String>,
+}
+
+/// Time bucket an aggregate groups by, from finest to coarsest. Buckets other
+/// than `Day` use the precomputed `yearweek`/`yearmonth`/`quarter`/`year` columns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Bucket {
+	Day,
+	Week,
+	Month,
+	Quarter,
+	Year,
+}
+
+impl Bucket {
+	pub fn parse(value: &str) -> Option<Bucket> {
+		match value {
+			"day" => Some(Bucket::Day),
+			"week" => Some(Bucket::Week),
+			"month" => Some(Bucket::Month),
+			"quarter" => Some(Bucket::Quarter),
+			"year" => Some(Bucket::Year),
+			_ => None,
+		}
+	}
+
+	/// First day of the bucket `date` falls in; weeks start on Monday.
+	pub fn start_of(&self, date: NaiveDate) -> NaiveDate {
+		let first_of = |month: u32| NaiveDate::from_ymd_opt(date.year(), month, 1).unwrap_or(date);
+		match self {
+			Bucket::Day => date,
+			Bucket::Week => date - Duration::days(date.weekday().num_days_from_monday() as i64),
+			Bucket::Month => first_of(date.month()),
+			Bucket::Quarter => first_of((date.month() - 1) / 3 * 3 + 1),
+			Bucket::Year => first_of(1),
+		}
+	}
+
+	/// First day of the bucket after the one starting at `start`.
+	pub fn next_start(&self, start: NaiveDate) -> NaiveDate {
+		let months = |n: u32| start.checked_add_months(Months::new(n)).unwrap_or(start);
+		match self {
+			Bucket::Day => start + Duration::days(1),
+			Bucket::Week => start + Duration::days(7),
+			Bucket::Month => months(1),
+			Bucket::Quarter => months(3),
+			Bucket::Year => months(12),
+		}
+	}
+
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Bucket::Day => "day",
+			Bucket::Week => "week",
+			Bucket::Month => "month",
+			Bucket::Quarter => "quarter",
+			Bucket::Year => "year",
+		}
+	}
+
+	/// Sortable label of a row's bucket, e.g. `2024-03-01`, `2024-W09`, `2024-03`,
+	/// `2024-Q1`, `2024`; NULL when the row lacks the column.
+	fn label_sql(&self) -> &'static str {
+		match self {
+			Bucket::Day => "to_char(matcheddate, 'YYYY-MM-DD')",
+			Bucket::Week => "(yearweek / 100)::text || '-W' || lpad((yearweek % 100)::text, 2, '0')",
+			Bucket::Month => "(yearmonth / 100)::text || '-' || lpad((yearmonth % 100)::text, 2, '0')",
+			Bucket::Quarter => "year::text || '-Q' || quarter::text",
+			Bucket::Year => "year::text",
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateFn {
+	Avg,
+	Sum,
+	Min,
+	Max,
+	Count,
+	Median,
+}
+
+impl AggregateFn {
+	pub fn parse(value: &str) -> Option<AggregateFn> {
+		match value {
+			"avg" => Some(AggregateFn::Avg),
+			"sum" => Some(AggregateFn::Sum),
+			"min" => Some(AggregateFn::Min),
+			"max" => Some(AggregateFn::Max),
+			"count" => Some(AggregateFn::Count),
+			"median" => Some(AggregateFn::Median),
+			_ => None,
+		}
+	}
+
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			AggregateFn::Avg => "avg",
+			AggregateFn::Sum => "sum",
+			AggregateFn::Min => "min",
+			AggregateFn::Max => "max",
+			AggregateFn::Count => "count",
+			AggregateFn::Median => "median",
+		}
+	}
+
+	fn sql(&self) -> &'static str {
+		match self {
+			AggregateFn::Avg => "AVG(value::double precision)",
+			AggregateFn::Sum => "SUM(value::double precision)",
+			AggregateFn::Min => "MIN(value::double precision)",
+			AggregateFn::Max => "MAX(value::double precision)",
+			AggregateFn::Count => "COUNT(*)::double precision",
+			AggregateFn::Median => "percentile_cont(0.5) WITHIN GROUP (ORDER BY value::double precision)",
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct AggregateQuery {
+	/// Defaults to the question's cadence.
+	pub bucket: Option<Bucket>,
+	pub func: AggregateFn,
+	pub from: Option<TimeBound>,
+	pub to: Option<TimeBound>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AggregatePage {
+	/// The bucket used, which may be coarser than the one asked for.
+	pub bucket: Bucket,
+	pub data: Vec<AggregateObj>,
+}
+
+#[derive(sqlx::FromRow, Debug, Clone, Serialize)]
+pub struct AggregateObj {
+	pub bucket: String,
+	/// First day in the bucket that has data.
+	pub start: NaiveDate,
+	pub value: Option<f64>,
+	/// Rows that went into `value`.
+	pub count: i64,
+}
+
+/// One number per day of a key, for the analyses built on top of raw_data.
+#[derive(Debug, Clone)]
+pub struct DailySeries {
+	pub question: Option<VizQuestionsObj>,
+	/// (matcheddate, mean of that day's values), oldest first.
+	pub values: Vec<(NaiveDate, f64)>,
+}
+
+#[derive(sqlx::FromRow)]
+struct DailyRow {
+	matcheddate: NaiveDate,
+	value: String,
+	#[sqlx(rename = "type")]
+	data_type: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct RawDataRow {
+	id: i32,
+	timestamp: i64,
+	value: String,
+	#[sqlx(rename = "type")]
+	data_type: Option<String>,
 }
 
 
@@ -23,26 +407,364 @@ pub struct RawData;
 impl RawData {
 	const TABLE: &'static str = "raw_data";
 	const COLUMNS: &'static [&'static str] = &["timestamp", "value"];
+	pub const MAX_PAGE_SIZE: i64 = 10_000;
+	const ENTRY_COLUMNS: &'static str = "id, key, timestamp, value, matcheddate";
+	/// `raw_data.source` of entries written through the API.
+	const SOURCE: &'static str = "viz";
+	// Values are stored as text; only rows that look like numbers are aggregated.
+	const NUMERIC_VALUE: &'static str = r"value ~ '^\s*[-+]?([0-9]+\.?[0-9]*|\.[0-9]+)([eE][-+]?[0-9]+)?\s*$'";
 }
 
 impl RawData {
 
-	pub async fn get_by_key(db: &Db, key: String) -> Result<Vec<RawDataObj>, model::Error> {
-		let sb = sqlb::select()
-			.table(Self::TABLE)
-			.columns(Self::COLUMNS).and_where_eq("key", key)
-			.order_by("timestamp");
+	/// Rows of a key within `query`'s range, one page at a time. Without a
+	/// limit every matching row is returned. Values are parsed by the key's
+	/// question type, or by each row's `type` for keys without a question.
+	pub async fn get_by_key(db: &Db, utx: Option<&UserCtx>, key: String, query: RawDataQuery) -> Result<RawDataPage, model::Error> {
+		let value_type = Self::readable_question(db, utx, &key).await?.map(|question| ValueType::from_question(&question));
+		let mut sb = sqlx::QueryBuilder::new(format!("SELECT id, type, {} FROM {} WHERE key = ", Self::COLUMNS.join(", "), Self::TABLE));
+		sb.push_bind(key);
+		push_time_bound(&mut sb, query.from, ">=");
+		push_time_bound(&mut sb, query.to, "<=");
+		if let Some(after) = query.after {
+			sb.push(" AND (timestamp, id) > (").push_bind(after.timestamp).push(", ").push_bind(after.id).push(")");
+		}
+		sb.push(" ORDER BY timestamp, id");
+		let limit = query.limit.map(|limit| limit.clamp(1, Self::MAX_PAGE_SIZE));
+		if let Some(limit) = limit {
+			// One extra row tells whether there is a next page.
+			sb.push(" LIMIT ").push_bind(limit + 1);
+		}
+
+		let mut rows: Vec<RawDataRow> = sb.build_query_as().fetch_all(db).await?;
+		let next_cursor = match limit {
+			Some(limit) if rows.len() as i64 > limit => {
+				rows.truncate(limit as usize);
+				rows.last().map(|row| RawDataCursor { timestamp: row.timestamp, id: row.id }.encode())
+			}
+			_ => None,
+		};
+		let (data, invalid) = parse_rows(rows, value_type);
+		Ok(RawDataPage { data, invalid, next_cursor })
+	}
+
+	/// Stores a new value for a question's key, as entered in the telegram bot.
+	pub async fn create(db: &Db, key: String, data: RawDataPatch) -> Result<RawDataEntryObj, model::Error> {
+		let question = Self::question_for(db, &key).await?;
+		let value = match &data.value {
+			Some(value) => validate_value(&question, value).map_err(model::Error::InvalidValue)?,
+			None => return Err(model::Error::InvalidValue("value is required".to_string())),
+		};
+		let timestamp = data.timestamp.unwrap_or_else(|| Utc::now().timestamp_millis());
+		let moment = LocalMoment::load(db, timestamp).await?;
+		let matcheddate = data.matcheddate.unwrap_or(moment.matcheddate);
+		let columns = DateColumns::new(&moment, matcheddate);
+
+		let entry = sqlx::query_as(&format!(
+			"INSERT INTO {} (timestamp, yearmonth, yearweek, year, quarter, month, day, hour, minute, week,
+				key, question, type, value, matcheddate, source, importedat, timezone)
+			VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, now(), $17)
+			RETURNING {}",
+			Self::TABLE,
+			Self::ENTRY_COLUMNS,
+		))
+		.bind(timestamp)
+		.bind(columns.yearmonth)
+		.bind(columns.yearweek)
+		.bind(columns.year)
+		.bind(columns.quarter)
+		.bind(columns.month)
+		.bind(columns.day)
+		.bind(columns.hour)
+		.bind(columns.minute)
+		.bind(columns.week)
+		.bind(&key)
+		.bind(&question.question)
+		.bind(&question.question_type)
+		.bind(value)
+		.bind(matcheddate)
+		.bind(Self::SOURCE)
+		.bind(moment.timezone.name())
+		.fetch_one(db)
+		.await?;
+		Ok(entry)
+	}
+
+	/// Corrects the value or time of an entry of `key`. Moving the timestamp
+	/// without a `matcheddate` moves the entry to the local day of the new timestamp.
+	pub async fn update(db: &Db, key: String, id: i32, data: RawDataPatch) -> Result<RawDataEntryObj, model::Error> {
+		let question = Self::question_for(db, &key).await?;
+		let current = Self::get_entry(db, &key, id).await?;
+		let value = match &data.value {
+			Some(value) => validate_value(&question, value).map_err(model::Error::InvalidValue)?,
+			None => current.value,
+		};
+		let timestamp = data.timestamp.unwrap_or(current.timestamp);
+		let moment = LocalMoment::load(db, timestamp).await?;
+		let matcheddate = match (data.matcheddate, data.timestamp) {
+			(Some(matcheddate), _) => matcheddate,
+			(None, Some(_)) => moment.matcheddate,
+			(None, None) => current.matcheddate.unwrap_or(moment.matcheddate),
+		};
+		let columns = DateColumns::new(&moment, matcheddate);
+
+		let entry = sqlx::query_as(&format!(
+			"UPDATE {} SET timestamp = $1, yearmonth = $2, yearweek = $3, year = $4, quarter = $5, month = $6,
+				day = $7, hour = $8, minute = $9, week = $10, value = $11, matcheddate = $12, timezone = $13
+			WHERE id = $14 AND key = $15
+			RETURNING {}",
+			Self::TABLE,
+			Self::ENTRY_COLUMNS,
+		))
+		.bind(timestamp)
+		.bind(columns.yearmonth)
+		.bind(columns.yearweek)
+		.bind(columns.year)
+		.bind(columns.quarter)
+		.bind(columns.month)
+		.bind(columns.day)
+		.bind(columns.hour)
+		.bind(columns.minute)
+		.bind(columns.week)
+		.bind(value)
+		.bind(matcheddate)
+		.bind(moment.timezone.name())
+		.bind(id)
+		.bind(&key)
+		.fetch_optional(db)
+		.await?;
+		entry.ok_or_else(|| model::Error::EntityNotFound(Self::TABLE, id.to_string()))
+	}
+
+	pub async fn delete(db: &Db, key: String, id: i32) -> Result<RawDataEntryObj, model::Error> {
+		let entry = sqlx::query_as(&format!("DELETE FROM {} WHERE id = $1 AND key = $2 RETURNING {}", Self::TABLE, Self::ENTRY_COLUMNS))
+			.bind(id)
+			.bind(&key)
+			.fetch_optional(db)
+			.await?;
+		entry.ok_or_else(|| model::Error::EntityNotFound(Self::TABLE, id.to_string()))
+	}
+
+	async fn get_entry(db: &Db, key: &str, id: i32) -> Result<RawDataEntryObj, model::Error> {
+		let entry = sqlx::query_as(&format!("SELECT {} FROM {} WHERE id = $1 AND key = $2", Self::ENTRY_COLUMNS, Self::TABLE))
+			.bind(id)
+			.bind(key)
+			.fetch_optional(db)
+			.await?;
+		entry.ok_or_else(|| model::Error::EntityNotFound(Self::TABLE, id.to_string()))
+	}
+
+	/// The question of a key, or EntityNotFound when it is private and the
+	/// caller is anonymous. Keys without a question are public.
+	pub(super) async fn readable_question(db: &Db, utx: Option<&UserCtx>, key: &str) -> Result<Option<VizQuestionsObj>, model::Error> {
+		match VizQuestions::get_by_key(db, key).await? {
+			Some(question) if question.is_private && utx.is_none() => Err(model::Error::EntityNotFound(Self::TABLE, key.to_string())),
+			question => Ok(question),
+		}
+	}
 
-		let data_by_key = sb.fetch_all(db).await?;
-		Ok(data_by_key)
+	/// Only keys with a question can be written to, as the question is what values are validated against.
+	async fn question_for(db: &Db, key: &str) -> Result<VizQuestionsObj, model::Error> {
+		VizQuestions::get_by_key(db, key)
+			.await?
+			.ok_or_else(|| model::Error::EntityNotFound("questions", key.to_string()))
+	}
+
+	/// One aggregated value per bucket, oldest first. `count` counts every row,
+	/// the other functions only rows with a numeric value. A question answered
+	/// once a week has no daily values, so buckets finer than its cadence are
+	/// widened to the cadence.
+	pub async fn aggregate_by_key(db: &Db, utx: Option<&UserCtx>, key: String, query: AggregateQuery) -> Result<AggregatePage, model::Error> {
+		let cadence = Self::readable_question(db, utx, &key)
+			.await?
+			.and_then(|question| Bucket::parse(&question.cadence))
+			.unwrap_or(Bucket::Day);
+		let bucket = query.bucket.map_or(cadence, |bucket| bucket.max(cadence));
+		let label = bucket.label_sql();
+		let mut sb = sqlx::QueryBuilder::new(format!(
+			"SELECT {label} AS bucket, MIN(matcheddate) AS start, {func} AS value, COUNT(*) AS count FROM {table} WHERE key = ",
+			label = label,
+			func = query.func.sql(),
+			table = Self::TABLE,
+		));
+		sb.push_bind(key);
+		sb.push(format!(" AND matcheddate IS NOT NULL AND {} IS NOT NULL", label));
+		if query.func != AggregateFn::Count {
+			sb.push(" AND ").push(Self::NUMERIC_VALUE);
+		}
+		push_time_bound(&mut sb, query.from, ">=");
+		push_time_bound(&mut sb, query.to, "<=");
+		sb.push(format!(" GROUP BY {} ORDER BY 1", label));
+
+		let data = sb.build_query_as().fetch_all(db).await?;
+		Ok(AggregatePage { bucket, data })
+	}
+
+	/// The mean value of each day of a key between `from` and `to`. Numbers
+	/// and booleans (as 1 or 0) count; values that don't parse are skipped.
+	pub async fn daily_values(
+		db: &Db,
+		utx: Option<&UserCtx>,
+		key: &str,
+		from: Option<TimeBound>,
+		to: Option<TimeBound>,
+	) -> Result<DailySeries, model::Error> {
+		let question = Self::readable_question(db, utx, key).await?;
+		let value_type = question.as_ref().map(ValueType::from_question);
+		let mut sb = sqlx::QueryBuilder::new(format!("SELECT matcheddate, value, type FROM {} WHERE key = ", Self::TABLE));
+		sb.push_bind(key.to_string());
+		sb.push(" AND matcheddate IS NOT NULL");
+		push_time_bound(&mut sb, from, ">=");
+		push_time_bound(&mut sb, to, "<=");
+		sb.push(" ORDER BY matcheddate");
+		let rows: Vec<DailyRow> = sb.build_query_as().fetch_all(db).await?;
+
+		let mut values: Vec<(NaiveDate, f64)> = Vec::new();
+		let mut count = 0;
+		for row in rows {
+			let row_type = value_type.unwrap_or_else(|| ValueType::from_name(row.data_type.as_deref().unwrap_or("number")));
+			let number = match row_type.parse(&row.value) {
+				Ok(RawValue::Number(number)) => number,
+				Ok(RawValue::Boolean(value)) => value as i32 as f64,
+				_ => continue,
+			};
+			match values.last_mut() {
+				// Running mean of the day's values.
+				Some((date, mean)) if *date == row.matcheddate => {
+					count += 1;
+					*mean += (number - *mean) / count as f64;
+				}
+				_ => {
+					count = 1;
+					values.push((row.matcheddate, number));
+				}
+			}
+		}
+		Ok(DailySeries { question, values })
 	}
 
 }
 // endregion: TodoMac
 
+// region:    Utils
+/// Where a timestamp fell on your wall clock, by the collector's local day
+/// rule: in the zone of the latest `timezone_history` entry at or before it,
+/// else `TIMEZONE` (default UTC), with days starting at `DAY_BOUNDARY_HOUR`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct LocalMoment {
+	/// The day the moment counts for, after the day boundary.
+	matcheddate: NaiveDate,
+	hour: i16,
+	minute: i16,
+	timezone: Tz,
+}
+
+impl LocalMoment {
+	async fn load(db: &Db, timestamp: i64) -> Result<LocalMoment, model::Error> {
+		let zones: Vec<(String,)> = sqlx::query_as("SELECT timezone FROM timezone_history WHERE starts_at <= $1 ORDER BY starts_at DESC")
+			.bind(timestamp)
+			.fetch_all(db)
+			.await?;
+		// Unknown zone names are skipped, as the collector does.
+		let zone = zones.iter().find_map(|(zone,)| zone.parse().ok()).unwrap_or_else(default_zone);
+		Ok(LocalMoment::new(timestamp, zone, day_boundary_hour()))
+	}
+
+	fn new(timestamp: i64, timezone: Tz, day_boundary_hour: u32) -> LocalMoment {
+		let utc = Utc.timestamp_millis_opt(timestamp).single().unwrap_or_default();
+		let local = utc.with_timezone(&timezone).naive_local();
+		let shifted = local - Duration::hours(day_boundary_hour as i64);
+		LocalMoment {
+			matcheddate: shifted.date(),
+			hour: local.hour() as i16,
+			minute: local.minute() as i16,
+			timezone,
+		}
+	}
+}
+
+/// The local day it is now, which goal periods and streaks roll over with.
+pub(super) async fn local_today(db: &Db) -> Result<NaiveDate, model::Error> {
+	Ok(LocalMoment::load(db, Utc::now().timestamp_millis()).await?.matcheddate)
+}
+
+/// `TIMEZONE`, an IANA name; UTC when unset or unknown.
+fn default_zone() -> Tz {
+	std::env::var("TIMEZONE").ok().and_then(|name| name.parse().ok()).unwrap_or(Tz::UTC)
+}
+
+/// `DAY_BOUNDARY_HOUR`, 0 to 23; 0 when unset or invalid.
+fn day_boundary_hour() -> u32 {
+	std::env::var("DAY_BOUNDARY_HOUR").ok().and_then(|hour| hour.parse().ok()).filter(|hour| *hour < 24).unwrap_or(0)
+}
+
+/// The derived date columns of a row, as the collector writes them: dates
+/// follow `matcheddate`, hour and minute the local time of the moment.
+#[derive(Debug, PartialEq)]
+struct DateColumns {
+	yearmonth: i32,
+	yearweek: i32,
+	year: i16,
+	quarter: i16,
+	month: i16,
+	day: i16,
+	hour: i16,
+	minute: i16,
+	week: i16,
+}
+
+impl DateColumns {
+	fn new(moment: &LocalMoment, date: NaiveDate) -> DateColumns {
+		let week = date.iso_week().week() as i32;
+		DateColumns {
+			yearmonth: date.year() * 100 + date.month() as i32,
+			yearweek: date.year() * 100 + week,
+			year: date.year() as i16,
+			quarter: ((date.month() - 1) / 3 + 1) as i16,
+			month: date.month() as i16,
+			day: date.day() as i16,
+			hour: moment.hour,
+			minute: moment.minute,
+			week: week as i16,
+		}
+	}
+}
+
+/// Splits rows into the values that parse as `value_type`, or as each row's
+/// own `type` when the key has no question, and those that don't.
+fn parse_rows(rows: Vec<RawDataRow>, value_type: Option<ValueType>) -> (Vec<RawDataObj>, Vec<InvalidRawDataObj>) {
+	let mut data = Vec::with_capacity(rows.len());
+	let mut invalid = Vec::new();
+	for row in rows {
+		let row_type = value_type.unwrap_or_else(|| ValueType::from_name(row.data_type.as_deref().unwrap_or("text")));
+		match row_type.parse(&row.value) {
+			Ok(value) => data.push(RawDataObj { timestamp: row.timestamp, value }),
+			Err(error) => invalid.push(InvalidRawDataObj { id: row.id, timestamp: row.timestamp, value: row.value, error }),
+		}
+	}
+	(data, invalid)
+}
+
+fn utc_date(timestamp: i64) -> NaiveDate {
+	Utc.timestamp_millis_opt(timestamp).single().map(|t| t.date_naive()).unwrap_or_default()
+}
+
+pub(super) fn push_time_bound(sb: &mut sqlx::QueryBuilder<sqlx::Postgres>, bound: Option<TimeBound>, op: &str) {
+	match bound {
+		Some(TimeBound::Timestamp(timestamp)) => {
+			sb.push(format!(" AND timestamp {} ", op)).push_bind(timestamp);
+		}
+		Some(TimeBound::Date(date)) => {
+			sb.push(format!(" AND matcheddate {} ", op)).push_bind(date);
+		}
+		None => {}
+	}
+}
+// endregion: Utils
+
 
 // region:    Test
 #[cfg(test)]
-#[path = "../_tests/model_todo.rs"]
+#[path = "../_tests/model_raw_data.rs"]
 mod tests;
 // endregion: Test
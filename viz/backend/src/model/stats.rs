@@ -0,0 +1,187 @@
+//! Small statistics helpers for the analyses on raw_data series.
+
+pub fn mean(values: &[f64]) -> Option<f64> {
+	if values.is_empty() {
+		return None;
+	}
+	Some(values.iter().sum::<f64>() / values.len() as f64)
+}
+
+/// Pearson's r; `None` with fewer than two pairs or when a side is constant.
+pub fn pearson(xs: &[f64], ys: &[f64]) -> Option<f64> {
+	let (mean_x, mean_y) = (mean(xs)?, mean(ys)?);
+	if xs.len() != ys.len() || xs.len() < 2 {
+		return None;
+	}
+	let (mut cov, mut var_x, mut var_y) = (0.0, 0.0, 0.0);
+	for (x, y) in xs.iter().zip(ys) {
+		let (dx, dy) = (x - mean_x, y - mean_y);
+		cov += dx * dy;
+		var_x += dx * dx;
+		var_y += dy * dy;
+	}
+	if var_x == 0.0 || var_y == 0.0 {
+		return None;
+	}
+	Some((cov / (var_x * var_y).sqrt()).clamp(-1.0, 1.0))
+}
+
+/// Spearman's rho: Pearson's r of the ranks, ties sharing their average rank.
+pub fn spearman(xs: &[f64], ys: &[f64]) -> Option<f64> {
+	pearson(&ranks(xs), &ranks(ys))
+}
+
+/// 1-based ranks; tied values get the mean of the ranks they span.
+pub fn ranks(values: &[f64]) -> Vec<f64> {
+	let mut order: Vec<usize> = (0..values.len()).collect();
+	order.sort_by(|&a, &b| values[a].total_cmp(&values[b]));
+	let mut ranks = vec![0.0; values.len()];
+	let mut start = 0;
+	while start < order.len() {
+		let mut end = start;
+		while end + 1 < order.len() && values[order[end + 1]] == values[order[start]] {
+			end += 1;
+		}
+		let rank = (start + end) as f64 / 2.0 + 1.0;
+		for &i in &order[start..=end] {
+			ranks[i] = rank;
+		}
+		start = end + 1;
+	}
+	ranks
+}
+
+/// Two-sided p-value of a correlation `r` over `n` pairs, from Student's t
+/// with n - 2 degrees of freedom.
+pub fn correlation_p_value(r: f64, n: usize) -> Option<f64> {
+	if n < 3 {
+		return None;
+	}
+	let df = (n - 2) as f64;
+	if r.abs() >= 1.0 {
+		return Some(0.0);
+	}
+	let t2 = r * r * df / (1.0 - r * r);
+	Some(incomplete_beta(df / 2.0, 0.5, df / (df + t2)).clamp(0.0, 1.0))
+}
+
+/// The t for which a two-sided test with `df` degrees of freedom has p-value
+/// `p`, e.g. about 2.045 for p = 0.05 and 29 degrees of freedom.
+pub fn t_critical(p: f64, df: f64) -> f64 {
+	let p_value = |t: f64| incomplete_beta(df / 2.0, 0.5, df / (df + t * t));
+	let (mut low, mut high) = (0.0, 1.0);
+	while p_value(high) > p && high < 1e6 {
+		high *= 2.0;
+	}
+	for _ in 0..100 {
+		let mid = (low + high) / 2.0;
+		if p_value(mid) > p {
+			low = mid;
+		} else {
+			high = mid;
+		}
+	}
+	(low + high) / 2.0
+}
+
+pub fn median(values: &[f64]) -> Option<f64> {
+	if values.is_empty() {
+		return None;
+	}
+	let mut sorted = values.to_vec();
+	sorted.sort_by(f64::total_cmp);
+	let mid = sorted.len() / 2;
+	Some(if sorted.len().is_multiple_of(2) { (sorted[mid - 1] + sorted[mid]) / 2.0 } else { sorted[mid] })
+}
+
+/// Sample standard deviation.
+pub fn std_dev(values: &[f64]) -> Option<f64> {
+	if values.len() < 2 {
+		return None;
+	}
+	let mean = mean(values)?;
+	let variance = values.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64;
+	Some(variance.sqrt())
+}
+
+/// Median absolute deviation from the median.
+pub fn mad(values: &[f64]) -> Option<f64> {
+	let median = median(values)?;
+	let deviations: Vec<f64> = values.iter().map(|value| (value - median).abs()).collect();
+	self::median(&deviations)
+}
+
+// region:    Utils
+/// Regularized incomplete beta function I_x(a, b), by its continued fraction.
+fn incomplete_beta(a: f64, b: f64, x: f64) -> f64 {
+	if x <= 0.0 {
+		return 0.0;
+	}
+	if x >= 1.0 {
+		return 1.0;
+	}
+	let front = (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+	// The continued fraction converges quickly only below this point.
+	if x < (a + 1.0) / (a + b + 2.0) {
+		front * beta_fraction(a, b, x) / a
+	} else {
+		1.0 - front * beta_fraction(b, a, 1.0 - x) / b
+	}
+}
+
+fn beta_fraction(a: f64, b: f64, x: f64) -> f64 {
+	const TINY: f64 = 1e-300;
+	let mut c = 1.0;
+	let mut d = 1.0 - (a + b) * x / (a + 1.0);
+	if d.abs() < TINY {
+		d = TINY;
+	}
+	d = 1.0 / d;
+	let mut result = d;
+	for m in 1..300 {
+		let m = m as f64;
+		let even = m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m));
+		let odd = -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0));
+		for step in [even, odd] {
+			d = 1.0 + step * d;
+			if d.abs() < TINY {
+				d = TINY;
+			}
+			c = 1.0 + step / c;
+			if c.abs() < TINY {
+				c = TINY;
+			}
+			d = 1.0 / d;
+			result *= d * c;
+		}
+		if (d * c - 1.0).abs() < 1e-12 {
+			break;
+		}
+	}
+	result
+}
+
+/// Lanczos approximation of ln Γ(x) for x > 0.
+fn ln_gamma(x: f64) -> f64 {
+	const COEFFICIENTS: [f64; 6] = [
+		76.18009172947146,
+		-86.50532032941677,
+		24.01409824083091,
+		-1.231739572450155,
+		0.1208650973866179e-2,
+		-0.5395239384953e-5,
+	];
+	let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+	let mut series = 1.000000000190015;
+	for (i, coefficient) in COEFFICIENTS.iter().enumerate() {
+		series += coefficient / (x + 1.0 + i as f64);
+	}
+	-tmp + (2.5066282746310005 * series / x).ln()
+}
+// endregion: Utils
+
+// region:    Test
+#[cfg(test)]
+#[path = "../_tests/model_stats.rs"]
+mod tests;
+// endregion: Test
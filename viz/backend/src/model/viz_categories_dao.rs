@@ -1,6 +1,8 @@
 use super::db::Db;
 use crate::model;
+use crate::security::UserCtx;
 use serde::{Deserialize, Serialize};
+use sqlb::HasFields;
 
 #[derive(sqlx::FromRow, Debug, Clone, Serialize, Deserialize)]
 pub struct VizCategoriesObj {
@@ -8,22 +10,162 @@ pub struct VizCategoriesObj {
 	pub name: String,
 	pub priority: i32,
 	pub description: String,
+	pub is_private: bool,
+}
+
+#[derive(sqlb::Fields, Debug, Clone, Default, Deserialize)]
+pub struct VizCategoriesPatch {
+	pub name: Option<String>,
+	pub priority: Option<i32>,
+	pub description: Option<String>,
+	pub is_private: Option<bool>,
 }
 
 pub struct VizCategories;
 
 impl VizCategories {
     const TABLE: &'static str = "category";
-    const COLUMNS: &'static [&'static str] = &["id", "name", "priority", "description"];
+    const COLUMNS: &'static [&'static str] = &["id", "name", "priority", "description", "is_private"];
 }
 
 impl VizCategories {
-	pub async fn get_all_categories(db: &Db) -> Result<Vec<VizCategoriesObj>, model::Error> {
-		let sb = sqlb::select()	
+	/// All categories by priority, without the private ones for anonymous callers.
+	pub async fn get_all_categories(db: &Db, utx: Option<&UserCtx>) -> Result<Vec<VizCategoriesObj>, model::Error> {
+		let mut sb = sqlb::select()
 			.table(Self::TABLE)
-			.columns(Self::COLUMNS);
+			.columns(Self::COLUMNS)
+			.order_bys(&["priority", "name"]);
+
+		if utx.is_none() {
+			sb = sb.and_where_eq("is_private", false);
+		}
 
 		let viz_categories_list = sb.fetch_all(db).await?;
 		Ok(viz_categories_list)
 	}
+
+	pub async fn get(db: &Db, id: i32) -> Result<VizCategoriesObj, model::Error> {
+		let sb = sqlb::select().table(Self::TABLE).columns(Self::COLUMNS).and_where_eq("id", id);
+
+		let result = sb.fetch_one(db).await;
+
+		handle_fetch_one_result(result, Self::TABLE, id)
+	}
+
+	/// Creates a category, after the existing ones unless a priority is given.
+	pub async fn create(db: &Db, mut data: VizCategoriesPatch) -> Result<VizCategoriesObj, model::Error> {
+		let name = validate_name(data.name.as_deref())?;
+		if Self::exists(db, &name).await? {
+			return Err(model::Error::EntityAlreadyExists(Self::TABLE, name));
+		}
+		data.name = Some(name);
+		if data.priority.is_none() {
+			let next: i32 = sqlx::query_scalar(&format!("SELECT COALESCE(MAX(priority), 0) + 1 FROM {}", Self::TABLE))
+				.fetch_one(db)
+				.await?;
+			data.priority = Some(next);
+		}
+		data.description.get_or_insert_with(String::new);
+
+		let sb = sqlb::insert().table(Self::TABLE).data(data.fields()).returning(Self::COLUMNS);
+		let category = sb.fetch_one(db).await?;
+		Ok(category)
+	}
+
+	/// Renames, reprioritises or describes a category. Its questions follow a rename.
+	pub async fn update(db: &Db, id: i32, mut data: VizCategoriesPatch) -> Result<VizCategoriesObj, model::Error> {
+		let current = Self::get(db, id).await?;
+		if let Some(name) = data.name.as_deref() {
+			let name = validate_name(Some(name))?;
+			if name != current.name && Self::exists(db, &name).await? {
+				return Err(model::Error::EntityAlreadyExists(Self::TABLE, name));
+			}
+			data.name = Some(name);
+		}
+		let fields = data.fields();
+		if fields.is_empty() {
+			return Ok(current);
+		}
+
+		let mut tx = db.begin().await?;
+		let sb = sqlb::update().table(Self::TABLE).data(fields).and_where_eq("id", id).returning(Self::COLUMNS);
+		let category: VizCategoriesObj = sb.fetch_one(&mut tx).await?;
+		if category.name != current.name {
+			sqlx::query("UPDATE questions SET category = $1 WHERE category = $2")
+				.bind(&category.name)
+				.bind(&current.name)
+				.execute(&mut tx)
+				.await?;
+		}
+		tx.commit().await?;
+		Ok(category)
+	}
+
+	/// Deletes a category. One that still has questions needs `move_to`, the
+	/// category they move to, where they are placed after its own questions.
+	pub async fn delete(db: &Db, id: i32, move_to: Option<String>) -> Result<VizCategoriesObj, model::Error> {
+		let current = Self::get(db, id).await?;
+		let questions: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM questions WHERE category = $1")
+			.bind(&current.name)
+			.fetch_one(db)
+			.await?;
+
+		let mut tx = db.begin().await?;
+		if questions > 0 {
+			let target = match move_to {
+				Some(target) if target != current.name => target,
+				_ => {
+					return Err(model::Error::InvalidValue(format!(
+						"category '{}' still has {} questions, pass move_to to move them",
+						current.name, questions
+					)))
+				}
+			};
+			if !Self::exists(db, &target).await? {
+				return Err(model::Error::EntityNotFound(Self::TABLE, target));
+			}
+			sqlx::query(
+				"UPDATE questions
+				SET category = $1, priority = priority + (SELECT COALESCE(MAX(priority), 0) + 1 FROM questions WHERE category = $1)
+				WHERE category = $2",
+			)
+			.bind(&target)
+			.bind(&current.name)
+			.execute(&mut tx)
+			.await?;
+		}
+		let sb = sqlb::delete().table(Self::TABLE).and_where_eq("id", id).returning(Self::COLUMNS);
+		let category = sb.fetch_one(&mut tx).await?;
+		tx.commit().await?;
+		Ok(category)
+	}
+
+	async fn exists(db: &Db, name: &str) -> Result<bool, model::Error> {
+		let exists = sqlx::query_scalar(&format!("SELECT EXISTS (SELECT 1 FROM {} WHERE name = $1)", Self::TABLE))
+			.bind(name)
+			.fetch_one(db)
+			.await?;
+		Ok(exists)
+	}
+}
+
+
+//region:    Utils
+fn validate_name(name: Option<&str>) -> Result<String, model::Error> {
+	match name.map(str::trim) {
+		Some(name) if !name.is_empty() => Ok(name.to_string()),
+		_ => Err(model::Error::InvalidValue("name is required".to_string())),
+	}
+}
+
+fn handle_fetch_one_result(
+	result: Result<VizCategoriesObj, sqlx::Error>,
+	typ: &'static str,
+	id: i32,
+) -> Result<VizCategoriesObj, model::Error> {
+	result.map_err(|sqlx_error| match sqlx_error {
+		sqlx::Error::RowNotFound => model::Error::EntityNotFound(typ, id.to_string()),
+		other => model::Error::Sqlx(other),
+	})
 }
+//endregion: Utils
@@ -1,18 +1,46 @@
 use super::db::Db;
 use crate::model;
+use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 #[derive(sqlx::FromRow, Debug, Clone, Serialize, Deserialize)]
 pub struct VizMetadataObj {
     pub key: String,
     pub value: String,
+    /// Whether `value` holds JSON, e.g. a list of pinned questions, rather than plain text.
+    pub is_json: bool,
+}
+
+impl VizMetadataObj {
+    /// The value as served: parsed when it is JSON, a string otherwise.
+    pub fn json_value(&self) -> Value {
+        json_value(&self.value, self.is_json)
+    }
+}
+
+/// A value a key had before a write replaced or deleted it.
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct VizMetadataHistoryObj {
+    pub id: i32,
+    /// `None` when the key didn't exist yet.
+    pub value: Option<String>,
+    pub is_json: bool,
+    pub replaced_at: NaiveDateTime,
+}
+
+impl VizMetadataHistoryObj {
+    pub fn json_value(&self) -> Value {
+        self.value.as_deref().map_or(Value::Null, |value| json_value(value, self.is_json))
+    }
 }
 
 pub struct VizMetadata;
 
 impl VizMetadata {
     const TABLE: &'static str = "metadata";
-    const COLUMNS: &'static [&'static str] = &["key", "value"];
+    const HISTORY_TABLE: &'static str = "metadata_history";
+    const COLUMNS: &'static [&'static str] = &["key", "value", "is_json"];
 }
 
 impl VizMetadata {
@@ -33,18 +61,129 @@ impl VizMetadata {
 		let viz_metadata_list = sb.fetch_all(db).await?;
 		Ok(viz_metadata_list)
 	}
+
+	/// Sets a key. Strings are stored as they are, anything else as JSON.
+	/// The value it replaces goes to the history.
+	pub async fn upsert(db: &Db, key: &str, value: &Value) -> Result<VizMetadataObj, model::Error> {
+		let (value, is_json) = match value {
+			Value::String(value) => (value.clone(), false),
+			Value::Null => return Err(model::Error::InvalidValue("value is required".to_string())),
+			other => (other.to_string(), true),
+		};
+
+		let mut tx = db.begin().await?;
+		Self::record_history(&mut tx, key).await?;
+		let metadata = Self::write(&mut tx, key, &value, is_json).await?;
+		tx.commit().await?;
+		Ok(metadata)
+	}
+
+	/// Deletes a key; its value goes to the history.
+	pub async fn delete(db: &Db, key: &str) -> Result<VizMetadataObj, model::Error> {
+		let mut tx = db.begin().await?;
+		Self::record_history(&mut tx, key).await?;
+		let result = sqlx::query_as(&format!("DELETE FROM {} WHERE key = $1 RETURNING {}", Self::TABLE, Self::COLUMNS.join(", ")))
+			.bind(key)
+			.fetch_one(&mut tx)
+			.await;
+		let metadata = handle_fetch_one_result(result, Self::TABLE, key)?;
+		tx.commit().await?;
+		Ok(metadata)
+	}
+
+	/// Earlier values of a key, newest first.
+	pub async fn history(db: &Db, key: &str) -> Result<Vec<VizMetadataHistoryObj>, model::Error> {
+		let history = sqlx::query_as(&format!(
+			"SELECT id, value, is_json, replaced_at FROM {} WHERE key = $1 ORDER BY id DESC",
+			Self::HISTORY_TABLE
+		))
+		.bind(key)
+		.fetch_all(db)
+		.await?;
+		Ok(history)
+	}
+
+	/// Puts back the value of history entry `id`, or of the latest one, deleting
+	/// the key if it didn't exist then. The revert is itself recorded, so it can
+	/// be reverted too. Returns the key as it is now, `None` once deleted.
+	pub async fn revert(db: &Db, key: &str, id: Option<i32>) -> Result<Option<VizMetadataObj>, model::Error> {
+		let entry: Option<VizMetadataHistoryObj> = sqlx::query_as(&format!(
+			"SELECT id, value, is_json, replaced_at FROM {} WHERE key = $1 AND ($2::int IS NULL OR id = $2) ORDER BY id DESC LIMIT 1",
+			Self::HISTORY_TABLE
+		))
+		.bind(key)
+		.bind(id)
+		.fetch_optional(db)
+		.await?;
+		let entry = entry.ok_or_else(|| {
+			model::Error::EntityNotFound(Self::HISTORY_TABLE, id.map_or_else(|| key.to_string(), |id| format!("{}/{}", key, id)))
+		})?;
+
+		let mut tx = db.begin().await?;
+		Self::record_history(&mut tx, key).await?;
+		let metadata = match &entry.value {
+			Some(value) => Some(Self::write(&mut tx, key, value, entry.is_json).await?),
+			None => {
+				sqlx::query(&format!("DELETE FROM {} WHERE key = $1", Self::TABLE))
+					.bind(key)
+					.execute(&mut tx)
+					.await?;
+				None
+			}
+		};
+		tx.commit().await?;
+		Ok(metadata)
+	}
+
+	async fn write<'e, E: sqlx::PgExecutor<'e>>(db: E, key: &str, value: &str, is_json: bool) -> Result<VizMetadataObj, model::Error> {
+		let metadata = sqlx::query_as(&format!(
+			"INSERT INTO {table} (key, value, is_json) VALUES ($1, $2, $3)
+			ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value, is_json = EXCLUDED.is_json
+			RETURNING {columns}",
+			table = Self::TABLE,
+			columns = Self::COLUMNS.join(", ")
+		))
+		.bind(key)
+		.bind(value)
+		.bind(is_json)
+		.fetch_one(db)
+		.await?;
+		Ok(metadata)
+	}
+
+	/// Keeps the current value of a key, or its absence, before it is overwritten.
+	async fn record_history<'e, E: sqlx::PgExecutor<'e>>(db: E, key: &str) -> Result<(), model::Error> {
+		sqlx::query(&format!(
+			"INSERT INTO {history} (key, value, is_json)
+			VALUES ($1, (SELECT value FROM {table} WHERE key = $1), COALESCE((SELECT is_json FROM {table} WHERE key = $1), FALSE))",
+			history = Self::HISTORY_TABLE,
+			table = Self::TABLE
+		))
+		.bind(key)
+		.execute(db)
+		.await?;
+		Ok(())
+	}
 }
 
 
 //region:    Utils
+fn json_value(value: &str, is_json: bool) -> Value {
+	if is_json {
+		serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()))
+	} else {
+		Value::String(value.to_string())
+	}
+}
+
 fn handle_fetch_one_result(
 	result: Result<VizMetadataObj, sqlx::Error>,
 	typ: &'static str,
-	key: &String,
+	key: &str,
 ) -> Result<VizMetadataObj, model::Error> {
 	result.map_err(|sqlx_error| match sqlx_error {
 		sqlx::Error::RowNotFound => model::Error::EntityNotFound(typ, key.to_string()),
 		other => model::Error::Sqlx(other),
 	})
 }
-//endregion: Utils
\ No newline at end of file
+//endregion: Utils
@@ -1,6 +1,9 @@
 use super::db::Db;
+use super::raw_data_dao::Bucket;
 use crate::model;
+use crate::security::UserCtx;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 #[derive(sqlx::FromRow, Debug, Clone, Serialize, Deserialize)]
 pub struct VizQuestionsObj {
@@ -15,6 +18,156 @@ pub struct VizQuestionsObj {
     pub display_name: String,
     pub graph_type: String,
     pub cadence: String,
+    pub priority: i32,
+    pub is_private: bool,
+}
+
+/// A question as written in `lifesheet.json`, used to create or update one.
+/// Everything is optional so an update can send only what changes.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VizQuestionsPatch {
+    pub key: Option<String>,
+    pub question: Option<String>,
+    #[serde(rename = "type")]
+    pub question_type: Option<String>,
+    pub category: Option<String>,
+    pub display_name: Option<String>,
+    pub buttons: Option<Value>,
+    pub is_positive: Option<bool>,
+    pub is_reverse: Option<bool>,
+    pub cadence: Option<String>,
+    pub graph_type: Option<String>,
+    pub is_visible_in_visualizer: Option<bool>,
+    pub is_private: Option<bool>,
+    pub is_archived: Option<bool>,
+    /// What the telegram bot answers, keyed like `buttons`.
+    pub replies: Option<Value>,
+    /// The `lifesheet.json` command that asks the question.
+    pub command: Option<String>,
+    /// Order within the command, set from the question's place in `lifesheet.json`.
+    #[serde(skip)]
+    pub position: Option<i32>,
+}
+
+/// A `questions` row as stored, with the question's own privacy flag.
+#[derive(sqlx::FromRow, Debug, Clone, PartialEq, Serialize)]
+pub struct QuestionRow {
+    pub key: String,
+    pub question: String,
+    pub question_type: String,
+    pub max_value: i32,
+    pub min_value: i32,
+    pub is_visible_in_visualizer: bool,
+    pub buttons: Option<String>,
+    pub category: String,
+    pub display_name: String,
+    pub is_positive: bool,
+    pub is_reverse: bool,
+    pub cadence: String,
+    pub graph_type: String,
+    pub is_private: bool,
+    pub is_archived: bool,
+    pub replies: Option<String>,
+    pub command: Option<String>,
+    pub position: i32,
+}
+
+/// Types the telegram bot can ask; `header` only structures `lifesheet.json`.
+const QUESTION_TYPES: &[&str] = &["range", "boolean", "number", "text", "location"];
+const MAX_BUTTONS_LENGTH: usize = 255;
+
+fn json_column(value: &Option<Value>) -> Option<String> {
+    match value {
+        Some(Value::Null) | None => None,
+        Some(value) => Some(value.to_string()),
+    }
+}
+
+impl VizQuestionsPatch {
+    /// Checks the invariants of `Question.from_json` in `db/questionDump.py` and
+    /// fills in its defaults. Ranges take their bounds from the button keys.
+    pub fn validate(&self) -> Result<QuestionRow, String> {
+        let required = |name: &str, value: &Option<String>| match value.as_deref().map(str::trim) {
+            Some(value) if !value.is_empty() => Ok(value.to_string()),
+            _ => Err(format!("{} is required", name)),
+        };
+        let key = required("key", &self.key)?;
+        let question = required("question", &self.question)?;
+        let question_type = required("type", &self.question_type)?;
+        let category = required("category", &self.category)?;
+        let display_name = required("displayName", &self.display_name)?;
+
+        if !QUESTION_TYPES.contains(&question_type.as_str()) {
+            return Err(format!("type '{}' is not one of {}", question_type, QUESTION_TYPES.join(", ")));
+        }
+        let cadence = self.cadence.clone().unwrap_or_else(|| "day".to_string());
+        if Bucket::parse(&cadence).is_none() {
+            return Err(format!("cadence '{}' is not one of day, week, month, quarter, year", cadence));
+        }
+
+        let (min_value, max_value) = match question_type.as_str() {
+            "range" => {
+                let buttons = match &self.buttons {
+                    Some(Value::Object(buttons)) if !buttons.is_empty() => buttons,
+                    _ => return Err("range questions need buttons keyed by their values".to_string()),
+                };
+                let values = buttons.keys()
+                    .map(|k| k.parse::<i32>().map_err(|_| format!("button '{}' is not a whole number", k)))
+                    .collect::<Result<Vec<i32>, String>>()?;
+                (*values.iter().min().unwrap_or(&0), *values.iter().max().unwrap_or(&0))
+            }
+            "boolean" => (0, 1),
+            _ => (0, 0),
+        };
+        let buttons = json_column(&self.buttons);
+        if buttons.as_ref().is_some_and(|buttons| buttons.len() > MAX_BUTTONS_LENGTH) {
+            return Err(format!("buttons are longer than {} characters", MAX_BUTTONS_LENGTH));
+        }
+
+        Ok(QuestionRow {
+            key,
+            question,
+            question_type,
+            max_value,
+            min_value,
+            is_visible_in_visualizer: self.is_visible_in_visualizer.unwrap_or(false),
+            buttons,
+            category,
+            display_name,
+            is_positive: self.is_positive.unwrap_or(true),
+            is_reverse: self.is_reverse.unwrap_or(false),
+            cadence,
+            graph_type: self.graph_type.clone().unwrap_or_else(|| "calendar".to_string()),
+            is_private: self.is_private.unwrap_or(false),
+            is_archived: self.is_archived.unwrap_or(false),
+            replies: json_column(&self.replies),
+            command: self.command.clone().filter(|command| !command.trim().is_empty()),
+            position: self.position.unwrap_or(0),
+        })
+    }
+
+    /// Fields of `self` over those of `row`.
+    pub fn merged_onto(self, row: QuestionRow) -> VizQuestionsPatch {
+        VizQuestionsPatch {
+            key: self.key.or(Some(row.key)),
+            question: self.question.or(Some(row.question)),
+            question_type: self.question_type.or(Some(row.question_type)),
+            category: self.category.or(Some(row.category)),
+            display_name: self.display_name.or(Some(row.display_name)),
+            buttons: self.buttons.or_else(|| row.buttons.and_then(|b| serde_json::from_str(&b).ok())),
+            is_positive: self.is_positive.or(Some(row.is_positive)),
+            is_reverse: self.is_reverse.or(Some(row.is_reverse)),
+            cadence: self.cadence.or(Some(row.cadence)),
+            graph_type: self.graph_type.or(Some(row.graph_type)),
+            is_visible_in_visualizer: self.is_visible_in_visualizer.or(Some(row.is_visible_in_visualizer)),
+            is_private: self.is_private.or(Some(row.is_private)),
+            is_archived: self.is_archived.or(Some(row.is_archived)),
+            replies: self.replies.or_else(|| row.replies.and_then(|r| serde_json::from_str(&r).ok())),
+            command: self.command.or(row.command),
+            position: self.position.or(Some(row.position)),
+        }
+    }
 }
 
 pub struct VizQuestions;
@@ -23,26 +176,207 @@ impl VizQuestions {
     const TABLE: &'static str = "questions";
     const COLUMNS: &'static [&'static str] =
         &["key", "question", "question_type", "max_value", "min_value", "buttons"
-        , "is_positive", "is_reverse", "display_name", "graph_type", "cadence"];
+        , "is_positive", "is_reverse", "display_name", "graph_type", "cadence", "priority"];
+    const ROW_COLUMNS: &'static [&'static str] =
+        &["key", "question", "question_type", "max_value", "min_value", "is_visible_in_visualizer", "buttons"
+        , "category", "display_name", "is_positive", "is_reverse", "cadence", "graph_type", "is_private", "is_archived"
+        , "replies", "command", "position"];
+    // A question is private on its own or through its category.
+    pub(super) const IS_PRIVATE: &'static str = "(questions.is_private OR EXISTS (
+        SELECT 1 FROM category WHERE category.name = questions.category AND category.is_private))";
 }
 
 impl VizQuestions {
+    /// Questions of a category, or of all categories when `category` is empty.
+    /// Archived questions are left out, and private ones for anonymous callers.
     pub async fn get_questions_with_query(
         db: &Db,
+        utx: Option<&UserCtx>,
         category: String,
         is_visible: bool,
     ) -> Result<Vec<VizQuestionsObj>, model::Error> {
-        let mut sb = sqlb::select().table(Self::TABLE).columns(Self::COLUMNS);
+        let mut sb = sqlx::QueryBuilder::new(Self::select_sql());
+        sb.push(" WHERE NOT is_archived");
 
 		if is_visible {
-			sb = sb.and_where_eq("is_visible_in_visualizer", true);
+			sb.push(" AND is_visible_in_visualizer = ").push_bind(true);
 		}
 
 		if !category.is_empty() {
-			sb = sb.and_where_eq("category", category);
+			sb.push(" AND category = ").push_bind(category);
 		}
 
-        let viz_questions_list = sb.fetch_all(db).await?;
+		if utx.is_none() {
+			sb.push(format!(" AND NOT {}", Self::IS_PRIVATE));
+		}
+
+        sb.push(" ORDER BY (SELECT priority FROM category WHERE category.name = questions.category) NULLS LAST, priority, key");
+
+        let viz_questions_list = sb.build_query_as().fetch_all(db).await?;
         Ok(viz_questions_list)
     }
+
+    /// The question of a key, private or not; callers decide who may see it.
+    pub async fn get_by_key(db: &Db, key: &str) -> Result<Option<VizQuestionsObj>, model::Error> {
+        let question = sqlx::query_as(&format!("{} WHERE key = $1", Self::select_sql()))
+            .bind(key)
+            .fetch_optional(db)
+            .await?;
+        Ok(question)
+    }
+
+    pub async fn create(db: &Db, data: VizQuestionsPatch) -> Result<VizQuestionsObj, model::Error> {
+        let row = data.validate().map_err(model::Error::InvalidValue)?;
+        if Self::get_row(db, &row.key).await?.is_some() {
+            return Err(model::Error::EntityAlreadyExists(Self::TABLE, row.key));
+        }
+        Self::insert_row(db, &row).await?;
+        Self::place_last(db, &row.key).await?;
+        Self::get_existing(db, &row.key).await
+    }
+
+    /// Changes the given fields of a question; the key itself can't change here.
+    pub async fn update(db: &Db, key: &str, data: VizQuestionsPatch) -> Result<VizQuestionsObj, model::Error> {
+        if data.key.as_deref().is_some_and(|new_key| new_key != key) {
+            return Err(model::Error::InvalidValue(format!("key '{}' can't be changed", key)));
+        }
+        let current = Self::get_row(db, key)
+            .await?
+            .ok_or_else(|| model::Error::EntityNotFound(Self::TABLE, key.to_string()))?;
+        let moved = data.category.as_deref().is_some_and(|category| category != current.category);
+        let row = data.merged_onto(current).validate().map_err(model::Error::InvalidValue)?;
+        Self::update_row(db, key, &row).await?;
+        if moved {
+            Self::place_last(db, key).await?;
+        }
+        Self::get_existing(db, key).await
+    }
+
+    /// Hides a question from the question list. Its data is kept.
+    pub async fn archive(db: &Db, key: &str) -> Result<VizQuestionsObj, model::Error> {
+        let archived = sqlx::query(&format!("UPDATE {} SET is_archived = TRUE WHERE key = $1", Self::TABLE))
+            .bind(key)
+            .execute(db)
+            .await?
+            .rows_affected();
+        if archived == 0 {
+            return Err(model::Error::EntityNotFound(Self::TABLE, key.to_string()));
+        }
+        Self::get_existing(db, key).await
+    }
+
+    /// Orders the questions of a category as `keys`, which must name each of
+    /// its questions that aren't archived exactly once.
+    pub async fn reorder(db: &Db, category: &str, keys: &[String]) -> Result<Vec<VizQuestionsObj>, model::Error> {
+        let mut current: Vec<String> = sqlx::query_scalar(&format!("SELECT key FROM {} WHERE category = $1 AND NOT is_archived", Self::TABLE))
+            .bind(category)
+            .fetch_all(db)
+            .await?;
+        let mut requested = keys.to_vec();
+        current.sort();
+        requested.sort();
+        if current != requested {
+            return Err(model::Error::InvalidValue(format!(
+                "the order must list each question of '{}' once: {}",
+                category,
+                current.join(", ")
+            )));
+        }
+
+        let mut tx = db.begin().await?;
+        for (priority, key) in keys.iter().enumerate() {
+            sqlx::query(&format!("UPDATE {} SET priority = $1 WHERE key = $2", Self::TABLE))
+                .bind(priority as i32)
+                .bind(key)
+                .execute(&mut tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Self::get_questions_with_query(db, Some(&UserCtx), category.to_string(), false).await
+    }
+
+    /// Puts a question after the others of its category.
+    pub async fn place_last<'e, E: sqlx::PgExecutor<'e>>(db: E, key: &str) -> Result<(), model::Error> {
+        sqlx::query(&format!(
+            "UPDATE {table} AS q SET priority = (
+                SELECT COALESCE(MAX(priority), -1) + 1 FROM {table} WHERE category = q.category AND key <> q.key)
+            WHERE key = $1",
+            table = Self::TABLE
+        ))
+        .bind(key)
+        .execute(db)
+        .await?;
+        Ok(())
+    }
+
+    /// Every stored question, archived ones included, by command and position.
+    pub async fn list_rows(db: &Db) -> Result<Vec<QuestionRow>, model::Error> {
+        let sql = format!("SELECT {} FROM {} ORDER BY command NULLS LAST, position, key", Self::ROW_COLUMNS.join(", "), Self::TABLE);
+        let rows = sqlx::query_as(&sql).fetch_all(db).await?;
+        Ok(rows)
+    }
+
+    pub async fn insert_row<'e, E: sqlx::PgExecutor<'e>>(db: E, row: &QuestionRow) -> Result<(), model::Error> {
+        let placeholders = (1..=Self::ROW_COLUMNS.len()).map(|i| format!("${}", i)).collect::<Vec<_>>().join(", ");
+        let sql = format!("INSERT INTO {} ({}) VALUES ({})", Self::TABLE, Self::ROW_COLUMNS.join(", "), placeholders);
+        bind_row(sqlx::query(&sql), row).execute(db).await?;
+        Ok(())
+    }
+
+    /// Overwrites the question stored as `key` with `row`, which may carry a new key.
+    pub async fn update_row<'e, E: sqlx::PgExecutor<'e>>(db: E, key: &str, row: &QuestionRow) -> Result<(), model::Error> {
+        let assignments = Self::ROW_COLUMNS.iter().enumerate()
+            .map(|(i, column)| format!("{} = ${}", column, i + 1))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let sql = format!("UPDATE {} SET {} WHERE key = ${}", Self::TABLE, assignments, Self::ROW_COLUMNS.len() + 1);
+        bind_row(sqlx::query(&sql), row).bind(key).execute(db).await?;
+        Ok(())
+    }
+
+    pub async fn get_row(db: &Db, key: &str) -> Result<Option<QuestionRow>, model::Error> {
+        let row = sqlx::query_as(&format!("SELECT {} FROM {} WHERE key = $1", Self::ROW_COLUMNS.join(", "), Self::TABLE))
+            .bind(key)
+            .fetch_optional(db)
+            .await?;
+        Ok(row)
+    }
+
+    async fn get_existing(db: &Db, key: &str) -> Result<VizQuestionsObj, model::Error> {
+        Self::get_by_key(db, key)
+            .await?
+            .ok_or_else(|| model::Error::EntityNotFound(Self::TABLE, key.to_string()))
+    }
+
+    fn select_sql() -> String {
+        format!("SELECT {}, {} AS is_private FROM {}", Self::COLUMNS.join(", "), Self::IS_PRIVATE, Self::TABLE)
+    }
+}
+
+//region:    Utils
+/// Binds the fields of `row` in the order of `ROW_COLUMNS`.
+fn bind_row<'q>(
+    query: sqlx::query::Query<'q, sqlx::Postgres, sqlx::postgres::PgArguments>,
+    row: &QuestionRow,
+) -> sqlx::query::Query<'q, sqlx::Postgres, sqlx::postgres::PgArguments> {
+    query
+        .bind(row.key.clone())
+        .bind(row.question.clone())
+        .bind(row.question_type.clone())
+        .bind(row.max_value)
+        .bind(row.min_value)
+        .bind(row.is_visible_in_visualizer)
+        .bind(row.buttons.clone())
+        .bind(row.category.clone())
+        .bind(row.display_name.clone())
+        .bind(row.is_positive)
+        .bind(row.is_reverse)
+        .bind(row.cadence.clone())
+        .bind(row.graph_type.clone())
+        .bind(row.is_private)
+        .bind(row.is_archived)
+        .bind(row.replies.clone())
+        .bind(row.command.clone())
+        .bind(row.position)
 }
+//endregion: Utils
@@ -0,0 +1,32 @@
+use std::env;
+
+/// The caller of an authenticated request. The lifesheet has a single owner,
+/// so a valid token is all there is to know about them.
+#[derive(Debug, Clone)]
+pub struct UserCtx;
+
+/// Checks `token` against `AUTH_TOKEN`. Without `AUTH_TOKEN` every token is
+/// refused, so writes stay disabled until one is configured.
+pub fn utx_from_token(token: &str) -> Result<UserCtx, Error> {
+	let expected = env::var("AUTH_TOKEN").map_err(|_| Error::NoAuthConfigured)?;
+	if !expected.is_empty() && constant_time_eq(expected.as_bytes(), token.as_bytes()) {
+		Ok(UserCtx)
+	} else {
+		Err(Error::InvalidToken)
+	}
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+	a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// region:    Error
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("Invalid auth token")]
+	InvalidToken,
+
+	#[error("No AUTH_TOKEN configured")]
+	NoAuthConfigured,
+}
+// endregion: Error
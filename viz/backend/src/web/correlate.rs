@@ -0,0 +1,64 @@
+use crate::model::{Correlation, CorrelationQuery, Db};
+use crate::security::UserCtx;
+use crate::web::filter_auth::with_auth;
+use crate::web::raw_data::parse_bound;
+use crate::web::Error;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use warp::reply::Json;
+use warp::Filter;
+
+#[derive(Serialize, Deserialize)]
+struct CorrelateQueryParams {
+	a: String,
+	b: String,
+	lag: Option<String>,
+	from: Option<String>,
+	to: Option<String>,
+}
+
+pub fn correlate_rest_filters(
+	base_path: &'static str,
+	db: &Arc<Db>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+	// `GET correlate?a=sleepHours&b=mood&lag=0..3&from=2024-01-01`
+	warp::path(base_path)
+		.and(warp::path("correlate"))
+		.and(warp::path::end())
+		.and(warp::get())
+		.and(super::filter_utils::with_db(db.clone()))
+		.and(with_auth(db.clone()))
+		.and(warp::query::<CorrelateQueryParams>())
+		.and_then(correlate)
+}
+
+async fn correlate(db: Arc<Db>, utx: Option<UserCtx>, params: CorrelateQueryParams) -> Result<Json, warp::Rejection> {
+	let query = CorrelationQuery {
+		lags: parse_lags(params.lag.as_deref().unwrap_or("0"))?,
+		from: parse_bound("from", params.from)?,
+		to: parse_bound("to", params.to)?,
+		a: params.a,
+		b: params.b,
+	};
+	let page = Correlation::correlate(&db, utx.as_ref(), query).await?;
+	Ok(warp::reply::json(&json!(page)))
+}
+
+/// `2` or `0..3`, both ends inclusive.
+fn parse_lags(value: &str) -> Result<RangeInclusive<i64>, Error> {
+	let invalid = || Error::InvalidQuery(format!("lag '{}' is neither a number of days nor a range like 0..3", value));
+	let (start, end) = match value.split_once("..") {
+		Some((start, end)) => (start.trim().parse().map_err(|_| invalid())?, end.trim().parse().map_err(|_| invalid())?),
+		None => {
+			let lag = value.trim().parse().map_err(|_| invalid())?;
+			(lag, lag)
+		}
+	};
+	let max = Correlation::MAX_LAG;
+	if start > end || start < -max || end > max {
+		return Err(Error::InvalidQuery(format!("lag '{}' must be an ascending range within -{}..{}", value, max, max)));
+	}
+	Ok(start..=end)
+}
@@ -0,0 +1,353 @@
+use crate::model::{Db, Export, ExportQuery, ExportRow};
+use crate::security::UserCtx;
+use crate::web::filter_auth::with_auth;
+use crate::web::raw_data::parse_bound;
+use crate::web::Error;
+use chrono::NaiveDate;
+use futures::channel::mpsc;
+use futures::SinkExt;
+use parquet::basic::Compression;
+use parquet::data_type::{BoolType, ByteArray, ByteArrayType, DataType, Int32Type, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::{SerializedColumnWriter, SerializedFileWriter};
+use parquet::schema::parser::parse_message_type;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use warp::http::{header, Response};
+use warp::hyper::Body;
+use warp::Filter;
+
+#[derive(Serialize, Deserialize)]
+struct ExportQueryParams {
+	format: Option<String>,
+	/// Comma-separated keys; every key when missing.
+	keys: Option<String>,
+	from: Option<String>,
+	to: Option<String>,
+}
+
+pub fn export_rest_filters(
+	base_path: &'static str,
+	db: &Arc<Db>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+	// `GET export?format=csv&keys=mood,sleepHours&from=2024-01-01`
+	warp::path(base_path)
+		.and(warp::path("export"))
+		.and(warp::path::end())
+		.and(warp::get())
+		.and(super::filter_utils::with_db(db.clone()))
+		.and(with_auth(db.clone()))
+		.and(warp::query::<ExportQueryParams>())
+		.and_then(export)
+}
+
+/// Streams every matching row. The first batch is read before answering so a
+/// bad query still gets an error status; after that rows are read batch by
+/// batch as the client takes them, and a failure cuts the download short.
+async fn export(db: Arc<Db>, utx: Option<UserCtx>, params: ExportQueryParams) -> Result<Response<Body>, warp::Rejection> {
+	let format = ExportFormat::parse(params.format.as_deref().unwrap_or("csv"))?;
+	let query = ExportQuery {
+		keys: params.keys.iter()
+			.flat_map(|keys| keys.split(','))
+			.map(str::trim)
+			.filter(|key| !key.is_empty())
+			.map(str::to_string)
+			.collect(),
+		from: parse_bound("from", params.from)?,
+		to: parse_bound("to", params.to)?,
+	};
+	let first = Export::batch(&db, utx.as_ref(), &query, 0).await?;
+
+	// A couple of encoded batches in flight at most; the reader waits for the client.
+	let (mut tx, rx) = mpsc::channel::<Result<Vec<u8>, std::io::Error>>(2);
+	tokio::spawn(async move {
+		let mut encoder = ExportEncoder::new(format);
+		let mut batch = first;
+		loop {
+			let done = (batch.len() as i64) < Export::BATCH_SIZE;
+			let after = batch.last().map(|row| row.id);
+			let chunk = encoder.encode(&batch);
+			if !send(&mut tx, chunk).await {
+				return;
+			}
+			match after {
+				Some(after) if !done => match Export::batch(&db, utx.as_ref(), &query, after).await {
+					Ok(next) => batch = next,
+					Err(e) => {
+						println!("ERROR - export failed. Cause {}", e);
+						let _ = tx.send(Err(std::io::Error::other(e.to_string()))).await;
+						return;
+					}
+				},
+				_ => break,
+			}
+		}
+		let end = encoder.finish();
+		send(&mut tx, end).await;
+	});
+
+	let response = Response::builder()
+		.header(header::CONTENT_TYPE, format.content_type())
+		.header(header::CONTENT_DISPOSITION, format!("attachment; filename=\"raw_data.{}\"", format.extension()))
+		.body(Body::wrap_stream(rx))
+		.map_err(|e| Error::InvalidQuery(e.to_string()))?;
+	Ok(response)
+}
+
+/// False once the client has gone or encoding failed, when the export stops.
+async fn send(tx: &mut mpsc::Sender<Result<Vec<u8>, std::io::Error>>, chunk: Result<Vec<u8>, String>) -> bool {
+	match chunk {
+		Ok(bytes) if bytes.is_empty() => true,
+		Ok(bytes) => tx.send(Ok(bytes)).await.is_ok(),
+		Err(e) => {
+			println!("ERROR - export failed. Cause {}", e);
+			let _ = tx.send(Err(std::io::Error::other(e))).await;
+			false
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExportFormat {
+	Csv,
+	Jsonl,
+	Parquet,
+}
+
+impl ExportFormat {
+	fn parse(value: &str) -> Result<ExportFormat, Error> {
+		match value {
+			"csv" => Ok(ExportFormat::Csv),
+			"jsonl" => Ok(ExportFormat::Jsonl),
+			"parquet" => Ok(ExportFormat::Parquet),
+			_ => Err(Error::InvalidQuery(format!("format '{}' is not one of csv, jsonl, parquet", value))),
+		}
+	}
+
+	fn content_type(&self) -> &'static str {
+		match self {
+			ExportFormat::Csv => "text/csv; charset=utf-8",
+			ExportFormat::Jsonl => "application/x-ndjson",
+			ExportFormat::Parquet => "application/vnd.apache.parquet",
+		}
+	}
+
+	fn extension(&self) -> &'static str {
+		match self {
+			ExportFormat::Csv => "csv",
+			ExportFormat::Jsonl => "jsonl",
+			ExportFormat::Parquet => "parquet",
+		}
+	}
+}
+
+// region:    Encoders
+/// Columns of the CSV and Parquet exports, in order.
+const COLUMNS: &[&str] = &[
+	"id", "timestamp", "matcheddate", "key", "value", "type", "source", "importid", "timezone",
+	"question", "category", "question_type", "min_value", "max_value", "is_positive", "is_reverse",
+];
+
+const PARQUET_SCHEMA: &str = "
+	message raw_data {
+		REQUIRED INT32 id;
+		OPTIONAL INT64 timestamp (TIMESTAMP(MILLIS, true));
+		OPTIONAL INT32 matcheddate (DATE);
+		OPTIONAL BYTE_ARRAY key (UTF8);
+		OPTIONAL BYTE_ARRAY value (UTF8);
+		OPTIONAL BYTE_ARRAY type (UTF8);
+		OPTIONAL BYTE_ARRAY source (UTF8);
+		OPTIONAL BYTE_ARRAY importid (UTF8);
+		OPTIONAL BYTE_ARRAY timezone (UTF8);
+		OPTIONAL BYTE_ARRAY question (UTF8);
+		OPTIONAL BYTE_ARRAY category (UTF8);
+		OPTIONAL BYTE_ARRAY question_type (UTF8);
+		OPTIONAL INT32 min_value;
+		OPTIONAL INT32 max_value;
+		OPTIONAL BOOLEAN is_positive;
+		OPTIONAL BOOLEAN is_reverse;
+	}
+";
+
+/// Turns batches of rows into the bytes of one export file.
+enum ExportEncoder {
+	Csv { header_written: bool },
+	Jsonl,
+	Parquet(Box<ParquetEncoder>),
+}
+
+impl ExportEncoder {
+	fn new(format: ExportFormat) -> ExportEncoder {
+		match format {
+			ExportFormat::Csv => ExportEncoder::Csv { header_written: false },
+			ExportFormat::Jsonl => ExportEncoder::Jsonl,
+			ExportFormat::Parquet => ExportEncoder::Parquet(Box::default()),
+		}
+	}
+
+	/// The bytes ready to send after `rows`; may be empty while Parquet fills a row group.
+	fn encode(&mut self, rows: &[ExportRow]) -> Result<Vec<u8>, String> {
+		match self {
+			ExportEncoder::Csv { header_written } => {
+				let mut out = Vec::new();
+				if !*header_written {
+					out.extend_from_slice(COLUMNS.join(",").as_bytes());
+					out.extend_from_slice(b"\r\n");
+					*header_written = true;
+				}
+				for row in rows {
+					out.extend_from_slice(csv_line(row).as_bytes());
+				}
+				Ok(out)
+			}
+			ExportEncoder::Jsonl => {
+				let mut out = Vec::new();
+				for row in rows {
+					serde_json::to_writer(&mut out, row).map_err(|e| e.to_string())?;
+					out.push(b'\n');
+				}
+				Ok(out)
+			}
+			ExportEncoder::Parquet(encoder) => encoder.encode(rows).map_err(|e| e.to_string()),
+		}
+	}
+
+	/// The trailing bytes; Parquet's last row group and footer.
+	fn finish(self) -> Result<Vec<u8>, String> {
+		match self {
+			ExportEncoder::Csv { .. } | ExportEncoder::Jsonl => Ok(Vec::new()),
+			ExportEncoder::Parquet(encoder) => encoder.finish().map_err(|e| e.to_string()),
+		}
+	}
+}
+
+fn csv_line(row: &ExportRow) -> String {
+	let text = |value: &Option<String>| value.as_deref().map(csv_field).unwrap_or_default();
+	let fields = [
+		row.id.to_string(),
+		display(row.timestamp),
+		display(row.matcheddate),
+		text(&row.key),
+		text(&row.value),
+		text(&row.data_type),
+		text(&row.source),
+		text(&row.importid),
+		text(&row.timezone),
+		text(&row.question),
+		text(&row.category),
+		text(&row.question_type),
+		display(row.min_value),
+		display(row.max_value),
+		display(row.is_positive),
+		display(row.is_reverse),
+	];
+	format!("{}\r\n", fields.join(","))
+}
+
+fn display<T: std::fmt::Display>(value: Option<T>) -> String {
+	value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+/// Quotes a field when it holds a delimiter, quote or line break (RFC 4180).
+fn csv_field(value: &str) -> String {
+	if value.contains([',', '"', '\n', '\r']) {
+		format!("\"{}\"", value.replace('"', "\"\""))
+	} else {
+		value.to_string()
+	}
+}
+
+/// Buffers rows into row groups and hands out the file's bytes as each row
+/// group is written.
+struct ParquetEncoder {
+	writer: SerializedFileWriter<Vec<u8>>,
+	pending: Vec<ExportRow>,
+}
+
+impl ParquetEncoder {
+	/// Rows per row group; large enough for good compression, small enough to buffer.
+	const ROW_GROUP_SIZE: usize = 50_000;
+}
+
+impl Default for ParquetEncoder {
+	fn default() -> ParquetEncoder {
+		let schema = Arc::new(parse_message_type(PARQUET_SCHEMA).expect("valid parquet schema"));
+		let properties = Arc::new(WriterProperties::builder().set_compression(Compression::SNAPPY).build());
+		let writer = SerializedFileWriter::new(Vec::new(), schema, properties).expect("writer on a Vec");
+		ParquetEncoder { writer, pending: Vec::new() }
+	}
+}
+
+impl ParquetEncoder {
+	fn encode(&mut self, rows: &[ExportRow]) -> parquet::errors::Result<Vec<u8>> {
+		self.pending.extend_from_slice(rows);
+		if self.pending.len() >= Self::ROW_GROUP_SIZE {
+			self.write_row_group()?;
+		}
+		Ok(std::mem::take(self.writer.inner_mut()))
+	}
+
+	fn finish(mut self) -> parquet::errors::Result<Vec<u8>> {
+		if !self.pending.is_empty() {
+			self.write_row_group()?;
+		}
+		self.writer.into_inner()
+	}
+
+	fn write_row_group(&mut self) -> parquet::errors::Result<()> {
+		let rows = std::mem::take(&mut self.pending);
+		let text = |value: &Option<String>| value.as_deref().map(ByteArray::from);
+		let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+
+		let mut row_group = self.writer.next_row_group()?;
+		let mut index = 0;
+		while let Some(mut column) = row_group.next_column()? {
+			let values = rows.iter();
+			match COLUMNS[index] {
+				"id" => {
+					let ids: Vec<i32> = values.map(|row| row.id).collect();
+					column.typed::<Int32Type>().write_batch(&ids, None, None)?;
+				}
+				"timestamp" => write_optional::<Int64Type>(&mut column, values.map(|row| row.timestamp))?,
+				"matcheddate" => write_optional::<Int32Type>(
+					&mut column,
+					values.map(|row| row.matcheddate.map(|date| (date - epoch).num_days() as i32)),
+				)?,
+				"key" => write_optional::<ByteArrayType>(&mut column, values.map(|row| text(&row.key)))?,
+				"value" => write_optional::<ByteArrayType>(&mut column, values.map(|row| text(&row.value)))?,
+				"type" => write_optional::<ByteArrayType>(&mut column, values.map(|row| text(&row.data_type)))?,
+				"source" => write_optional::<ByteArrayType>(&mut column, values.map(|row| text(&row.source)))?,
+				"importid" => write_optional::<ByteArrayType>(&mut column, values.map(|row| text(&row.importid)))?,
+				"timezone" => write_optional::<ByteArrayType>(&mut column, values.map(|row| text(&row.timezone)))?,
+				"question" => write_optional::<ByteArrayType>(&mut column, values.map(|row| text(&row.question)))?,
+				"category" => write_optional::<ByteArrayType>(&mut column, values.map(|row| text(&row.category)))?,
+				"question_type" => write_optional::<ByteArrayType>(&mut column, values.map(|row| text(&row.question_type)))?,
+				"min_value" => write_optional::<Int32Type>(&mut column, values.map(|row| row.min_value))?,
+				"max_value" => write_optional::<Int32Type>(&mut column, values.map(|row| row.max_value))?,
+				"is_positive" => write_optional::<BoolType>(&mut column, values.map(|row| row.is_positive))?,
+				"is_reverse" => write_optional::<BoolType>(&mut column, values.map(|row| row.is_reverse))?,
+				name => unreachable!("export column {} has no writer", name),
+			}
+			column.close()?;
+			index += 1;
+		}
+		row_group.close()?;
+		Ok(())
+	}
+}
+
+/// Writes a nullable column: definition level 1 for values, 0 for nulls.
+fn write_optional<T: DataType>(
+	column: &mut SerializedColumnWriter<'_>,
+	values: impl Iterator<Item = Option<T::T>>,
+) -> parquet::errors::Result<()> {
+	let mut levels = Vec::new();
+	let mut present = Vec::new();
+	for value in values {
+		levels.push(value.is_some() as i16);
+		present.extend(value);
+	}
+	column.typed::<T>().write_batch(&present, Some(&levels), None)?;
+	Ok(())
+}
+// endregion: Encoders
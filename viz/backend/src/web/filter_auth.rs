@@ -0,0 +1,32 @@
+use crate::model::Db;
+use crate::security::{utx_from_token, UserCtx};
+use crate::web::WebErrorMessage;
+use std::sync::Arc;
+use warp::{Filter, Rejection};
+
+const HEADER_XAUTH: &str = "X-Auth-Token";
+
+/// Rejects requests without a valid `X-Auth-Token` header.
+pub fn do_auth(_db: Arc<Db>) -> impl Filter<Extract = (UserCtx,), Error = Rejection> + Clone {
+	warp::any()
+		.and(warp::header::optional::<String>(HEADER_XAUTH))
+		.and_then(|xauth: Option<String>| async move {
+			match xauth {
+				Some(xauth) => utx_from_token(&xauth).map_err(Rejection::from),
+				None => Err(WebErrorMessage::rejection("security::Error", format!("no {} header", HEADER_XAUTH))),
+			}
+		})
+}
+
+/// The caller if an `X-Auth-Token` header is sent, None for anonymous requests.
+/// An invalid token is rejected rather than treated as anonymous.
+pub fn with_auth(_db: Arc<Db>) -> impl Filter<Extract = (Option<UserCtx>,), Error = Rejection> + Clone {
+	warp::any()
+		.and(warp::header::optional::<String>(HEADER_XAUTH))
+		.and_then(|xauth: Option<String>| async move {
+			match xauth {
+				Some(xauth) => utx_from_token(&xauth).map(Some).map_err(Rejection::from),
+				None => Ok(None),
+			}
+		})
+}
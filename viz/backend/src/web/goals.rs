@@ -0,0 +1,94 @@
+use crate::model::{Db, GoalPatch, Goals};
+use crate::security::UserCtx;
+use crate::web::filter_auth::{do_auth, with_auth};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::Arc;
+use warp::reply::Json;
+use warp::Filter;
+
+#[derive(Serialize, Deserialize)]
+struct GoalsQuery {
+	at_risk: Option<bool>,
+}
+
+pub fn goals_rest_filters(
+	base_path: &'static str,
+	db: &Arc<Db>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+	let data_path = warp::path(base_path).and(warp::path("goals"));
+	let common = super::filter_utils::with_db(db.clone());
+
+	// list with streaks `GET goals?at_risk=true`
+	let list = data_path
+		.and(warp::get())
+		.and(warp::path::end())
+		.and(common.clone())
+		.and(with_auth(db.clone()))
+		.and(warp::query::<GoalsQuery>())
+		.and_then(goals_list);
+
+	// one goal with all its periods `GET goals/3`
+	let get = data_path
+		.and(warp::get())
+		.and(common.clone())
+		.and(with_auth(db.clone()))
+		.and(warp::path::param())
+		.and(warp::path::end())
+		.and_then(goal_get);
+
+	// create `POST goals` with `{"key": "gym", "target": 3, "period": "week"}`
+	let create = data_path
+		.and(warp::post())
+		.and(warp::path::end())
+		.and(common.clone())
+		.and(do_auth(db.clone()))
+		.and(warp::body::json())
+		.and_then(goal_create);
+
+	// update `PUT goals/3` with the fields to change
+	let update = data_path
+		.and(warp::put())
+		.and(common.clone())
+		.and(do_auth(db.clone()))
+		.and(warp::path::param())
+		.and(warp::path::end())
+		.and(warp::body::json())
+		.and_then(goal_update);
+
+	// delete `DELETE goals/3`
+	let delete = data_path
+		.and(warp::delete())
+		.and(common.clone())
+		.and(do_auth(db.clone()))
+		.and(warp::path::param())
+		.and(warp::path::end())
+		.and_then(goal_delete);
+
+	list.or(get).or(create).or(update).or(delete)
+}
+
+async fn goals_list(db: Arc<Db>, utx: Option<UserCtx>, query: GoalsQuery) -> Result<Json, warp::Rejection> {
+	let goals = Goals::list(&db, utx.as_ref(), query.at_risk.unwrap_or_default()).await?;
+	Ok(warp::reply::json(&json!(goals)))
+}
+
+async fn goal_get(db: Arc<Db>, utx: Option<UserCtx>, id: i32) -> Result<Json, warp::Rejection> {
+	let goal = Goals::get(&db, utx.as_ref(), id).await?;
+	Ok(warp::reply::json(&json!(goal)))
+}
+
+async fn goal_create(db: Arc<Db>, utx: UserCtx, data: GoalPatch) -> Result<Json, warp::Rejection> {
+	let goal = Goals::create(&db, &utx, data).await?;
+	Ok(warp::reply::json(&json!(goal)))
+}
+
+async fn goal_update(db: Arc<Db>, utx: UserCtx, id: i32, data: GoalPatch) -> Result<Json, warp::Rejection> {
+	let goal = Goals::update(&db, &utx, id, data).await?;
+	Ok(warp::reply::json(&json!(goal)))
+}
+
+async fn goal_delete(db: Arc<Db>, utx: UserCtx, id: i32) -> Result<Json, warp::Rejection> {
+	let goal = Goals::delete(&db, &utx, id).await?;
+	Ok(warp::reply::json(&json!(goal)))
+}
@@ -0,0 +1,54 @@
+use crate::model::{Db, Lifesheet, LifesheetImport};
+use crate::security::UserCtx;
+use crate::web::filter_auth::{do_auth, with_auth};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::Arc;
+use warp::reply::Json;
+use warp::Filter;
+
+#[derive(Serialize, Deserialize)]
+struct LifesheetImportQuery {
+	dry_run: Option<bool>,
+}
+
+pub fn lifesheet_rest_filters(
+	base_path: &'static str,
+	db: &Arc<Db>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+	let data_path = warp::path(base_path).and(warp::path("lifesheet"));
+	let common = super::filter_utils::with_db(db.clone());
+
+	// export `GET lifesheet`, usable as the telegram bot's LIFESHEET_JSON_URL
+	let export = data_path
+		.and(warp::get())
+		.and(warp::path::end())
+		.and(common.clone())
+		.and(with_auth(db.clone()))
+		.and_then(lifesheet_export);
+
+	// import `POST lifesheet?dry_run=true` with `{"lifesheet": {...}, "renames": {"old": "new"}}`
+	let import = data_path
+		.and(warp::post())
+		.and(warp::path::end())
+		.and(common.clone())
+		.and(do_auth(db.clone()))
+		.and(warp::query::<LifesheetImportQuery>())
+		.and(warp::body::json())
+		.and_then(lifesheet_import);
+
+	export.or(import)
+}
+
+async fn lifesheet_export(db: Arc<Db>, utx: Option<UserCtx>) -> Result<Json, warp::Rejection> {
+	let lifesheet = Lifesheet::export(&db, utx.as_ref()).await?;
+	Ok(warp::reply::json(&json!(lifesheet)))
+}
+
+async fn lifesheet_import(db: Arc<Db>, _utx: UserCtx, query: LifesheetImportQuery, import: LifesheetImport) -> Result<Json, warp::Rejection> {
+	let diff = match query.dry_run.unwrap_or(false) {
+		true => Lifesheet::diff(&db, import).await?,
+		false => Lifesheet::apply(&db, import).await?,
+	};
+	Ok(warp::reply::json(&json!(diff)))
+}
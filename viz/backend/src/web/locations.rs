@@ -0,0 +1,158 @@
+use crate::model::{Db, LocationCompareQuery, LocationDaysQuery, LocationGroupBy, Locations, PlacePatch, Places};
+use crate::security::UserCtx;
+use crate::web::filter_auth::do_auth;
+use crate::web::raw_data::parse_bound;
+use crate::web::Error;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::Arc;
+use warp::reply::Json;
+use warp::Filter;
+
+#[derive(Serialize, Deserialize)]
+struct LocationDaysParams {
+	carry_days: Option<i64>,
+	from: Option<String>,
+	to: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LocationCompareParams {
+	key: String,
+	by: Option<String>,
+	confidence: Option<f64>,
+	carry_days: Option<i64>,
+	from: Option<String>,
+	to: Option<String>,
+}
+
+pub fn locations_rest_filters(
+	base_path: &'static str,
+	db: &Arc<Db>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+	let places_path = warp::path(base_path).and(warp::path("places"));
+	let locations_path = warp::path(base_path).and(warp::path("locations"));
+	let common = super::filter_utils::with_db(db.clone());
+
+	// LIST places `GET places`
+	let list = places_path
+		.and(warp::get())
+		.and(warp::path::end())
+		.and(common.clone())
+		.and(do_auth(db.clone()))
+		.and_then(places_list);
+
+	// create `POST places` with `{"city": "Berlin", "country": "Germany", "lat": 52.52, "lng": 13.40}`
+	let create = places_path
+		.and(warp::post())
+		.and(warp::path::end())
+		.and(common.clone())
+		.and(do_auth(db.clone()))
+		.and(warp::body::json())
+		.and_then(place_create);
+
+	// update `PUT places/3` with the fields to change
+	let update = places_path
+		.and(warp::put())
+		.and(common.clone())
+		.and(do_auth(db.clone()))
+		.and(warp::path::param())
+		.and(warp::path::end())
+		.and(warp::body::json())
+		.and_then(place_update);
+
+	// delete `DELETE places/3`
+	let delete = places_path
+		.and(warp::delete())
+		.and(common.clone())
+		.and(do_auth(db.clone()))
+		.and(warp::path::param())
+		.and(warp::path::end())
+		.and_then(place_delete);
+
+	// Exact coordinates and places trace your movements, so locations are only
+	// served to authenticated callers.
+	// per-day location `GET locations?from=2024-01-01&carry_days=7`
+	let days = locations_path
+		.and(warp::get())
+		.and(warp::path::end())
+		.and(common.clone())
+		.and(do_auth(db.clone()))
+		.and(warp::query::<LocationDaysParams>())
+		.and_then(locations_days);
+
+	// `GET locations/compare?key=moodScore&by=city&confidence=0.95`
+	let compare = locations_path
+		.and(warp::get())
+		.and(warp::path("compare"))
+		.and(warp::path::end())
+		.and(common.clone())
+		.and(do_auth(db.clone()))
+		.and(warp::query::<LocationCompareParams>())
+		.and_then(locations_compare);
+
+	list.or(create).or(update).or(delete).or(days).or(compare)
+}
+
+async fn places_list(db: Arc<Db>, _utx: UserCtx) -> Result<Json, warp::Rejection> {
+	let places = Places::list(&db).await?;
+	Ok(warp::reply::json(&json!(places)))
+}
+
+async fn place_create(db: Arc<Db>, _utx: UserCtx, data: PlacePatch) -> Result<Json, warp::Rejection> {
+	let place = Places::create(&db, data).await?;
+	Ok(warp::reply::json(&json!(place)))
+}
+
+async fn place_update(db: Arc<Db>, _utx: UserCtx, id: i32, data: PlacePatch) -> Result<Json, warp::Rejection> {
+	let place = Places::update(&db, id, data).await?;
+	Ok(warp::reply::json(&json!(place)))
+}
+
+async fn place_delete(db: Arc<Db>, _utx: UserCtx, id: i32) -> Result<Json, warp::Rejection> {
+	let place = Places::delete(&db, id).await?;
+	Ok(warp::reply::json(&json!(place)))
+}
+
+async fn locations_days(db: Arc<Db>, utx: UserCtx, params: LocationDaysParams) -> Result<Json, warp::Rejection> {
+	let query = parse_days_query(params.carry_days, params.from, params.to)?;
+	let days = Locations::days(&db, &utx, &query).await?;
+	Ok(warp::reply::json(&json!({ "data": days })))
+}
+
+async fn locations_compare(db: Arc<Db>, utx: UserCtx, params: LocationCompareParams) -> Result<Json, warp::Rejection> {
+	let by = match params.by {
+		Some(by) => LocationGroupBy::parse(&by).ok_or_else(|| Error::InvalidQuery(format!("unknown by '{}', use city or country", by)))?,
+		None => LocationGroupBy::City,
+	};
+	let confidence = params.confidence.unwrap_or(0.95);
+	if !(confidence > 0.0 && confidence < 1.0) {
+		return Err(Error::InvalidQuery(format!("confidence {} must be between 0 and 1", confidence)).into());
+	}
+	let query = LocationCompareQuery {
+		key: params.key,
+		by,
+		confidence,
+		days: parse_days_query(params.carry_days, params.from, params.to)?,
+	};
+	let page = Locations::compare(&db, &utx, query).await?;
+	Ok(warp::reply::json(&json!(page)))
+}
+
+fn parse_days_query(carry_days: Option<i64>, from: Option<String>, to: Option<String>) -> Result<LocationDaysQuery, Error> {
+	let carry_days = carry_days.unwrap_or(7);
+	if carry_days < 0 {
+		return Err(Error::InvalidQuery(format!("carry_days {} can't be negative", carry_days)));
+	}
+	Ok(LocationDaysQuery {
+		carry_days,
+		from: parse_bound("from", from)?,
+		to: parse_bound("to", to)?,
+	})
+}
+
+// region:    Test
+#[cfg(test)]
+#[path = "../_tests/web_locations.rs"]
+mod tests;
+// endregion: Test
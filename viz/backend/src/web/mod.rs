@@ -1,4 +1,10 @@
 use crate::model::{self, Db};
+use crate::security;
+use crate::web::correlate::correlate_rest_filters;
+use crate::web::export::export_rest_filters;
+use crate::web::goals::goals_rest_filters;
+use crate::web::lifesheet::lifesheet_rest_filters;
+use crate::web::locations::locations_rest_filters;
 use crate::web::raw_data::raw_data_rest_filters;
 use crate::web::viz_metadata::viz_metadata_rest_filters;
 use crate::web::viz_questions::viz_questions_rest_filters;
@@ -8,7 +14,13 @@ use std::convert::Infallible;
 use std::sync::Arc;
 use warp::{Filter, Rejection, Reply};
 
+mod correlate;
+mod export;
+mod filter_auth;
 mod filter_utils;
+mod goals;
+mod lifesheet;
+mod locations;
 mod raw_data;
 mod viz_metadata;
 mod viz_questions;
@@ -20,6 +32,11 @@ pub async fn start_web(web_port: u16, db: Arc<Db>) -> Result<(), Error> {
 	let metadata_apis = viz_metadata_rest_filters("api", &db);
 	let questions_apis = viz_questions_rest_filters("api", &db);
 	let categories_apis = viz_categories_rest_filters("api", &db);
+	let lifesheet_apis = lifesheet_rest_filters("api", &db);
+	let correlate_apis = correlate_rest_filters("api", &db);
+	let goals_apis = goals_rest_filters("api", &db);
+	let locations_apis = locations_rest_filters("api", &db);
+	let export_apis = export_rest_filters("api", &db);
 
 	// Static content
 	let static_s = warp::fs::dir("../frontend/build/");
@@ -27,11 +44,13 @@ pub async fn start_web(web_port: u16, db: Arc<Db>) -> Result<(), Error> {
 
 	let cors = warp::cors()
 		.allow_origin("https://metrics.soumyadeep.in")
-		.allow_methods(vec!["GET"]);
+		.allow_methods(vec!["GET", "POST", "PUT", "DELETE"])
+		.allow_headers(vec!["Content-Type", "X-Auth-Token"]);
 	let log = warp::log("access");
 
 	// Combine all routes
-	let routes = raw_data_apis.or(metadata_apis).or(questions_apis).or(categories_apis)
+	let routes = raw_data_apis.or(metadata_apis).or(questions_apis).or(categories_apis).or(lifesheet_apis)
+		.or(correlate_apis).or(goals_apis).or(locations_apis).or(export_apis)
 		.or(static_s).recover(handle_rejection).with(cors).with(log);
 
 	println!("Start 0.0.0.0:{}", web_port);
@@ -51,17 +70,24 @@ async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible> {
 		Some(err) => err.typ.to_string(),
 		None => "Unknown".to_string(),
 	};
+	let status = match user_message.as_str() {
+		"security::Error" => warp::http::StatusCode::UNAUTHORIZED,
+		_ => warp::http::StatusCode::BAD_REQUEST,
+	};
 
 	let result = json!({ "errorMessage": user_message });
 	let result = warp::reply::json(&result);
 
-	Ok(warp::reply::with_status(result, warp::http::StatusCode::BAD_REQUEST))
+	Ok(warp::reply::with_status(result, status))
 }
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
 	#[error("Web server failed to start because web-folder '{0}' not found.")]
 	FailStartWebFolderNotFound(String),
+
+	#[error("Invalid query - {0}")]
+	InvalidQuery(String),
 }
 
 // region:    Warp Custom Error
@@ -83,6 +109,11 @@ impl From<self::Error> for warp::Rejection {
 		WebErrorMessage::rejection("web::Error", format!("{}", other))
 	}
 }
+impl From<security::Error> for warp::Rejection {
+	fn from(other: security::Error) -> Self {
+		WebErrorMessage::rejection("security::Error", format!("{}", other))
+	}
+}
 impl From<model::Error> for warp::Rejection {
 	fn from(other: model::Error) -> Self {
 		WebErrorMessage::rejection("model::Error", format!("{}", other))
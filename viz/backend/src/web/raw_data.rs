@@ -1,39 +1,262 @@
-use crate::model::{Db, RawData};
-use serde::Serialize;
+use crate::model::{AggregateFn, Anomalies, AnomalyMethod, AnomalyQuery, ChangePointQuery, AggregateQuery, Bucket, Db, RawData, RawDataCursor, RawDataPatch, RawDataQuery, TimeBound};
+use crate::security::UserCtx;
+use crate::web::filter_auth::{do_auth, with_auth};
+use crate::web::Error;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::sync::Arc;
 use warp::reply::Json;
 use warp::Filter;
 
+#[derive(Serialize, Deserialize)]
+struct RawDataQueryParams {
+	from: Option<String>,
+	to: Option<String>,
+	limit: Option<i64>,
+	cursor: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AggregateQueryParams {
+	bucket: Option<String>,
+	#[serde(rename = "fn")]
+	func: Option<String>,
+	from: Option<String>,
+	to: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AnomalyQueryParams {
+	method: Option<String>,
+	threshold: Option<f64>,
+	window: Option<usize>,
+	from: Option<String>,
+	to: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ChangePointQueryParams {
+	threshold: Option<f64>,
+	min_size: Option<usize>,
+	from: Option<String>,
+	to: Option<String>,
+}
+
 pub fn raw_data_rest_filters(
 	base_path: &'static str,
 	db: &Arc<Db>,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
 	let data_path = warp::path(base_path).and(warp::path("data"));
-	// let common = super::filter_utils::with_db(db.clone()).and(do_auth(db.clone()));
 	let common = super::filter_utils::with_db(db.clone());
 
-	data_path
+	// get with query params `GET data/foo?from=2024-01-01&to=1709251200000&limit=500&cursor=...`
+	let get_by_key = data_path
+		.and(warp::get())
+		.and(common.clone())
+		.and(with_auth(db.clone()))
+		.and(warp::path::param())
+		.and(warp::path::end())
+		.and(warp::query::<RawDataQueryParams>())
+		.and_then(data_get_by_key);
+
+	// aggregate `GET data/foo/aggregate?bucket=week&fn=avg&from=2024-01-01`
+	let aggregate = data_path
+		.and(warp::get())
+		.and(common.clone())
+		.and(with_auth(db.clone()))
+		.and(warp::path::param())
+		.and(warp::path("aggregate"))
+		.and(warp::path::end())
+		.and(warp::query::<AggregateQueryParams>())
+		.and_then(data_aggregate_by_key);
+
+	// unusual values `GET data/foo/anomalies?method=mad&window=30&threshold=3`
+	let anomalies = data_path
 		.and(warp::get())
 		.and(common.clone())
+		.and(with_auth(db.clone()))
+		.and(warp::path::param())
+		.and(warp::path("anomalies"))
+		.and(warp::path::end())
+		.and(warp::query::<AnomalyQueryParams>())
+		.and_then(data_anomalies_by_key);
+
+	// level shifts `GET data/foo/changepoints?threshold=4&min_size=7`
+	let changepoints = data_path
+		.and(warp::get())
+		.and(common.clone())
+		.and(with_auth(db.clone()))
+		.and(warp::path::param())
+		.and(warp::path("changepoints"))
+		.and(warp::path::end())
+		.and(warp::query::<ChangePointQueryParams>())
+		.and_then(data_changepoints_by_key);
+
+	// create `POST data/foo` with `{"value": 4, "timestamp": 1709251200000}`
+	let create = data_path
+		.and(warp::post())
+		.and(common.clone())
+		.and(do_auth(db.clone()))
 		.and(warp::path::param())
-		.and_then(data_get_by_key)
+		.and(warp::path::end())
+		.and(warp::body::json())
+		.and_then(data_create);
+
+	// correct `PUT data/foo/123` with any of `value`, `timestamp`, `matcheddate`
+	let update = data_path
+		.and(warp::put())
+		.and(common.clone())
+		.and(do_auth(db.clone()))
+		.and(warp::path::param())
+		.and(warp::path::param())
+		.and(warp::path::end())
+		.and(warp::body::json())
+		.and_then(data_update);
+
+	// delete `DELETE data/foo/123`
+	let delete = data_path
+		.and(warp::delete())
+		.and(common.clone())
+		.and(do_auth(db.clone()))
+		.and(warp::path::param())
+		.and(warp::path::param())
+		.and(warp::path::end())
+		.and_then(data_delete);
+
+	get_by_key.or(aggregate).or(anomalies).or(changepoints).or(create).or(update).or(delete)
+}
+
+async fn data_get_by_key(db: Arc<Db>, utx: Option<UserCtx>, key: String, params: RawDataQueryParams) -> Result<Json, warp::Rejection> {
+	let query = parse_query(params)?;
+	let page = RawData::get_by_key(&db, utx.as_ref(), key, query).await?;
+	let response = json!({ "data": page.data, "invalid": page.invalid, "next_cursor": page.next_cursor });
+	Ok(warp::reply::json(&response))
 }
 
-async fn data_get_by_key(db: Arc<Db>, key: String) -> Result<Json, warp::Rejection> {
-	let data = RawData::get_by_key(&db,  key).await?;
-	json_response(data)
+async fn data_create(db: Arc<Db>, _utx: UserCtx, key: String, data: RawDataPatch) -> Result<Json, warp::Rejection> {
+	let entry = RawData::create(&db, key, data).await?;
+	Ok(warp::reply::json(&json!({ "data": entry })))
+}
+
+async fn data_update(db: Arc<Db>, _utx: UserCtx, key: String, id: i32, data: RawDataPatch) -> Result<Json, warp::Rejection> {
+	let entry = RawData::update(&db, key, id, data).await?;
+	Ok(warp::reply::json(&json!({ "data": entry })))
+}
+
+async fn data_delete(db: Arc<Db>, _utx: UserCtx, key: String, id: i32) -> Result<Json, warp::Rejection> {
+	let entry = RawData::delete(&db, key, id).await?;
+	Ok(warp::reply::json(&json!({ "data": entry })))
+}
+
+async fn data_aggregate_by_key(db: Arc<Db>, utx: Option<UserCtx>, key: String, params: AggregateQueryParams) -> Result<Json, warp::Rejection> {
+	let query = parse_aggregate_query(params)?;
+	let func = query.func;
+	let page = RawData::aggregate_by_key(&db, utx.as_ref(), key.clone(), query).await?;
+	let response = json!({
+		"key": key,
+		"bucket": page.bucket.as_str(),
+		"fn": func.as_str(),
+		"data": page.data,
+	});
+	Ok(warp::reply::json(&response))
 }
 
-// region:    Utils
-fn json_response<D: Serialize>(data: D) -> Result<Json, warp::Rejection> {
-	let response = json!({ "data": data });
+async fn data_anomalies_by_key(db: Arc<Db>, utx: Option<UserCtx>, key: String, params: AnomalyQueryParams) -> Result<Json, warp::Rejection> {
+	let query = parse_anomaly_query(params)?;
+	let (method, threshold) = (query.method, query.threshold);
+	let data = Anomalies::detect(&db, utx.as_ref(), key.clone(), query).await?;
+	let response = json!({
+		"key": key,
+		"method": method.as_str(),
+		"threshold": threshold,
+		"data": data,
+	});
 	Ok(warp::reply::json(&response))
 }
-// endregion: Utils
+
+async fn data_changepoints_by_key(db: Arc<Db>, utx: Option<UserCtx>, key: String, params: ChangePointQueryParams) -> Result<Json, warp::Rejection> {
+	let query = ChangePointQuery {
+		threshold: parse_threshold(params.threshold, 4.0)?,
+		min_size: params.min_size.unwrap_or(7).max(1),
+		from: parse_bound("from", params.from)?,
+		to: parse_bound("to", params.to)?,
+	};
+	let page = Anomalies::changepoints(&db, utx.as_ref(), key.clone(), query).await?;
+	let response = json!({ "key": key, "data": page.data, "segments": page.segments });
+	Ok(warp::reply::json(&response))
+}
+
+fn parse_query(params: RawDataQueryParams) -> Result<RawDataQuery, Error> {
+	let after = match params.cursor {
+		Some(cursor) => Some(RawDataCursor::decode(&cursor).ok_or_else(|| Error::InvalidQuery(format!("invalid cursor '{}'", cursor)))?),
+		None => None,
+	};
+	if let Some(limit) = params.limit {
+		if limit < 1 {
+			return Err(Error::InvalidQuery(format!("limit {} must be positive", limit)));
+		}
+	}
+	Ok(RawDataQuery {
+		from: parse_bound("from", params.from)?,
+		to: parse_bound("to", params.to)?,
+		limit: params.limit,
+		after,
+	})
+}
+
+fn parse_aggregate_query(params: AggregateQueryParams) -> Result<AggregateQuery, Error> {
+	let bucket = match params.bucket {
+		Some(bucket) => Some(Bucket::parse(&bucket).ok_or_else(|| Error::InvalidQuery(format!("unknown bucket '{}'", bucket)))?),
+		None => None,
+	};
+	let func = match params.func {
+		Some(func) => AggregateFn::parse(&func).ok_or_else(|| Error::InvalidQuery(format!("unknown fn '{}'", func)))?,
+		None => AggregateFn::Avg,
+	};
+	Ok(AggregateQuery {
+		bucket,
+		func,
+		from: parse_bound("from", params.from)?,
+		to: parse_bound("to", params.to)?,
+	})
+}
+
+fn parse_anomaly_query(params: AnomalyQueryParams) -> Result<AnomalyQuery, Error> {
+	let method = match params.method {
+		Some(method) => AnomalyMethod::parse(&method).ok_or_else(|| Error::InvalidQuery(format!("unknown method '{}'", method)))?,
+		None => AnomalyMethod::RollingMad,
+	};
+	let window = params.window.unwrap_or(30);
+	if window < 2 {
+		return Err(Error::InvalidQuery(format!("window {} must be at least 2", window)));
+	}
+	Ok(AnomalyQuery {
+		method,
+		threshold: parse_threshold(params.threshold, 3.0)?,
+		window,
+		from: parse_bound("from", params.from)?,
+		to: parse_bound("to", params.to)?,
+	})
+}
+
+fn parse_threshold(value: Option<f64>, default: f64) -> Result<f64, Error> {
+	match value {
+		Some(threshold) if !(threshold.is_finite() && threshold > 0.0) => Err(Error::InvalidQuery(format!("threshold {} must be positive", threshold))),
+		threshold => Ok(threshold.unwrap_or(default)),
+	}
+}
+
+pub(super) fn parse_bound(name: &str, value: Option<String>) -> Result<Option<TimeBound>, Error> {
+	match value {
+		Some(value) => TimeBound::parse(&value)
+			.map(Some)
+			.ok_or_else(|| Error::InvalidQuery(format!("{} '{}' is neither a timestamp nor a YYYY-MM-DD date", name, value))),
+		None => Ok(None),
+	}
+}
 
 // region:    Test
 #[cfg(test)]
-#[path = "../_tests/web_todo.rs"]
+#[path = "../_tests/web_raw_data.rs"]
 mod tests;
 // endregion: Test
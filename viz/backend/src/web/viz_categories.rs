@@ -1,26 +1,99 @@
-use crate::model::{Db, VizCategories};
+use crate::model::{Db, VizCategories, VizCategoriesPatch, VizQuestions};
+use crate::security::UserCtx;
+use crate::web::filter_auth::{do_auth, with_auth};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::sync::Arc;
 use warp::reply::Json;
 use warp::Filter;
 
+#[derive(Serialize, Deserialize)]
+struct VizCategoriesDeleteQuery {
+    move_to: Option<String>,
+}
+
 pub fn viz_categories_rest_filters(
     base_path: &'static str,
     db: &Arc<Db>,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     let data_path = warp::path(base_path).and(warp::path("categories"));
-    let common = super::filter_utils::with_db(db.clone());
+    let common = super::filter_utils::with_db(db.clone()).and(with_auth(db.clone()));
+
+    let with_db = super::filter_utils::with_db(db.clone());
 
-    data_path
+    let list = data_path
         .and(warp::get())
         .and(warp::path::end())
         .and(common.clone())
-        .and_then(get_all_categories)
+        .and_then(get_all_categories);
+
+    // create `POST categories/` with a name and optionally priority, description, is_private
+    let create = data_path
+        .and(warp::post())
+        .and(warp::path::end())
+        .and(with_db.clone())
+        .and(do_auth(db.clone()))
+        .and(warp::body::json())
+        .and_then(category_create);
+
+    // rename or reprioritise `PUT categories/3` with the fields to change
+    let update = data_path
+        .and(warp::put())
+        .and(with_db.clone())
+        .and(do_auth(db.clone()))
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .and(warp::body::json())
+        .and_then(category_update);
+
+    // delete `DELETE categories/3?move_to=Other`
+    let delete = data_path
+        .and(warp::delete())
+        .and(with_db.clone())
+        .and(do_auth(db.clone()))
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .and(warp::query::<VizCategoriesDeleteQuery>())
+        .and_then(category_delete);
+
+    // reorder its questions `PUT categories/3/questions` with `["sleep", "moodScore"]`
+    let reorder = data_path
+        .and(warp::put())
+        .and(with_db.clone())
+        .and(do_auth(db.clone()))
+        .and(warp::path::param())
+        .and(warp::path("questions"))
+        .and(warp::path::end())
+        .and(warp::body::json())
+        .and_then(category_reorder_questions);
+
+    list.or(create).or(update).or(delete).or(reorder)
 }
 
-async fn get_all_categories(db: Arc<Db>) -> Result<Json, warp::Rejection> {
+async fn get_all_categories(db: Arc<Db>, utx: Option<UserCtx>) -> Result<Json, warp::Rejection> {
     println!("get_all_categories");
-    let categories = VizCategories::get_all_categories(&db).await?;
+    let categories = VizCategories::get_all_categories(&db, utx.as_ref()).await?;
     let response = json!(categories);
     Ok(warp::reply::json(&response))
 }
+
+async fn category_create(db: Arc<Db>, _utx: UserCtx, data: VizCategoriesPatch) -> Result<Json, warp::Rejection> {
+    let category = VizCategories::create(&db, data).await?;
+    Ok(warp::reply::json(&json!(category)))
+}
+
+async fn category_update(db: Arc<Db>, _utx: UserCtx, id: i32, data: VizCategoriesPatch) -> Result<Json, warp::Rejection> {
+    let category = VizCategories::update(&db, id, data).await?;
+    Ok(warp::reply::json(&json!(category)))
+}
+
+async fn category_delete(db: Arc<Db>, _utx: UserCtx, id: i32, query: VizCategoriesDeleteQuery) -> Result<Json, warp::Rejection> {
+    let category = VizCategories::delete(&db, id, query.move_to).await?;
+    Ok(warp::reply::json(&json!(category)))
+}
+
+async fn category_reorder_questions(db: Arc<Db>, _utx: UserCtx, id: i32, keys: Vec<String>) -> Result<Json, warp::Rejection> {
+    let category = VizCategories::get(&db, id).await?;
+    let questions = VizQuestions::reorder(&db, &category.name, &keys).await?;
+    Ok(warp::reply::json(&json!(questions)))
+}
@@ -1,21 +1,35 @@
 use crate::model::{Db, VizMetadata};
-use serde_json::json;
+use crate::security::UserCtx;
+use crate::web::filter_auth::do_auth;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use std::sync::Arc;
 use warp::reply::Json;
 use warp::Filter;
 
+#[derive(Serialize, Deserialize)]
+struct VizMetadataBody {
+    value: Value,
+}
+
+#[derive(Serialize, Deserialize)]
+struct VizMetadataRevertQuery {
+    id: Option<i32>,
+}
+
 pub fn viz_metadata_rest_filters(
     base_path: &'static str,
     db: &Arc<Db>,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     let data_path = warp::path(base_path).and(warp::path("metadata"));
-    // let common = super::filter_utils::with_db(db.clone()).and(do_auth(db.clone()));
     let common = super::filter_utils::with_db(db.clone());
+    let common_auth = common.clone().and(do_auth(db.clone()));
 
     let get = data_path
         .and(warp::get())
         .and(common.clone())
         .and(warp::path::param())
+        .and(warp::path::end())
         .and_then(metadata_get_by_key);
 
     // LIST viz_metadata `GET metadata/`
@@ -25,12 +39,48 @@ pub fn viz_metadata_rest_filters(
         .and(common.clone())
         .and_then(metadata_list);
 
-    get.or(list)
+    // set `PUT metadata/theme` with `{"value": "dark"}`, or any JSON value
+    let upsert = data_path
+        .and(warp::put())
+        .and(common_auth.clone())
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .and(warp::body::json())
+        .and_then(metadata_upsert);
+
+    // delete `DELETE metadata/theme`
+    let delete = data_path
+        .and(warp::delete())
+        .and(common_auth.clone())
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .and_then(metadata_delete);
+
+    // earlier values `GET metadata/theme/history`
+    let history = data_path
+        .and(warp::get())
+        .and(common.clone())
+        .and(warp::path::param())
+        .and(warp::path("history"))
+        .and(warp::path::end())
+        .and_then(metadata_history);
+
+    // revert `POST metadata/theme/revert?id=3`, to the latest history entry without an id
+    let revert = data_path
+        .and(warp::post())
+        .and(common_auth.clone())
+        .and(warp::path::param())
+        .and(warp::path("revert"))
+        .and(warp::path::end())
+        .and(warp::query::<VizMetadataRevertQuery>())
+        .and_then(metadata_revert);
+
+    get.or(list).or(upsert).or(delete).or(history).or(revert)
 }
 
 async fn metadata_get_by_key(db: Arc<Db>, key: String) -> Result<Json, warp::Rejection> {
     let data = VizMetadata::get_by_key(&db,  key).await?;
-    let response = json!({ data.key: data.value });
+    let response = json!({ data.key.clone(): data.json_value() });
     Ok(warp::reply::json(&response))
 }
 
@@ -39,9 +89,37 @@ async fn metadata_list(db: Arc<Db>) -> Result<Json, warp::Rejection> {
     // convert metadata_list to a map
     let mut metadata_map = std::collections::HashMap::new();
     for metadata in metadata_list {
-        metadata_map.insert(metadata.key, metadata.value);
+        let value = metadata.json_value();
+        metadata_map.insert(metadata.key, value);
     }
     // convert metadata_map to json without extra key
     let response = json!(metadata_map);
     Ok(warp::reply::json(&response))
 }
+
+async fn metadata_upsert(db: Arc<Db>, _utx: UserCtx, key: String, body: VizMetadataBody) -> Result<Json, warp::Rejection> {
+    let data = VizMetadata::upsert(&db, &key, &body.value).await?;
+    let response = json!({ data.key.clone(): data.json_value() });
+    Ok(warp::reply::json(&response))
+}
+
+async fn metadata_delete(db: Arc<Db>, _utx: UserCtx, key: String) -> Result<Json, warp::Rejection> {
+    let data = VizMetadata::delete(&db, &key).await?;
+    let response = json!({ data.key.clone(): data.json_value() });
+    Ok(warp::reply::json(&response))
+}
+
+async fn metadata_history(db: Arc<Db>, key: String) -> Result<Json, warp::Rejection> {
+    let history = VizMetadata::history(&db, &key).await?;
+    let response: Vec<Value> = history
+        .iter()
+        .map(|entry| json!({ "id": entry.id, "value": entry.json_value(), "replaced_at": entry.replaced_at }))
+        .collect();
+    Ok(warp::reply::json(&json!(response)))
+}
+
+async fn metadata_revert(db: Arc<Db>, _utx: UserCtx, key: String, query: VizMetadataRevertQuery) -> Result<Json, warp::Rejection> {
+    let data = VizMetadata::revert(&db, &key, query.id).await?;
+    let value = data.map_or(Value::Null, |data| data.json_value());
+    Ok(warp::reply::json(&json!({ key: value })))
+}
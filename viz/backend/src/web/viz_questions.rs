@@ -1,4 +1,6 @@
-use crate::model::{Db, VizQuestions};
+use crate::model::{Db, VizQuestions, VizQuestionsPatch};
+use crate::security::UserCtx;
+use crate::web::filter_auth::{do_auth, with_auth};
 use serde::{Serialize, Deserialize};
 use serde_json::json;
 use std::sync::Arc;
@@ -16,19 +18,50 @@ pub fn viz_questions_rest_filters(
     db: &Arc<Db>,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     let data_path = warp::path(base_path).and(warp::path("questions"));
-    // let common = super::filter_utils::with_db(db.clone()).and(do_auth(db.clone()));
-    let common = super::filter_utils::with_db(db.clone());
+    let common = super::filter_utils::with_db(db.clone()).and(with_auth(db.clone()));
+
+    let with_db = super::filter_utils::with_db(db.clone());
 
     // get with query params `GET questions/?category=foo&is_visible=true`
-    data_path
+    let list = data_path
         .and(warp::get())
         .and(warp::path::end())
         .and(common.clone())
         .and(warp::query::<VizQuestionsQuery>())
-        .and_then(questions_with_query)
+        .and_then(questions_with_query);
+
+    // create `POST questions/` with a question as written in lifesheet.json
+    let create = data_path
+        .and(warp::post())
+        .and(warp::path::end())
+        .and(with_db.clone())
+        .and(do_auth(db.clone()))
+        .and(warp::body::json())
+        .and_then(question_create);
+
+    // update `PUT questions/foo` with the fields to change
+    let update = data_path
+        .and(warp::put())
+        .and(with_db.clone())
+        .and(do_auth(db.clone()))
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .and(warp::body::json())
+        .and_then(question_update);
+
+    // archive `DELETE questions/foo`
+    let archive = data_path
+        .and(warp::delete())
+        .and(with_db.clone())
+        .and(do_auth(db.clone()))
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .and_then(question_archive);
+
+    list.or(create).or(update).or(archive)
 }
 
-async fn questions_with_query(db: Arc<Db>, query: VizQuestionsQuery) -> Result<Json, warp::Rejection> {
+async fn questions_with_query(db: Arc<Db>, utx: Option<UserCtx>, query: VizQuestionsQuery) -> Result<Json, warp::Rejection> {
     let is_visible = query.is_visible;
     let category = query.category;
 
@@ -36,7 +69,22 @@ async fn questions_with_query(db: Arc<Db>, query: VizQuestionsQuery) -> Result<J
 
     let unwrapped_category = category.unwrap_or_default();
 
-    let questions = VizQuestions::get_questions_with_query(&db, unwrapped_category, unwrapped_visibility).await?;
+    let questions = VizQuestions::get_questions_with_query(&db, utx.as_ref(), unwrapped_category, unwrapped_visibility).await?;
     let response = json!(questions);
     Ok(warp::reply::json(&response))
 }
+
+async fn question_create(db: Arc<Db>, _utx: UserCtx, data: VizQuestionsPatch) -> Result<Json, warp::Rejection> {
+    let question = VizQuestions::create(&db, data).await?;
+    Ok(warp::reply::json(&json!(question)))
+}
+
+async fn question_update(db: Arc<Db>, _utx: UserCtx, key: String, data: VizQuestionsPatch) -> Result<Json, warp::Rejection> {
+    let question = VizQuestions::update(&db, &key, data).await?;
+    Ok(warp::reply::json(&json!(question)))
+}
+
+async fn question_archive(db: Arc<Db>, _utx: UserCtx, key: String) -> Result<Json, warp::Rejection> {
+    let question = VizQuestions::archive(&db, &key).await?;
+    Ok(warp::reply::json(&json!(question)))
+}